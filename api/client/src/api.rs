@@ -0,0 +1,235 @@
+//! Client of the routes of the API, see `api/README.md`.
+
+use reqwest::{header::AUTHORIZATION, Method, RequestBuilder, Url};
+use serde::{de::DeserializeOwned, Deserialize, Serialize};
+use serde_json::{json, Value};
+use sha2::{Digest, Sha256};
+
+use crate::error::{ClientError, ClientResult};
+
+#[derive(Clone, Debug, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct CreatedSession {
+    pub session_name: String,
+    pub session_id: String,
+    pub access_code: String,
+    pub jwt: String,
+}
+
+#[derive(Clone, Debug, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct HostedSession {
+    pub session_name: String,
+    pub session_id: String,
+    pub access_code: String,
+}
+
+#[derive(Clone, Debug, Deserialize)]
+pub struct FileInfo {
+    pub name: String,
+    pub size: u64,
+    pub is_owner: bool,
+}
+
+#[derive(Clone, Debug, Serialize)]
+pub struct NewFile {
+    pub name: String,
+    pub size: u64,
+}
+
+#[derive(Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct SessionIdResponse {
+    session_id: String,
+}
+
+#[derive(Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct SessionNameResponse {
+    session_name: String,
+}
+
+#[derive(Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct AccessCodeResponse {
+    access_code: String,
+}
+
+#[derive(Deserialize)]
+struct JwtResponse {
+    jwt: String,
+}
+
+#[derive(Clone)]
+pub struct ApiClient {
+    http: reqwest::Client,
+    base_url: Url,
+}
+
+impl ApiClient {
+    /// `base_url` is the root of the API, e.g. `http://localhost:7878`.
+    pub fn new(base_url: &str) -> ClientResult<Self> {
+        let base_url = Url::parse(base_url)
+            .map_err(|e| ClientError::Protocol(format!("invalid API url: {}", e)))?;
+
+        Ok(ApiClient {
+            http: reqwest::Client::new(),
+            base_url,
+        })
+    }
+
+    /// Timestamp of the API.
+    pub async fn ping(&self) -> ClientResult<u128> {
+        self.send(self.request(Method::GET, &[])).await
+    }
+
+    /// Session hosted by the user of the JWT, with a new access code.
+    pub async fn get_session(&self, jwt: &str) -> ClientResult<HostedSession> {
+        self.send(self.authorized(Method::GET, &["session"], jwt))
+            .await
+    }
+
+    pub async fn create_session(&self) -> ClientResult<CreatedSession> {
+        self.send(self.request(Method::POST, &["session"])).await
+    }
+
+    pub async fn id_for_name(&self, session_name: &str) -> ClientResult<String> {
+        let response: SessionIdResponse = self
+            .send(self.request(Method::GET, &["idForName", session_name]))
+            .await?;
+
+        Ok(response.session_id)
+    }
+
+    /// Name of the session.
+    pub async fn session_metadata(&self, session_id: &str) -> ClientResult<String> {
+        let response: SessionNameResponse = self
+            .send(self.request(Method::GET, &["session", session_id]))
+            .await?;
+
+        Ok(response.session_name)
+    }
+
+    /// Joins a session with its six digit access code, returns the JWT of the new user.
+    pub async fn join_session(&self, session_id: &str, access_code: &str) -> ClientResult<String> {
+        let encrypted_code = hex::encode(Sha256::digest(access_code.as_bytes()));
+        let request = self
+            .request(Method::GET, &["access", session_id])
+            .header(AUTHORIZATION, encrypted_code);
+        let response: JwtResponse = self.send(request).await?;
+
+        Ok(response.jwt)
+    }
+
+    /// Renames the session, returns the new access code.
+    pub async fn update_session(
+        &self,
+        jwt: &str,
+        session_id: &str,
+        name: &str,
+    ) -> ClientResult<String> {
+        let request = self
+            .authorized(Method::PUT, &["session", session_id], jwt)
+            .json(&json!({ "name": name }));
+        let response: AccessCodeResponse = self.send(request).await?;
+
+        Ok(response.access_code)
+    }
+
+    /// Keeps the session alive, returns a new JWT for the same user.
+    pub async fn refresh_session(&self, jwt: &str, session_id: &str) -> ClientResult<String> {
+        let response: JwtResponse = self
+            .send(self.authorized(Method::POST, &["session", session_id, "refresh"], jwt))
+            .await?;
+
+        Ok(response.jwt)
+    }
+
+    pub async fn delete_session(&self, jwt: &str, session_id: &str) -> ClientResult<()> {
+        let _: Value = self
+            .send(self.authorized(Method::DELETE, &["session", session_id], jwt))
+            .await?;
+
+        Ok(())
+    }
+
+    pub async fn list_files(&self, jwt: &str, session_id: &str) -> ClientResult<Vec<FileInfo>> {
+        self.send(self.authorized(Method::GET, &["files", session_id], jwt))
+            .await
+    }
+
+    pub async fn add_files(
+        &self,
+        jwt: &str,
+        session_id: &str,
+        files: &[NewFile],
+    ) -> ClientResult<()> {
+        let request = self
+            .authorized(Method::POST, &["files", session_id], jwt)
+            .json(files);
+        let _: Value = self.send(request).await?;
+
+        Ok(())
+    }
+
+    pub async fn file_metadata(
+        &self,
+        jwt: &str,
+        session_id: &str,
+        filename: &str,
+    ) -> ClientResult<FileInfo> {
+        self.send(self.authorized(Method::GET, &["files", session_id, filename], jwt))
+            .await
+    }
+
+    pub async fn delete_file(
+        &self,
+        jwt: &str,
+        session_id: &str,
+        filename: &str,
+    ) -> ClientResult<()> {
+        let _: Value = self
+            .send(self.authorized(Method::DELETE, &["files", session_id, filename], jwt))
+            .await?;
+
+        Ok(())
+    }
+
+    /// Request of the path made of the segments, which are percent encoded.
+    fn request(&self, method: Method, segments: &[&str]) -> RequestBuilder {
+        let mut url = self.base_url.clone();
+        if let Ok(mut path) = url.path_segments_mut() {
+            path.pop_if_empty().extend(segments);
+        }
+
+        self.http.request(method, url)
+    }
+
+    fn authorized(&self, method: Method, segments: &[&str], jwt: &str) -> RequestBuilder {
+        self.request(method, segments)
+            .header(AUTHORIZATION, format!("Bearer {}", jwt))
+    }
+
+    /// Sends the request and unwraps the `response` of the envelope.
+    async fn send<T: DeserializeOwned>(&self, request: RequestBuilder) -> ClientResult<T> {
+        let response = request.send().await?;
+        let status = response.status();
+        let body = response.text().await?;
+
+        let envelope: Value = serde_json::from_str(&body).map_err(|_| {
+            ClientError::Protocol(format!("unexpected response ({}): {}", status, body))
+        })?;
+
+        if !status.is_success() || envelope["success"] != true {
+            return Err(ClientError::Api {
+                status: status.as_u16(),
+                code: envelope["code"].as_str().unwrap_or_default().to_string(),
+                message: envelope["message"].as_str().unwrap_or_default().to_string(),
+                field: envelope["field"].as_str().map(|field| field.to_string()),
+            });
+        }
+
+        serde_json::from_value(envelope["response"].clone())
+            .map_err(|e| ClientError::Protocol(format!("unexpected response: {}", e)))
+    }
+}
@@ -0,0 +1,286 @@
+//! Key exchange and encryption, matching the Web Crypto calls of the web app (`utils.js`).
+//!
+//! - ECDH P-256 key pairs, public keys are exported raw (uncompressed point) and base64 encoded
+//! - the shared AES-GCM-256 key is the x coordinate of the shared point, as `deriveKey` uses it
+//! - every chunk is encrypted with a random 12 byte IV
+
+use aes_gcm::{
+    aead::{Aead, KeyInit, OsRng},
+    AeadCore, Aes256Gcm, Key, Nonce,
+};
+use base64::{engine::general_purpose::STANDARD as BASE64, Engine};
+use p256::{ecdh, elliptic_curve::sec1::ToEncodedPoint, PublicKey, SecretKey};
+use sha2::{Digest, Sha256};
+
+use utils::chunk_frame::IV_LEN;
+use utils::protocol::{ContentHash, WrappedKey};
+
+use crate::error::{ClientError, ClientResult};
+
+/// Length of a chunk digest and of the content hash.
+pub const DIGEST_LEN: usize = 32;
+
+pub type ChunkDigest = [u8; DIGEST_LEN];
+
+/// ECDH key pair of a single file request.
+pub struct KeyPair {
+    secret: SecretKey,
+}
+
+impl KeyPair {
+    pub fn generate() -> Self {
+        KeyPair {
+            secret: SecretKey::random(&mut OsRng),
+        }
+    }
+
+    /// Raw public key, base64 encoded.
+    pub fn public_key(&self) -> String {
+        let point = self.secret.public_key().to_encoded_point(false);
+
+        BASE64.encode(point.as_bytes())
+    }
+
+    /// Derives the key shared with the owner of `public_key`.
+    pub fn derive(&self, public_key: &str) -> ClientResult<ChunkKey> {
+        let public_key = BASE64
+            .decode(public_key)
+            .ok()
+            .and_then(|public_key| PublicKey::from_sec1_bytes(&public_key).ok())
+            .ok_or(ClientError::Crypto("invalid public key".to_string()))?;
+
+        let shared = ecdh::diffie_hellman(self.secret.to_nonzero_scalar(), public_key.as_affine());
+
+        ChunkKey::from_bytes(shared.raw_secret_bytes())
+    }
+}
+
+/// AES-GCM-256 key the chunks of a file request are encrypted with.
+#[derive(Clone)]
+pub struct ChunkKey {
+    cipher: Aes256Gcm,
+}
+
+impl ChunkKey {
+    pub fn from_bytes(key: &[u8]) -> ClientResult<Self> {
+        if key.len() != 32 {
+            return Err(ClientError::Crypto("invalid key length".to_string()));
+        }
+
+        Ok(ChunkKey {
+            cipher: Aes256Gcm::new(Key::<Aes256Gcm>::from_slice(key)),
+        })
+    }
+
+    pub fn encrypt(&self, iv: &[u8; IV_LEN], plaintext: &[u8]) -> ClientResult<Vec<u8>> {
+        self.cipher
+            .encrypt(Nonce::from_slice(iv), plaintext)
+            .map_err(|_| ClientError::Crypto("failed to encrypt".to_string()))
+    }
+
+    pub fn decrypt(&self, iv: &[u8], ciphertext: &[u8]) -> ClientResult<Vec<u8>> {
+        if iv.len() != IV_LEN {
+            return Err(ClientError::Crypto("invalid IV length".to_string()));
+        }
+
+        self.cipher
+            .decrypt(Nonce::from_slice(iv), ciphertext)
+            .map_err(|_| ClientError::Crypto("failed to decrypt".to_string()))
+    }
+
+    /// Encrypts a chunk digest with a fresh IV, which is prepended.
+    pub fn encrypt_digest(&self, digest: &ChunkDigest) -> ClientResult<Vec<u8>> {
+        let iv = generate_iv();
+        let mut bytes = iv.to_vec();
+        bytes.extend(self.encrypt(&iv, digest)?);
+
+        Ok(bytes)
+    }
+
+    pub fn decrypt_digest(&self, bytes: &[u8]) -> ClientResult<Vec<u8>> {
+        if bytes.len() < IV_LEN {
+            return Err(ClientError::Crypto("invalid digest".to_string()));
+        }
+        let (iv, digest) = bytes.split_at(IV_LEN);
+
+        self.decrypt(iv, digest)
+    }
+
+    pub fn encrypt_content_hash(&self, hash: &ChunkDigest) -> ClientResult<ContentHash> {
+        let iv = generate_iv();
+
+        Ok(ContentHash {
+            hash: BASE64.encode(self.encrypt(&iv, hash)?),
+            iv: BASE64.encode(iv),
+        })
+    }
+
+    pub fn decrypt_content_hash(&self, content_hash: &ContentHash) -> ClientResult<Vec<u8>> {
+        let (hash, iv) = match (
+            BASE64.decode(&content_hash.hash),
+            BASE64.decode(&content_hash.iv),
+        ) {
+            (Ok(hash), Ok(iv)) => (hash, iv),
+            _ => return Err(ClientError::Crypto("invalid content hash".to_string())),
+        };
+
+        self.decrypt(&iv, &hash)
+    }
+
+    /// Content key of a broadcast, wrapped by the sender with this key.
+    pub fn unwrap_key(&self, wrapped_key: &WrappedKey) -> ClientResult<ChunkKey> {
+        let (key, iv) = match (
+            BASE64.decode(&wrapped_key.key),
+            BASE64.decode(&wrapped_key.iv),
+        ) {
+            (Ok(key), Ok(iv)) => (key, iv),
+            _ => return Err(ClientError::Crypto("invalid wrapped key".to_string())),
+        };
+
+        ChunkKey::from_bytes(&self.decrypt(&iv, &key)?)
+    }
+}
+
+pub fn generate_iv() -> [u8; IV_LEN] {
+    Aes256Gcm::generate_nonce(&mut OsRng).into()
+}
+
+/// `SHA-256(chunk nr (u32, big endian) || plaintext chunk)`.
+pub fn chunk_digest(chunk_nr: u32, chunk: &[u8]) -> ChunkDigest {
+    let mut hasher = Sha256::new();
+    hasher.update(chunk_nr.to_be_bytes());
+    hasher.update(chunk);
+
+    hasher.finalize().into()
+}
+
+/// `SHA-256` over the digests of all chunks, in order.
+pub fn content_hash(digests: &[ChunkDigest]) -> ChunkDigest {
+    let mut hasher = Sha256::new();
+    for digest in digests {
+        hasher.update(digest);
+    }
+
+    hasher.finalize().into()
+}
+
+#[cfg(test)]
+mod tests {
+    //! Vectors generated with the functions of the web app (`utils.js`) on the Web Crypto API of Node,
+    //! with `crypto.getRandomValues` replaced to get fixed IVs.
+
+    use super::*;
+
+    const SECRET_A: &str = "c9afa9d845ba75166b5c215767b1d6934e50c3db36e89b127b8a622b120f6721";
+    const SECRET_B: &str = "0f56db78ca460b055c500064824bed999a25aaf48ebb519ac201537b85479813";
+    /// `convertKeyToBase64(keyPair.publicKey)`
+    const PUBLIC_KEY_A: &str =
+        "BGD+1LolWp0xyWHrdMY1bWjASbiSO2H6bOZpYi5g8p+2eQP+EAi4vJmkGunpVii8ZPLxsgwtfp9Rd6PClNRGIpk=";
+    const PUBLIC_KEY_B: &str =
+        "BOJm3f3BJmjbMNTKPo93SUMsQWBE8tK4wQvz1AEq7/qKv6hkBKLp/+Z9R8WH73qXp/RWuGO00Cz8aSiXOrWxyzk=";
+    /// `exportSharedSecretToBase64(await deriveSharedSecret(privateKeyA, publicKeyB))`, hex encoded
+    const SHARED_KEY: &str = "90223373f75e989ab8965d8cc88f01ceb4c622875861771da7bf1a0faccae374";
+
+    /// `digestChunk(0, 'hello')`
+    const DIGEST_0: &str = "44c0a0d0ddc9808a27834e778f82623f9c8970726bc935014f376cc1c7823673";
+    /// `digestChunk(258, 'world')`
+    const DIGEST_258: &str = "9c13e731b64917a4d348d063d8151e87f76b0ebf496bb0642a712555757f9afd";
+    /// `hashDigests([DIGEST_0, DIGEST_258])`
+    const CONTENT_HASH: &str = "60195d2079c6a338ea46563c83bd2058f6d7082b7e7c30773db5e9695cc1b175";
+    /// `encryptDigest(sharedKey, DIGEST_258)` with the IV `0..12`
+    const ENCRYPTED_DIGEST: &str =
+        "AAECAwQFBgcICQoLr5BvThY0urfDdvfljdFE/BsdFEw44E3Kc1s3had0a4+J2nmh8zx6GAYHRm3xx/nY";
+
+    /// Content key `0..32`, encrypted with the shared key and the IV `11..=0`
+    const WRAPPED_KEY: &str = "eEzLc/I0SXWu+inPdcEt3AuQ7t+DxPXnOM/WIDPWzaHI1jQUFpqQyc2UwzFNl/OO";
+    const WRAPPED_KEY_IV: &str = "CwoJCAcGBQQDAgEA";
+    /// `encryptData(contentKey, iv, 'hello')` with the IV of the wrapped key
+    const CONTENT_CHUNK: &str = "4898357e59b2f2cda59effc60eed08a631be84cb99";
+
+    fn key_pair(secret: &str) -> KeyPair {
+        KeyPair {
+            secret: SecretKey::from_slice(&hex::decode(secret).unwrap()).unwrap(),
+        }
+    }
+
+    fn shared_key() -> ChunkKey {
+        ChunkKey::from_bytes(&hex::decode(SHARED_KEY).unwrap()).unwrap()
+    }
+
+    fn decrypt_digest(key: &ChunkKey) -> Vec<u8> {
+        key.decrypt_digest(&BASE64.decode(ENCRYPTED_DIGEST).unwrap())
+            .unwrap()
+    }
+
+    #[test]
+    fn public_key_is_exported_raw() {
+        assert_eq!(key_pair(SECRET_A).public_key(), PUBLIC_KEY_A);
+        assert_eq!(key_pair(SECRET_B).public_key(), PUBLIC_KEY_B);
+    }
+
+    #[test]
+    fn shared_key_is_the_x_coordinate() {
+        let key = key_pair(SECRET_A).derive(PUBLIC_KEY_B).unwrap();
+        assert_eq!(hex::encode(decrypt_digest(&key)), DIGEST_258);
+
+        let key = key_pair(SECRET_B).derive(PUBLIC_KEY_A).unwrap();
+        assert_eq!(hex::encode(decrypt_digest(&key)), DIGEST_258);
+    }
+
+    #[test]
+    fn invalid_public_key_is_rejected() {
+        assert!(key_pair(SECRET_A).derive("BGD+1Lol").is_err());
+    }
+
+    #[test]
+    fn chunk_nr_is_digested_big_endian() {
+        assert_eq!(hex::encode(chunk_digest(0, b"hello")), DIGEST_0);
+        assert_eq!(hex::encode(chunk_digest(258, b"world")), DIGEST_258);
+    }
+
+    #[test]
+    fn content_hash_covers_the_digests_in_order() {
+        let digests = [chunk_digest(0, b"hello"), chunk_digest(258, b"world")];
+        assert_eq!(hex::encode(content_hash(&digests)), CONTENT_HASH);
+
+        let digests = [digests[1], digests[0]];
+        assert_ne!(hex::encode(content_hash(&digests)), CONTENT_HASH);
+    }
+
+    #[test]
+    fn digest_is_prefixed_with_its_iv() {
+        assert_eq!(hex::encode(decrypt_digest(&shared_key())), DIGEST_258);
+
+        let digest = chunk_digest(258, b"world");
+        let bytes = shared_key().encrypt_digest(&digest).unwrap();
+        // followed by the encrypted digest and the 16 byte tag
+        assert_eq!(bytes.len(), IV_LEN + DIGEST_LEN + 16);
+        assert_eq!(shared_key().decrypt_digest(&bytes).unwrap(), digest);
+    }
+
+    #[test]
+    fn content_key_is_unwrapped() {
+        let wrapped_key = WrappedKey {
+            key: WRAPPED_KEY.to_string(),
+            iv: WRAPPED_KEY_IV.to_string(),
+        };
+        let key = shared_key().unwrap_key(&wrapped_key).unwrap();
+
+        let iv = BASE64.decode(WRAPPED_KEY_IV).unwrap();
+        let chunk = key
+            .decrypt(&iv, &hex::decode(CONTENT_CHUNK).unwrap())
+            .unwrap();
+        assert_eq!(chunk, b"hello");
+    }
+
+    #[test]
+    fn wrapped_key_of_another_receiver_is_rejected() {
+        let wrapped_key = WrappedKey {
+            key: WRAPPED_KEY.to_string(),
+            iv: WRAPPED_KEY_IV.to_string(),
+        };
+        let key = ChunkKey::from_bytes(&[0; 32]).unwrap();
+
+        assert!(key.unwrap_key(&wrapped_key).is_err());
+    }
+}
@@ -0,0 +1,393 @@
+//! Drag-n-Share from a terminal.
+//!
+//! - `dns host <files>...` creates a session and serves the files until each was downloaded
+//! - `dns get <session name> <access code>` downloads the files of a session
+//! - `dns drop-box <dir>` keeps the files of a directory shared until it is stopped
+
+use std::collections::{HashMap, HashSet};
+use std::path::{Path, PathBuf};
+
+use clap::{Parser, Subcommand};
+use indicatif::{MultiProgress, ProgressBar, ProgressStyle};
+
+use dns_client::api::NewFile;
+use dns_client::{ApiClient, DropBox, Event, Transmittor};
+
+type CliResult = Result<(), Box<dyn std::error::Error>>;
+
+#[derive(Parser)]
+#[command(name = "dns", about = "Send and receive files with Drag-n-Share")]
+struct Cli {
+    /// Root of the API.
+    #[arg(
+        long,
+        global = true,
+        env = "DNS_API_URL",
+        default_value = "http://localhost:7878"
+    )]
+    api: String,
+    /// Root of the transmittor.
+    #[arg(
+        long,
+        global = true,
+        env = "DNS_TRANSMITTOR_URL",
+        default_value = "http://localhost:7879"
+    )]
+    transmittor: String,
+    #[command(subcommand)]
+    command: Command,
+}
+
+#[derive(Subcommand)]
+enum Command {
+    /// Creates a session and serves the files until each of them was downloaded.
+    Host {
+        #[arg(required = true)]
+        files: Vec<PathBuf>,
+    },
+    /// Joins a session and downloads its files.
+    Get {
+        session_name: String,
+        access_code: String,
+        /// Directory the files are written to.
+        #[arg(long, default_value = ".")]
+        out: PathBuf,
+        /// Only download this file, can be repeated.
+        #[arg(long = "file")]
+        files: Vec<String>,
+    },
+    /// Creates a session and keeps the files of the directory shared in it, until Ctrl-C.
+    DropBox { dir: PathBuf },
+}
+
+#[tokio::main]
+async fn main() {
+    env_logger::Builder::from_env(env_logger::Env::default().default_filter_or("warn")).init();
+
+    let cli = Cli::parse();
+    let result = match cli.command {
+        Command::Host { files } => host(&cli.api, &cli.transmittor, files).await,
+        Command::Get {
+            session_name,
+            access_code,
+            out,
+            files,
+        } => {
+            get(
+                &cli.api,
+                &cli.transmittor,
+                &session_name,
+                &access_code,
+                &out,
+                files,
+            )
+            .await
+        }
+        Command::DropBox { dir } => drop_box(&cli.api, &cli.transmittor, &dir).await,
+    };
+
+    if let Err(e) = result {
+        eprintln!("error: {}", e);
+        std::process::exit(1);
+    }
+}
+
+async fn host(api_url: &str, transmittor_url: &str, paths: Vec<PathBuf>) -> CliResult {
+    let mut files = Vec::new();
+    for path in paths {
+        let name = match path.file_name() {
+            Some(name) => name.to_string_lossy().to_string(),
+            None => return Err(format!("not a file: {}", path.display()).into()),
+        };
+        let data = tokio::fs::read(&path)
+            .await
+            .map_err(|e| format!("{}: {}", path.display(), e))?;
+        files.push((name, data));
+    }
+
+    let api = ApiClient::new(api_url)?;
+    let session = api.create_session().await?;
+
+    let new_files = files
+        .iter()
+        .map(|(name, data)| NewFile {
+            name: name.clone(),
+            size: data.len() as u64,
+        })
+        .collect::<Vec<NewFile>>();
+    api.add_files(&session.jwt, &session.session_id, &new_files)
+        .await?;
+
+    let mut transmittor =
+        Transmittor::connect(transmittor_url, &session.session_id, &session.jwt).await?;
+    transmittor.register().await?;
+    for (name, data) in &files {
+        transmittor.share(name, data);
+    }
+
+    println!("Session: {}", session.session_name);
+    println!("Code:    {}", session.access_code);
+
+    let result = serve(
+        &mut transmittor,
+        files.into_iter().map(|(name, _)| name).collect(),
+    )
+    .await;
+
+    // the session ends with its host
+    let _ = transmittor.close().await;
+    api.delete_session(&session.jwt, &session.session_id)
+        .await?;
+
+    result
+}
+
+/// Serves the files until each of them was downloaded once, or until Ctrl-C.
+async fn serve(transmittor: &mut Transmittor, mut pending: HashSet<String>) -> CliResult {
+    let bars = MultiProgress::new();
+    let mut transfers: HashMap<String, (String, ProgressBar)> = HashMap::new();
+
+    loop {
+        let event = tokio::select! {
+            _ = tokio::signal::ctrl_c() => return Ok(()),
+            event = transmittor.next_event() => event?,
+        };
+
+        match event {
+            None => return Err("transmittor closed the connection".into()),
+            Some(Event::FileRequested {
+                request_id,
+                filename,
+            }) => {
+                let bar = bars.add(progress_bar(&filename));
+                transfers.insert(request_id, (filename, bar));
+            }
+            Some(Event::Progress {
+                request_id,
+                progress,
+            }) => {
+                let (filename, bar) = match transfers.get(&request_id) {
+                    Some(transfer) => transfer,
+                    None => continue,
+                };
+                bar.set_length(progress.amount_of_chunks as u64);
+                bar.set_position(progress.chunks_done as u64);
+
+                if progress.chunks_done >= progress.amount_of_chunks {
+                    bar.finish();
+                    pending.remove(filename);
+                    transfers.remove(&request_id);
+
+                    if pending.is_empty() {
+                        return Ok(());
+                    }
+                }
+            }
+            Some(Event::Paused { request_id, .. }) => {
+                if let Some((filename, bar)) = transfers.get(&request_id) {
+                    bar.set_message(format!("{} (paused)", filename));
+                }
+            }
+            Some(Event::Resumed { request_id, .. }) => {
+                if let Some((filename, bar)) = transfers.get(&request_id) {
+                    bar.set_message(filename.clone());
+                }
+            }
+            Some(Event::Cancelled { request_id, reason }) => {
+                if let Some((filename, bar)) = transfers.remove(&request_id) {
+                    bar.abandon_with_message(format!("{} (cancelled: {})", filename, reason));
+                }
+            }
+            Some(Event::Failed {
+                command, message, ..
+            }) => {
+                let _ = bars.println(format!("{}: {}", command.unwrap_or_default(), message));
+            }
+            Some(_) => (),
+        }
+    }
+}
+
+async fn drop_box(api_url: &str, transmittor_url: &str, dir: &Path) -> CliResult {
+    let api = ApiClient::new(api_url)?;
+    let mut drop_box = DropBox::create(api, transmittor_url, dir).await?;
+
+    println!("Session: {}", drop_box.session().session_name);
+    println!("Code:    {}", drop_box.session().access_code);
+
+    let result = tokio::select! {
+        _ = tokio::signal::ctrl_c() => Ok(()),
+        result = drop_box.run() => result,
+    };
+
+    drop_box.close().await?;
+
+    Ok(result?)
+}
+
+async fn get(
+    api_url: &str,
+    transmittor_url: &str,
+    session_name: &str,
+    access_code: &str,
+    out: &Path,
+    wanted: Vec<String>,
+) -> CliResult {
+    let api = ApiClient::new(api_url)?;
+    let session_id = api.id_for_name(session_name).await?;
+    let jwt = api.join_session(&session_id, access_code).await?;
+
+    let filenames = api
+        .list_files(&jwt, &session_id)
+        .await?
+        .into_iter()
+        .filter(|file| !file.is_owner)
+        .map(|file| file.name)
+        .filter(|name| wanted.is_empty() || wanted.contains(name))
+        .collect::<Vec<String>>();
+    if filenames.is_empty() {
+        return Err("no files to download".into());
+    }
+
+    tokio::fs::create_dir_all(out).await?;
+
+    let mut transmittor = Transmittor::connect(transmittor_url, &session_id, &jwt).await?;
+    transmittor.register().await?;
+
+    let bars = MultiProgress::new();
+    let mut pending: HashMap<String, ProgressBar> = HashMap::new();
+    for filename in filenames {
+        transmittor.request_file(&filename).await?;
+
+        let bar = bars.add(progress_bar(&filename));
+        bar.set_message(format!("{} (requested)", filename));
+        pending.insert(filename, bar);
+    }
+
+    let result = download(&mut transmittor, &bars, &mut pending, out).await;
+    let _ = transmittor.close().await;
+
+    result
+}
+
+/// Receives the requested files, until each of them arrived or was cancelled.
+async fn download(
+    transmittor: &mut Transmittor,
+    bars: &MultiProgress,
+    pending: &mut HashMap<String, ProgressBar>,
+    out: &Path,
+) -> CliResult {
+    let mut filenames: HashMap<String, String> = HashMap::new();
+    let mut failed = 0;
+
+    while !pending.is_empty() {
+        let event = tokio::select! {
+            _ = tokio::signal::ctrl_c() => return Err("interrupted".into()),
+            event = transmittor.next_event() => event?,
+        };
+
+        match event {
+            None => return Err("transmittor closed the connection".into()),
+            Some(Event::QueuePosition { filename, position }) => {
+                if let Some(bar) = pending.get(&filename) {
+                    bar.set_message(format!("{} (queued, position {})", filename, position + 1));
+                }
+            }
+            Some(Event::FileAccepted {
+                request_id,
+                filename,
+                amount_of_chunks,
+            }) => {
+                if let Some(bar) = pending.get(&filename) {
+                    bar.set_message(filename.clone());
+                    bar.set_length(amount_of_chunks as u64);
+                }
+                filenames.insert(request_id, filename);
+            }
+            Some(Event::Progress {
+                request_id,
+                progress,
+            }) => {
+                let bar = filenames
+                    .get(&request_id)
+                    .and_then(|filename| pending.get(filename));
+                if let Some(bar) = bar {
+                    bar.set_position(progress.chunks_done as u64);
+                }
+            }
+            Some(Event::FileReceived { filename, data, .. }) => {
+                let bar = match pending.remove(&filename) {
+                    Some(bar) => bar,
+                    None => continue,
+                };
+
+                // the name comes from another user, it must not leave the output directory
+                let path = match Path::new(&filename).file_name() {
+                    Some(name) => out.join(name),
+                    None => {
+                        bar.abandon_with_message(format!("{} (invalid filename)", filename));
+                        failed += 1;
+                        continue;
+                    }
+                };
+                tokio::fs::write(&path, data)
+                    .await
+                    .map_err(|e| format!("{}: {}", path.display(), e))?;
+
+                bar.finish_with_message(path.display().to_string());
+            }
+            Some(Event::Paused { request_id, .. }) => {
+                if let Some(filename) = filenames.get(&request_id) {
+                    if let Some(bar) = pending.get(filename) {
+                        bar.set_message(format!("{} (paused)", filename));
+                    }
+                }
+            }
+            Some(Event::Resumed { request_id, .. }) => {
+                if let Some(filename) = filenames.get(&request_id) {
+                    if let Some(bar) = pending.get(filename) {
+                        bar.set_message(filename.clone());
+                    }
+                }
+            }
+            Some(Event::Cancelled { request_id, reason }) => {
+                let filename = filenames.remove(&request_id).unwrap_or_default();
+                if let Some(bar) = pending.remove(&filename) {
+                    bar.abandon_with_message(format!("{} (cancelled: {})", filename, reason));
+                    failed += 1;
+                }
+            }
+            Some(Event::Failed {
+                filename: Some(filename),
+                message,
+                ..
+            }) => {
+                if let Some(bar) = pending.remove(&filename) {
+                    bar.abandon_with_message(format!("{} (failed: {})", filename, message));
+                    failed += 1;
+                }
+            }
+            Some(Event::Failed {
+                command, message, ..
+            }) => {
+                let _ = bars.println(format!("{}: {}", command.unwrap_or_default(), message));
+            }
+            Some(_) => (),
+        }
+    }
+
+    match failed {
+        0 => Ok(()),
+        failed => Err(format!("{} file(s) could not be downloaded", failed).into()),
+    }
+}
+
+fn progress_bar(filename: &str) -> ProgressBar {
+    let style = ProgressStyle::with_template("{msg:30!} [{bar:40}] {pos}/{len} chunks {eta}")
+        .unwrap_or_else(|_| ProgressStyle::default_bar())
+        .progress_chars("=> ");
+
+    ProgressBar::new(0)
+        .with_style(style)
+        .with_message(filename.to_string())
+}
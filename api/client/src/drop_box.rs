@@ -0,0 +1,285 @@
+//! Keeps the files of a directory shared in a session, for as long as it runs.
+//!
+//! New and changed files are added to the session, removed ones are deleted from it.
+//! Requests are answered by the [`Transmittor`], the session and the JWT are refreshed
+//! before they expire and the websocket is reopened after it was lost.
+
+use std::collections::HashMap;
+use std::path::{Path, PathBuf};
+use std::time::{Duration, SystemTime};
+
+use log::{error, info, warn};
+use tokio::time::{self, Instant, MissedTickBehavior};
+
+use crate::api::{ApiClient, CreatedSession, NewFile};
+use crate::error::{ClientError, ClientResult};
+use crate::transmittor::{Event, Transmittor};
+
+const SCAN_INTERVAL: Duration = Duration::from_secs(2);
+/// Well within the lifetime of a session (5min) and of a JWT (1h).
+const REFRESH_INTERVAL: Duration = Duration::from_secs(60);
+const MAX_RECONNECT_DELAY: Duration = Duration::from_secs(60);
+
+/// Version of a shared file, it is shared again once it changed.
+#[derive(Clone, Copy, PartialEq)]
+struct FileState {
+    size: u64,
+    modified: Option<SystemTime>,
+}
+
+pub struct DropBox {
+    api: ApiClient,
+    transmittor: Transmittor,
+    dir: PathBuf,
+    session: CreatedSession,
+    files: HashMap<String, FileState>,
+}
+
+impl DropBox {
+    /// Creates a session and shares the files of the directory in it.
+    pub async fn create(api: ApiClient, transmittor_url: &str, dir: &Path) -> ClientResult<Self> {
+        let session = api.create_session().await?;
+
+        let mut transmittor =
+            Transmittor::connect(transmittor_url, &session.session_id, &session.jwt).await?;
+        transmittor.register().await?;
+
+        let mut drop_box = DropBox {
+            api,
+            transmittor,
+            dir: dir.to_path_buf(),
+            session,
+            files: HashMap::new(),
+        };
+        drop_box.scan().await?;
+
+        Ok(drop_box)
+    }
+
+    /// The session, its JWT is replaced on every refresh.
+    pub fn session(&self) -> &CreatedSession {
+        &self.session
+    }
+
+    /// Serves the directory, only returns once the session can't be kept alive.
+    pub async fn run(&mut self) -> ClientResult<()> {
+        let mut scan = time::interval(SCAN_INTERVAL);
+        scan.set_missed_tick_behavior(MissedTickBehavior::Delay);
+        let mut refresh = time::interval_at(Instant::now() + REFRESH_INTERVAL, REFRESH_INTERVAL);
+        refresh.set_missed_tick_behavior(MissedTickBehavior::Delay);
+
+        loop {
+            tokio::select! {
+                _ = scan.tick() => {
+                    match self.scan().await {
+                        Ok(_) => (),
+                        Err(e) => {
+                            error!("Failed to scan: {} ({})", self.dir.display(), e);
+                        }
+                    }
+                }
+                _ = refresh.tick() => {
+                    self.refresh().await?;
+
+                    // extends the connection with the new JWT
+                    match self.transmittor.hello().await {
+                        Ok(_) => (),
+                        Err(e) => {
+                            error!("Failed to send the new JWT ({})", e);
+                        }
+                    }
+                }
+                // cancel safe, the ticks above do not interrupt the handling of a message
+                event = self.transmittor.next_event() => {
+                    match event {
+                        Ok(Some(event)) => log_event(event),
+                        Ok(None) => {
+                            warn!("Transmittor closed the connection");
+                            self.reconnect().await?;
+                        }
+                        Err(e) => {
+                            error!("Lost the connection to the transmittor ({})", e);
+                            self.reconnect().await?;
+                        }
+                    }
+                }
+            }
+        }
+    }
+
+    /// Ends the session.
+    pub async fn close(mut self) -> ClientResult<()> {
+        let _ = self.transmittor.close().await;
+
+        self.api
+            .delete_session(&self.session.jwt, &self.session.session_id)
+            .await
+    }
+
+    /// Adds new and changed files to the session and deletes removed ones.
+    async fn scan(&mut self) -> ClientResult<()> {
+        let found = read_dir(&self.dir).await?;
+
+        let removed = self
+            .files
+            .keys()
+            .filter(|name| !found.contains_key(*name))
+            .cloned()
+            .collect::<Vec<String>>();
+        for name in removed {
+            match self.remove_file(&name).await {
+                Ok(_) => info!("Removed: {}", &name),
+                Err(e) => {
+                    error!("Failed to remove file: {} ({})", &name, e);
+                }
+            }
+        }
+
+        for (name, state) in found {
+            if self.files.get(&name) == Some(&state) {
+                continue;
+            }
+
+            match self.share_file(&name, state).await {
+                Ok(_) => info!("Shared: {}", &name),
+                Err(e) => {
+                    error!("Failed to share file: {} ({})", &name, e);
+                }
+            }
+        }
+
+        Ok(())
+    }
+
+    async fn share_file(&mut self, name: &str, state: FileState) -> ClientResult<()> {
+        let data = tokio::fs::read(self.dir.join(name)).await?;
+
+        // a changed file replaces the old one
+        if self.files.contains_key(name) {
+            self.remove_file(name).await?;
+        }
+
+        let file = NewFile {
+            name: name.to_string(),
+            size: data.len() as u64,
+        };
+        self.api
+            .add_files(&self.session.jwt, &self.session.session_id, &[file])
+            .await?;
+
+        self.transmittor.share(name, &data);
+        self.files.insert(name.to_string(), state);
+
+        Ok(())
+    }
+
+    async fn remove_file(&mut self, name: &str) -> ClientResult<()> {
+        self.api
+            .delete_file(&self.session.jwt, &self.session.session_id, name)
+            .await?;
+
+        self.transmittor.unshare(name);
+        self.files.remove(name);
+
+        Ok(())
+    }
+
+    /// Prolongs the session and replaces the JWT, only fails if the API refused it.
+    async fn refresh(&mut self) -> ClientResult<()> {
+        let jwt = match self
+            .api
+            .refresh_session(&self.session.jwt, &self.session.session_id)
+            .await
+        {
+            Ok(jwt) => jwt,
+            Err(e @ ClientError::Api { .. }) => return Err(e),
+            Err(e) => {
+                // retried with the next refresh, long before the session expires
+                error!(
+                    "Failed to refresh session: {} ({})",
+                    &self.session.session_id, e
+                );
+                return Ok(());
+            }
+        };
+
+        self.transmittor.set_jwt(&jwt);
+        self.session.jwt = jwt;
+
+        Ok(())
+    }
+
+    /// Reopens the websocket, retrying with a growing delay.
+    async fn reconnect(&mut self) -> ClientResult<()> {
+        let mut delay = Duration::from_secs(1);
+
+        loop {
+            time::sleep(delay).await;
+
+            // the connection might have been closed because the JWT expired
+            self.refresh().await?;
+
+            match self.transmittor.reconnect().await {
+                Ok(_) => {
+                    info!("Reconnected to the transmittor");
+                    return Ok(());
+                }
+                Err(e) => {
+                    error!("Failed to reconnect to the transmittor ({})", e);
+                }
+            }
+
+            delay = (delay * 2).min(MAX_RECONNECT_DELAY);
+        }
+    }
+}
+
+/// Regular files of the directory, hidden ones are skipped.
+async fn read_dir(dir: &Path) -> ClientResult<HashMap<String, FileState>> {
+    let mut files = HashMap::new();
+
+    let mut entries = tokio::fs::read_dir(dir).await?;
+    while let Some(entry) = entries.next_entry().await? {
+        let name = match entry.file_name().into_string() {
+            Ok(name) if !name.starts_with('.') => name,
+            _ => continue,
+        };
+
+        let metadata = match entry.metadata().await {
+            Ok(metadata) if metadata.is_file() => metadata,
+            _ => continue,
+        };
+
+        let state = FileState {
+            size: metadata.len(),
+            modified: metadata.modified().ok(),
+        };
+        files.insert(name, state);
+    }
+
+    Ok(files)
+}
+
+fn log_event(event: Event) {
+    match event {
+        Event::FileRequested {
+            request_id,
+            filename,
+        } => info!("Requested: {} ({})", filename, request_id),
+        Event::Progress {
+            request_id,
+            progress,
+        } if progress.chunks_done >= progress.amount_of_chunks => {
+            info!("Transferred: {}", request_id)
+        }
+        Event::Cancelled { request_id, reason } => {
+            warn!("Cancelled: {} ({})", request_id, reason)
+        }
+        Event::Failed {
+            command, message, ..
+        } => {
+            warn!("{}: {}", command.unwrap_or_default(), message)
+        }
+        _ => (),
+    }
+}
@@ -0,0 +1,62 @@
+use std::fmt;
+
+/// Error of a call to the API or the transmittor.
+#[derive(Debug)]
+pub enum ClientError {
+    /// Error envelope answered by the API.
+    Api {
+        status: u16,
+        code: String,
+        message: String,
+        /// Field of the request that is invalid.
+        field: Option<String>,
+    },
+    Http(reqwest::Error),
+    WebSocket(Box<tokio_tungstenite::tungstenite::Error>),
+    /// Unexpected message or state.
+    Protocol(String),
+    /// Key exchange, encryption or an integrity check failed.
+    Crypto(String),
+    /// Reading or writing local files.
+    Io(std::io::Error),
+}
+
+impl fmt::Display for ClientError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ClientError::Api {
+                status,
+                code,
+                message,
+                ..
+            } => write!(f, "{} ({}): {}", code, status, message),
+            ClientError::Http(e) => write!(f, "http: {}", e),
+            ClientError::WebSocket(e) => write!(f, "websocket: {}", e),
+            ClientError::Protocol(message) => write!(f, "protocol: {}", message),
+            ClientError::Crypto(message) => write!(f, "crypto: {}", message),
+            ClientError::Io(e) => write!(f, "io: {}", e),
+        }
+    }
+}
+
+impl std::error::Error for ClientError {}
+
+impl From<reqwest::Error> for ClientError {
+    fn from(e: reqwest::Error) -> Self {
+        ClientError::Http(e)
+    }
+}
+
+impl From<tokio_tungstenite::tungstenite::Error> for ClientError {
+    fn from(e: tokio_tungstenite::tungstenite::Error) -> Self {
+        ClientError::WebSocket(Box::new(e))
+    }
+}
+
+impl From<std::io::Error> for ClientError {
+    fn from(e: std::io::Error) -> Self {
+        ClientError::Io(e)
+    }
+}
+
+pub type ClientResult<T> = Result<T, ClientError>;
@@ -0,0 +1,17 @@
+//! Client of Drag-n-Share, interoperable with the web app.
+//!
+//! [`api::ApiClient`] wraps the routes of the API,
+//! [`transmittor::Transmittor`] sends and receives files over the websocket of the transmittor,
+//! [`drop_box::DropBox`] keeps a directory shared.
+
+pub mod api;
+pub mod crypto;
+pub mod drop_box;
+pub mod error;
+pub mod payload;
+pub mod transmittor;
+
+pub use api::ApiClient;
+pub use drop_box::DropBox;
+pub use error::{ClientError, ClientResult};
+pub use transmittor::{Event, Transmittor};
@@ -0,0 +1,45 @@
+//! Files are exchanged in the format the web app stores them in:
+//! `name:<filename>;data:<mime type>;base64,<file>`, read back as a data URL and sliced into text chunks.
+
+use base64::{engine::general_purpose::STANDARD as BASE64, Engine};
+
+use crate::error::{ClientError, ClientResult};
+
+/// Characters of the payload per chunk, as sliced by the web app.
+pub const CHUNK_SIZE: usize = 32768;
+
+const MIME_TYPE: &str = "application/octet-stream";
+
+/// Payload of a file, as the web app sends it.
+pub fn encode(filename: &str, data: &[u8]) -> String {
+    let file = format!(
+        "name:{};data:{};base64,{}",
+        filename,
+        MIME_TYPE,
+        BASE64.encode(data)
+    );
+
+    format!("data:{};base64,{}", MIME_TYPE, BASE64.encode(file))
+}
+
+/// Content of a file received from the web app or another client.
+pub fn decode(payload: &str) -> ClientResult<Vec<u8>> {
+    let file = payload
+        .split_once(',')
+        .and_then(|(_, file)| BASE64.decode(file).ok())
+        .and_then(|file| String::from_utf8(file).ok())
+        .ok_or(ClientError::Protocol("invalid file payload".to_string()))?;
+
+    file.rsplit_once(',')
+        .and_then(|(_, data)| BASE64.decode(data).ok())
+        .ok_or(ClientError::Protocol("invalid file payload".to_string()))
+}
+
+/// Chunks of the payload, the payload is plain ASCII.
+pub fn chunks(payload: &str) -> Vec<String> {
+    payload
+        .as_bytes()
+        .chunks(CHUNK_SIZE)
+        .map(|chunk| String::from_utf8_lossy(chunk).to_string())
+        .collect()
+}
@@ -0,0 +1,701 @@
+//! Websocket client of the transmittor, sending and receiving files like the web app does.
+//!
+//! Shared files are served automatically: every `acknowledge-file-request` is answered
+//! and every `send-next-chunk` is answered with the encrypted chunk.
+//! Requested files are acknowledged chunk by chunk and verified before they are handed out.
+
+use std::collections::{HashMap, VecDeque};
+use std::sync::Arc;
+
+use futures_util::{future, SinkExt, StreamExt};
+use reqwest::Url;
+use tokio::net::TcpStream;
+use tokio_tungstenite::{
+    tungstenite::{client::IntoClientRequest, http::HeaderValue, Message},
+    MaybeTlsStream, WebSocketStream,
+};
+
+use log::{error, info};
+
+use utils::chunk_frame::ChunkFrame;
+use utils::protocol::{
+    self, ContentHash, ReqAcknowledgeFileRequest, ReqCancelTransfer, ReqHello,
+    ReqReadyForFileRequest, ReqReceivedChunk, ReqRegister, ReqRequestFile, ReqResumeTransfer,
+    WsCommand, WsMessage, WsMsg, WsMsgAcknowledgeFileRequest, WsMsgPrepareForFileTransfer,
+    WsMsgTransferProgress, WsRequest,
+};
+
+use crate::crypto::{self, ChunkDigest, ChunkKey, KeyPair};
+use crate::error::{ClientError, ClientResult};
+use crate::payload;
+
+/// Chunks in flight offered to and requested from the transmittor.
+const WINDOW_SIZE: u32 = 8;
+
+/// Prefix of the id a `request-file` is sent with, followed by the filename.
+const REQUEST_FILE_ID_PREFIX: &str = "request-file:";
+
+const CAPABILITIES: &[&str] = &[
+    protocol::CAPABILITY_BINARY_CHUNKS,
+    protocol::CAPABILITY_CHUNK_DIGESTS,
+];
+
+/// Something that happened on the websocket.
+#[derive(Debug)]
+pub enum Event {
+    /// Another user requested a shared file, the transfer starts once they are ready.
+    FileRequested {
+        request_id: String,
+        filename: String,
+    },
+    /// The sender accepted the request of a file, the transfer starts.
+    FileAccepted {
+        request_id: String,
+        filename: String,
+        amount_of_chunks: u32,
+    },
+    /// A requested file has been received and verified.
+    FileReceived {
+        request_id: String,
+        filename: String,
+        data: Vec<u8>,
+    },
+    /// Position of a requested file in the queue of the sender.
+    QueuePosition {
+        filename: String,
+        position: usize,
+    },
+    Progress {
+        request_id: String,
+        progress: WsMsgTransferProgress,
+    },
+    /// Another participant disconnected.
+    Paused {
+        request_id: String,
+        grace_period: i64,
+    },
+    Resumed {
+        request_id: String,
+        chunk_nr: u32,
+    },
+    Cancelled {
+        request_id: String,
+        reason: String,
+    },
+    /// A command was answered with an error.
+    Failed {
+        command: Option<String>,
+        /// File of a failed `request-file`, it is not requested anymore.
+        filename: Option<String>,
+        code: String,
+        message: String,
+    },
+}
+
+/// File served to other users.
+#[derive(Clone)]
+struct SharedFile {
+    chunks: Arc<Vec<String>>,
+    digests: Arc<Vec<ChunkDigest>>,
+}
+
+/// Transfer of a shared file.
+struct Outgoing {
+    filename: String,
+    key: ChunkKey,
+    file: SharedFile,
+}
+
+/// Transfer of a requested file.
+struct Incoming {
+    filename: String,
+    key: ChunkKey,
+    content_hash: Option<ContentHash>,
+    digests: Vec<ChunkDigest>,
+    payload: String,
+}
+
+impl Incoming {
+    fn received_chunk_nr(&self) -> u32 {
+        self.digests.len() as u32
+    }
+}
+
+pub struct Transmittor {
+    ws: WebSocketStream<MaybeTlsStream<TcpStream>>,
+    /// Websocket of the session, to reconnect to.
+    url: String,
+    jwt: String,
+    files: HashMap<String, SharedFile>,
+    /// Key pairs of requested files that are not prepared yet, by filename.
+    requested: HashMap<String, KeyPair>,
+    outgoing: HashMap<String, Outgoing>,
+    incoming: HashMap<String, Incoming>,
+    events: VecDeque<Event>,
+    /// Frames of handled messages that are not sent yet.
+    outbox: VecDeque<Message>,
+}
+
+impl Transmittor {
+    /// Opens the websocket of a session, `base_url` is the root of the transmittor.
+    pub async fn connect(base_url: &str, session_id: &str, jwt: &str) -> ClientResult<Self> {
+        let mut url = Url::parse(base_url)
+            .map_err(|e| ClientError::Protocol(format!("invalid transmittor url: {}", e)))?;
+        let scheme = match url.scheme() {
+            "https" | "wss" => "wss",
+            _ => "ws",
+        };
+        let _ = url.set_scheme(scheme);
+        if let Ok(mut path) = url.path_segments_mut() {
+            path.pop_if_empty().extend(["session", session_id]);
+        }
+
+        let ws = Self::open(url.as_str(), jwt).await?;
+
+        Ok(Transmittor {
+            ws,
+            url: url.to_string(),
+            jwt: jwt.to_string(),
+            files: HashMap::new(),
+            requested: HashMap::new(),
+            outgoing: HashMap::new(),
+            incoming: HashMap::new(),
+            events: VecDeque::new(),
+            outbox: VecDeque::new(),
+        })
+    }
+
+    /// Opens a new websocket after the connection was lost, shared files and running
+    /// transfers are kept. Transfers are resumed within the grace period of the transmittor.
+    /// Fails with a conflict while the transmittor still serves the old connection.
+    pub async fn reconnect(&mut self) -> ClientResult<()> {
+        // the transmittor only allows one listener per user
+        let _ = self.ws.close(None).await;
+        // frames of the old connection, the transfers are resumed instead
+        self.outbox.clear();
+
+        self.ws = Self::open(&self.url, &self.jwt).await?;
+        self.register().await?;
+
+        for request_id in self.transfers() {
+            self.resume(&request_id).await?;
+        }
+
+        Ok(())
+    }
+
+    /// Starts receiving messages, waits until the transmittor confirmed.
+    pub async fn register(&mut self) -> ClientResult<()> {
+        self.send(WsCommand::Register(ReqRegister {
+            version: Some(protocol::PROTOCOL_VERSION),
+            capabilities: Some(capabilities()),
+        }))
+        .await?;
+
+        self.wait_for_reply("register").await
+    }
+
+    /// Sends the JWT with the following commands, which extends the connection until it expires.
+    pub fn set_jwt(&mut self, jwt: &str) {
+        self.jwt = jwt.to_string();
+    }
+
+    /// States the protocol version and the capabilities again,
+    /// e.g. to extend the connection with a new JWT right away.
+    pub async fn hello(&mut self) -> ClientResult<()> {
+        self.send(WsCommand::Hello(ReqHello {
+            version: protocol::PROTOCOL_VERSION,
+            capabilities: capabilities(),
+        }))
+        .await
+    }
+
+    /// Serves a file to the users requesting it.
+    /// The file still has to be added to the session with [`crate::api::ApiClient::add_files`].
+    pub fn share(&mut self, filename: &str, data: &[u8]) {
+        let chunks = payload::chunks(&payload::encode(filename, data));
+        let digests = chunks
+            .iter()
+            .enumerate()
+            .map(|(i, chunk)| crypto::chunk_digest(i as u32 + 1, chunk.as_bytes()))
+            .collect();
+
+        self.files.insert(
+            filename.to_string(),
+            SharedFile {
+                chunks: Arc::new(chunks),
+                digests: Arc::new(digests),
+            },
+        );
+    }
+
+    /// Stops serving a file, running transfers are finished.
+    pub fn unshare(&mut self, filename: &str) {
+        self.files.remove(filename);
+    }
+
+    /// Requests a file of the session, it is handed out with [`Event::FileReceived`].
+    pub async fn request_file(&mut self, filename: &str) -> ClientResult<()> {
+        let key_pair = KeyPair::generate();
+        let public_key = key_pair.public_key();
+        self.requested.insert(filename.to_string(), key_pair);
+
+        // the reply is told apart by its id, see `request_file_id`
+        self.queue_with_id(
+            Some(request_file_id(filename)),
+            WsCommand::RequestFile(ReqRequestFile {
+                public_key,
+                filename: filename.to_string(),
+            }),
+        )?;
+
+        self.flush().await
+    }
+
+    pub async fn cancel(&mut self, request_id: &str, reason: &str) -> ClientResult<()> {
+        self.queue_cancel(request_id, reason)?;
+
+        self.flush().await
+    }
+
+    /// Continues the transfers of this client after it reconnected.
+    pub async fn resume(&mut self, request_id: &str) -> ClientResult<()> {
+        self.send(WsCommand::ResumeTransfer(ReqResumeTransfer {
+            request_id: request_id.to_string(),
+        }))
+        .await
+    }
+
+    /// Requests of the running transfers of this client.
+    pub fn transfers(&self) -> Vec<String> {
+        self.outgoing
+            .keys()
+            .chain(self.incoming.keys())
+            .cloned()
+            .collect()
+    }
+
+    /// Handles messages until something happens, `None` once the websocket closed.
+    ///
+    /// Cancel safe, e.g. in `tokio::select!`: a message is handled without awaiting,
+    /// its replies are queued and sent by the next call.
+    pub async fn next_event(&mut self) -> ClientResult<Option<Event>> {
+        loop {
+            self.flush().await?;
+
+            if let Some(event) = self.events.pop_front() {
+                return Ok(Some(event));
+            }
+
+            match self.ws.next().await {
+                Some(message) => self.handle(message?)?,
+                None => return Ok(None),
+            }
+        }
+    }
+
+    pub async fn close(&mut self) -> ClientResult<()> {
+        self.ws.close(None).await?;
+
+        Ok(())
+    }
+
+    async fn open(
+        url: &str,
+        jwt: &str,
+    ) -> ClientResult<WebSocketStream<MaybeTlsStream<TcpStream>>> {
+        let mut request = url.into_client_request()?;
+        let authorization = HeaderValue::from_str(&format!("Bearer {}", jwt))
+            .map_err(|_| ClientError::Protocol("invalid jwt".to_string()))?;
+        request.headers_mut().insert("authorization", authorization);
+
+        let (ws, _) = tokio_tungstenite::connect_async(request).await?;
+
+        Ok(ws)
+    }
+
+    async fn send(&mut self, command: WsCommand) -> ClientResult<()> {
+        self.queue(command)?;
+
+        self.flush().await
+    }
+
+    fn queue(&mut self, command: WsCommand) -> ClientResult<()> {
+        self.queue_with_id(None, command)
+    }
+
+    fn queue_with_id(&mut self, id: Option<String>, command: WsCommand) -> ClientResult<()> {
+        let request = WsRequest {
+            id,
+            jwt: Some(self.jwt.clone()),
+            command,
+        };
+        let message = serde_json::to_string(&request)
+            .map_err(|e| ClientError::Protocol(format!("invalid request: {}", e)))?;
+
+        self.outbox.push_back(Message::Text(message));
+
+        Ok(())
+    }
+
+    /// Sends the queued frames. Cancel safe, a frame leaves the queue
+    /// only once the websocket took it.
+    async fn flush(&mut self) -> ClientResult<()> {
+        while !self.outbox.is_empty() {
+            future::poll_fn(|cx| self.ws.poll_ready_unpin(cx)).await?;
+
+            if let Some(message) = self.outbox.pop_front() {
+                self.ws.start_send_unpin(message)?;
+            }
+        }
+
+        future::poll_fn(|cx| self.ws.poll_flush_unpin(cx)).await?;
+
+        Ok(())
+    }
+
+    fn queue_cancel(&mut self, request_id: &str, reason: &str) -> ClientResult<()> {
+        self.outgoing.remove(request_id);
+        self.incoming.remove(request_id);
+
+        self.queue(WsCommand::CancelTransfer(ReqCancelTransfer {
+            request_id: request_id.to_string(),
+            reason: Some(reason.to_string()),
+        }))
+    }
+
+    /// Handles messages until the command is answered.
+    async fn wait_for_reply(&mut self, command: &str) -> ClientResult<()> {
+        loop {
+            let message = match self.ws.next().await {
+                Some(message) => message?,
+                None => return Err(ClientError::Protocol("websocket closed".to_string())),
+            };
+
+            if let Message::Text(text) = &message {
+                if let Ok(reply) = serde_json::from_str::<WsMessage>(text) {
+                    match reply.msg {
+                        WsMsg::Ok(ok) if ok.command.as_deref() == Some(command) => return Ok(()),
+                        WsMsg::Error(e) if e.command.as_deref() == Some(command) => {
+                            return Err(ClientError::Api {
+                                status: 0,
+                                code: e.code,
+                                message: e.message,
+                                field: e.field,
+                            });
+                        }
+                        _ => (),
+                    }
+                }
+            }
+
+            self.handle(message)?;
+            self.flush().await?;
+        }
+    }
+
+    /// Changes the state according to the message and queues the replies, without awaiting.
+    fn handle(&mut self, message: Message) -> ClientResult<()> {
+        match message {
+            Message::Text(text) => match serde_json::from_str::<WsMessage>(&text) {
+                Ok(message) => self.handle_message(message),
+                Err(e) => {
+                    error!("Unknown message: {} ({})", text, e);
+                    Ok(())
+                }
+            },
+            Message::Binary(bytes) => match ChunkFrame::decode(&bytes) {
+                Ok(frame) => self.add_chunk(frame),
+                Err(e) => {
+                    error!("Invalid chunk frame ({})", e);
+                    Ok(())
+                }
+            },
+            _ => Ok(()),
+        }
+    }
+
+    fn handle_message(&mut self, message: WsMessage) -> ClientResult<()> {
+        let request_id = message.request_id;
+
+        match message.msg {
+            WsMsg::AcknowledgeFileRequest(data) => {
+                self.acknowledge_file_request(request_id, data)?
+            }
+            WsMsg::PrepareForFileTransfer(data) => {
+                self.prepare_for_file_transfer(request_id, data)?
+            }
+            WsMsg::SendNextChunk(data) => self.send_chunk(&request_id, data.chunk_nr)?,
+            WsMsg::AddChunk(data) => {
+                let frame = ChunkFrame::from_text(
+                    &request_id,
+                    data.chunk_nr,
+                    data.is_last_chunk,
+                    &data.chunk,
+                    &data.iv,
+                    data.digest.as_deref(),
+                )
+                .map_err(|e| ClientError::Protocol(format!("invalid chunk: {}", e)))?;
+                self.add_chunk(frame)?
+            }
+            WsMsg::QueuePosition(data) => self.events.push_back(Event::QueuePosition {
+                filename: data.filename,
+                position: data.position,
+            }),
+            WsMsg::TransferProgress(progress) => self.events.push_back(Event::Progress {
+                request_id,
+                progress,
+            }),
+            WsMsg::TransferPaused(data) => self.events.push_back(Event::Paused {
+                request_id,
+                grace_period: data.grace_period,
+            }),
+            WsMsg::TransferResumed(data) => self.events.push_back(Event::Resumed {
+                request_id,
+                chunk_nr: data.chunk_nr,
+            }),
+            WsMsg::TransferCancelled(data) => {
+                self.outgoing.remove(&request_id);
+                self.incoming.remove(&request_id);
+                self.events.push_back(Event::Cancelled {
+                    request_id,
+                    reason: data.reason,
+                });
+            }
+            WsMsg::Error(e) => {
+                let filename =
+                    e.id.as_deref()
+                        .and_then(|id| id.strip_prefix(REQUEST_FILE_ID_PREFIX))
+                        .filter(|filename| self.requested.remove(*filename).is_some())
+                        .map(String::from);
+
+                self.events.push_back(Event::Failed {
+                    command: e.command,
+                    filename,
+                    code: e.code,
+                    message: e.message,
+                });
+            }
+            WsMsg::Hello(_) | WsMsg::Ok(_) => (),
+        }
+
+        Ok(())
+    }
+
+    /// Accepts the request of a shared file, the key is derived from the public key of the receiver.
+    fn acknowledge_file_request(
+        &mut self,
+        request_id: String,
+        data: WsMsgAcknowledgeFileRequest,
+    ) -> ClientResult<()> {
+        info!("acknowledge_file_request: {}", &data.filename);
+
+        let file = match self.files.get(&data.filename) {
+            Some(file) => file.clone(),
+            None => return self.queue_cancel(&request_id, "File is no longer shared."),
+        };
+
+        let key_pair = KeyPair::generate();
+        let key = key_pair.derive(&data.public_key)?;
+        let content_hash = key.encrypt_content_hash(&crypto::content_hash(&file.digests))?;
+
+        self.queue(WsCommand::AcknowledgeFileRequest(
+            ReqAcknowledgeFileRequest {
+                request_id: request_id.clone(),
+                public_key: key_pair.public_key(),
+                amount_of_chunks: file.chunks.len() as u32,
+                filename: data.filename.clone(),
+                window_size: Some(WINDOW_SIZE),
+                wrapped_keys: None,
+                content_hash: Some(content_hash),
+            },
+        ))?;
+
+        self.outgoing.insert(
+            request_id.clone(),
+            Outgoing {
+                filename: data.filename.clone(),
+                key,
+                file,
+            },
+        );
+        self.events.push_back(Event::FileRequested {
+            request_id,
+            filename: data.filename,
+        });
+
+        Ok(())
+    }
+
+    fn prepare_for_file_transfer(
+        &mut self,
+        request_id: String,
+        data: WsMsgPrepareForFileTransfer,
+    ) -> ClientResult<()> {
+        info!("prepare_for_file_transfer: {}", &data.filename);
+
+        let key_pair = match self.requested.remove(&data.filename) {
+            Some(key_pair) => key_pair,
+            None => return self.queue_cancel(&request_id, "File was not requested."),
+        };
+
+        let key = key_pair.derive(&data.public_key)?;
+        let key = match &data.wrapped_key {
+            Some(wrapped_key) => key.unwrap_key(wrapped_key)?,
+            None => key,
+        };
+
+        self.events.push_back(Event::FileAccepted {
+            request_id: request_id.clone(),
+            filename: data.filename.clone(),
+            amount_of_chunks: data.amount_of_chunks,
+        });
+        self.incoming.insert(
+            request_id.clone(),
+            Incoming {
+                filename: data.filename,
+                key,
+                content_hash: data.content_hash,
+                digests: Vec::new(),
+                payload: String::new(),
+            },
+        );
+
+        self.queue(WsCommand::ReadyForFileTransfer(ReqReadyForFileRequest {
+            request_id,
+            window_size: Some(WINDOW_SIZE),
+        }))
+    }
+
+    fn send_chunk(&mut self, request_id: &str, chunk_nr: u32) -> ClientResult<()> {
+        let transfer = match self.outgoing.get(request_id) {
+            Some(transfer) => transfer,
+            None => return Ok(()),
+        };
+
+        let chunks = &transfer.file.chunks;
+        let chunk = match chunk_nr.checked_sub(1).and_then(|i| chunks.get(i as usize)) {
+            Some(chunk) => chunk,
+            None => return Ok(()),
+        };
+
+        let iv = crypto::generate_iv();
+        let digest = transfer.file.digests[chunk_nr as usize - 1];
+        let frame = ChunkFrame {
+            request_id: request_id.to_string(),
+            chunk_nr,
+            iv,
+            is_last_chunk: chunk_nr as usize == chunks.len(),
+            digest: Some(transfer.key.encrypt_digest(&digest)?),
+            chunk: transfer.key.encrypt(&iv, chunk.as_bytes())?,
+        };
+        let bytes = frame
+            .encode()
+            .map_err(|e| ClientError::Protocol(format!("invalid chunk: {}", e)))?;
+
+        if frame.is_last_chunk {
+            info!("Sent last chunk of {}", &transfer.filename);
+        }
+
+        self.outbox.push_back(Message::Binary(bytes));
+
+        Ok(())
+    }
+
+    /// Decrypts and verifies a chunk, the file is handed out after the last one.
+    fn add_chunk(&mut self, frame: ChunkFrame) -> ClientResult<()> {
+        let request_id = frame.request_id.clone();
+        let transfer = match self.incoming.get_mut(&request_id) {
+            Some(transfer) => transfer,
+            None => return Ok(()),
+        };
+
+        // chunks sent again after a resume
+        if frame.chunk_nr <= transfer.received_chunk_nr() {
+            return Ok(());
+        }
+        if frame.chunk_nr != transfer.received_chunk_nr() + 1 {
+            return self.queue_cancel(&request_id, "Chunk is missing.");
+        }
+
+        let chunk = transfer.key.decrypt(&frame.iv, &frame.chunk)?;
+        let digest = crypto::chunk_digest(frame.chunk_nr, &chunk);
+
+        // chunk-digests is advertised, a sender that hashed the whole file sends the digest
+        // of every chunk; only chunks of a sender without a content hash go without one
+        let is_valid = match &frame.digest {
+            Some(expected) => transfer
+                .key
+                .decrypt_digest(expected)
+                .is_ok_and(|expected| expected == digest),
+            None => transfer.content_hash.is_none(),
+        };
+        let chunk = String::from_utf8(chunk).ok().filter(|_| is_valid);
+        let chunk = match chunk {
+            Some(chunk) => chunk,
+            None => return self.fail_integrity(&request_id),
+        };
+
+        transfer.digests.push(digest);
+        transfer.payload.push_str(&chunk);
+
+        self.queue(WsCommand::ReceivedChunk(ReqReceivedChunk {
+            request_id: request_id.clone(),
+            chunk_nr: frame.chunk_nr,
+            last_chunk_nr: None,
+        }))?;
+
+        if frame.is_last_chunk {
+            self.finish(&request_id)?;
+        }
+
+        Ok(())
+    }
+
+    fn finish(&mut self, request_id: &str) -> ClientResult<()> {
+        let transfer = match self.incoming.remove(request_id) {
+            Some(transfer) => transfer,
+            None => return Ok(()),
+        };
+
+        // a content hash that cannot be read fails the check, it is not skipped
+        if let Some(content_hash) = &transfer.content_hash {
+            let is_valid = transfer
+                .key
+                .decrypt_content_hash(content_hash)
+                .is_ok_and(|expected| expected == crypto::content_hash(&transfer.digests));
+            if !is_valid {
+                return self.fail_integrity(request_id);
+            }
+        }
+
+        let data = payload::decode(&transfer.payload)?;
+        self.events.push_back(Event::FileReceived {
+            request_id: request_id.to_string(),
+            filename: transfer.filename,
+            data,
+        });
+
+        Ok(())
+    }
+
+    fn fail_integrity(&mut self, request_id: &str) -> ClientResult<()> {
+        let reason = "Integrity check failed.";
+        self.queue_cancel(request_id, reason)?;
+        self.events.push_back(Event::Cancelled {
+            request_id: request_id.to_string(),
+            reason: reason.to_string(),
+        });
+
+        Ok(())
+    }
+}
+
+/// Id of the `request-file` command of a file.
+fn request_file_id(filename: &str) -> String {
+    format!("{}{}", REQUEST_FILE_ID_PREFIX, filename)
+}
+
+fn capabilities() -> Vec<String> {
+    CAPABILITIES.iter().map(|c| c.to_string()).collect()
+}
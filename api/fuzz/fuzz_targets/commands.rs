@@ -0,0 +1,544 @@
+//! Runs sequences of commands of a host and two guests against the transmittor in-process.
+//!
+//! Invariants:
+//! - nothing panics, neither the handlers nor the listeners
+//! - every chunk handed to a user was added to the same request, no chunk leaks across requests
+//! - users only receive messages of requests they take part in
+//! - commands on a request the user does not take part in fail
+
+#![no_main]
+
+use std::collections::{HashMap, HashSet};
+use std::net::SocketAddr;
+
+use arbitrary::Arbitrary;
+use axum::body::Body;
+use axum::extract::{ws::Message, ConnectInfo};
+use axum::Router;
+use http::Request;
+use libfuzzer_sys::fuzz_target;
+use serde_json::{json, Value};
+use tower::ServiceExt;
+
+use utils::chunk_frame::{ChunkFrame, IV_LEN};
+use utils::keys;
+use utils::store::{memory_store::MemoryStore, DynStore};
+use utils::transmittor::fuzzing::FuzzConnection;
+
+/// Files of the session and the user sharing them.
+const FILES: &[(&str, usize)] = &[("notes.txt", 0), ("photo.jpg", 1)];
+const USERS: usize = 3;
+/// Yields that let the listeners react to an action.
+const SETTLE_YIELDS: usize = 64;
+
+#[derive(Arbitrary, Debug)]
+struct Input {
+    capabilities: [Capabilities; USERS],
+    actions: Vec<Action>,
+}
+
+#[derive(Arbitrary, Debug, Clone, Copy)]
+struct Capabilities {
+    binary_chunks: bool,
+    chunk_digests: bool,
+    broadcast: bool,
+}
+
+/// Users, requests and files are picked by index, modulo the ones there are.
+#[derive(Arbitrary, Debug)]
+enum Action {
+    RequestFile {
+        user: u8,
+        file: u8,
+    },
+    Acknowledge {
+        user: u8,
+        request: u8,
+        file: u8,
+        amount_of_chunks: u8,
+        window_size: Option<u8>,
+    },
+    Ready {
+        user: u8,
+        request: u8,
+    },
+    AddChunk {
+        user: u8,
+        request: u8,
+        chunk_nr: u8,
+        is_last_chunk: bool,
+        chunk: Vec<u8>,
+        binary: bool,
+    },
+    ReceivedChunk {
+        user: u8,
+        request: u8,
+        chunk_nr: u8,
+    },
+    Resume {
+        user: u8,
+        request: u8,
+    },
+    Cancel {
+        user: u8,
+        request: u8,
+    },
+    Reconnect {
+        user: u8,
+    },
+    Text {
+        user: u8,
+        text: String,
+    },
+    Binary {
+        user: u8,
+        bytes: Vec<u8>,
+    },
+}
+
+fuzz_target!(
+    init: {
+        std::env::set_var("JWT_KEY", "fuzzing");
+        std::env::set_var("RATE_LIMIT", "off");
+    },
+    |input: Input| {
+        let runtime = tokio::runtime::Builder::new_current_thread()
+            .enable_all()
+            .build()
+            .unwrap();
+
+        runtime.block_on(async {
+            let mut session = Session::start(input.capabilities).await;
+            for action in input.actions {
+                session.run(action).await;
+            }
+            session.close().await;
+        });
+    }
+);
+
+struct Session {
+    store: DynStore,
+    session_id: String,
+    jwts: Vec<String>,
+    user_ids: Vec<String>,
+    capabilities: [Capabilities; USERS],
+    connections: Vec<Option<FuzzConnection>>,
+    /// Request ids in the order they were seen.
+    requests: Vec<String>,
+    /// Users that took part in a request at some point.
+    participants: HashMap<String, HashSet<String>>,
+    /// Chunks added to a request by anyone.
+    chunks: HashMap<String, HashSet<Vec<u8>>>,
+    next_id: usize,
+}
+
+impl Session {
+    /// Creates the session over the api, the guests join with the access code.
+    async fn start(capabilities: [Capabilities; USERS]) -> Self {
+        let memory_store = MemoryStore::new();
+        let store: DynStore = memory_store;
+        tokio::spawn(utils::transmittor::dispatch_events(store.clone()));
+
+        let api = utils::api::router(store.clone());
+
+        let created = call(&api, "POST", "/session", None, None).await;
+        let session_id = created["sessionId"].as_str().unwrap().to_string();
+        let code = created["accessCode"].as_str().unwrap();
+
+        let mut jwts = vec![created["jwt"].as_str().unwrap().to_string()];
+        for _ in 1..USERS {
+            let path = format!("/access/{}", session_id);
+            let joined = call(&api, "GET", &path, Some(sha256::digest(code)), None).await;
+            jwts.push(joined["jwt"].as_str().unwrap().to_string());
+        }
+
+        for (filename, owner) in FILES {
+            let path = format!("/files/{}", session_id);
+            let authorization = format!("Bearer {}", jwts[*owner]);
+            let files = json!([{ "name": filename, "size": 100 }]);
+            call(&api, "POST", &path, Some(authorization), Some(files)).await;
+        }
+
+        let mut session = Session {
+            store,
+            session_id,
+            user_ids: Vec::new(),
+            jwts,
+            capabilities,
+            connections: Vec::new(),
+            requests: Vec::new(),
+            participants: HashMap::new(),
+            chunks: HashMap::new(),
+            next_id: 0,
+        };
+
+        for user in 0..USERS {
+            let connection = session.connect(user).await;
+            session.user_ids.push(connection.user_id().to_string());
+            session.connections.push(Some(connection));
+        }
+        session.settle().await;
+        session.check_messages().await;
+
+        session
+    }
+
+    async fn run(&mut self, action: Action) {
+        match action {
+            Action::RequestFile { user, file } => {
+                let (filename, _) = FILES[file as usize % FILES.len()];
+                let data = json!({ "public_key": "public-key", "filename": filename });
+                self.command(user, "request-file", data, None).await;
+            }
+            Action::Acknowledge {
+                user,
+                request,
+                file,
+                amount_of_chunks,
+                window_size,
+            } => {
+                let (filename, _) = FILES[file as usize % FILES.len()];
+                let request_id = self.request_id(request);
+                let data = json!({
+                    "request_id": request_id,
+                    "public_key": "public-key",
+                    "amount_of_chunks": amount_of_chunks,
+                    "window_size": window_size,
+                    "filename": filename,
+                });
+                self.command(user, "acknowledge-file-request", data, Some(request_id))
+                    .await;
+            }
+            Action::Ready { user, request } => {
+                let request_id = self.request_id(request);
+                let data = json!({ "request_id": request_id });
+                self.command(user, "ready-for-file-transfer", data, Some(request_id))
+                    .await;
+            }
+            Action::AddChunk {
+                user,
+                request,
+                chunk_nr,
+                is_last_chunk,
+                chunk,
+                binary,
+            } => {
+                let request_id = self.request_id(request);
+                self.chunks
+                    .entry(request_id.clone())
+                    .or_default()
+                    .insert(chunk.clone());
+
+                if binary {
+                    let frame = ChunkFrame {
+                        request_id: request_id.clone(),
+                        chunk_nr: chunk_nr as u32,
+                        iv: [0; IV_LEN],
+                        is_last_chunk,
+                        digest: None,
+                        chunk,
+                    };
+                    self.binary_chunk(user, frame).await;
+                } else {
+                    let data = json!({
+                        "request_id": request_id,
+                        "chunk_nr": chunk_nr,
+                        "is_last_chunk": is_last_chunk,
+                        "chunk": hex::encode(&chunk),
+                        "iv": "AAAAAAAAAAAAAAAA",
+                    });
+                    self.command(user, "add-chunk", data, Some(request_id))
+                        .await;
+                }
+            }
+            Action::ReceivedChunk {
+                user,
+                request,
+                chunk_nr,
+            } => {
+                let request_id = self.request_id(request);
+                let data = json!({ "request_id": request_id, "chunk_nr": chunk_nr });
+                self.command(user, "received-chunk", data, Some(request_id))
+                    .await;
+            }
+            Action::Resume { user, request } => {
+                let request_id = self.request_id(request);
+                let data = json!({ "request_id": request_id });
+                self.command(user, "resume-transfer", data, Some(request_id))
+                    .await;
+            }
+            Action::Cancel { user, request } => {
+                let request_id = self.request_id(request);
+                let data = json!({ "request_id": request_id, "reason": "fuzzing" });
+                self.command(user, "cancel-transfer", data, Some(request_id))
+                    .await;
+            }
+            Action::Reconnect { user } => {
+                let user = user as usize % USERS;
+                if let Some(connection) = self.connections[user].take() {
+                    connection.close();
+                }
+                self.settle().await;
+                self.check_messages().await;
+
+                let connection = self.connect(user).await;
+                self.connections[user] = Some(connection);
+            }
+            Action::Text { user, text } => {
+                let user = user as usize % USERS;
+                let store = self.store.clone();
+                self.connection(user).text(store, &text).await;
+            }
+            Action::Binary { user, bytes } => {
+                let user = user as usize % USERS;
+                let store = self.store.clone();
+                self.connection(user).binary(store, &bytes).await;
+            }
+        }
+
+        self.settle().await;
+        self.check_messages().await;
+    }
+
+    async fn close(mut self) {
+        for connection in self.connections.iter_mut() {
+            if let Some(connection) = connection.take() {
+                connection.close();
+            }
+        }
+        self.settle().await;
+    }
+
+    async fn connect(&mut self, user: usize) -> FuzzConnection {
+        let mut connection = FuzzConnection::open(&self.session_id, &self.jwts[user]).unwrap();
+
+        let capabilities = self.capabilities[user];
+        let names = [
+            ("binary-chunks", capabilities.binary_chunks),
+            ("chunk-digests", capabilities.chunk_digests),
+            ("broadcast", capabilities.broadcast),
+        ]
+        .iter()
+        .filter(|(_, enabled)| *enabled)
+        .map(|(name, _)| name.to_string())
+        .collect::<Vec<String>>();
+
+        let register = json!({
+            "command": "register",
+            "data": { "version": 1, "capabilities": names },
+        });
+        connection
+            .text(self.store.clone(), &register.to_string())
+            .await;
+
+        connection
+    }
+
+    fn connection(&mut self, user: usize) -> &mut FuzzConnection {
+        self.connections[user].as_mut().unwrap()
+    }
+
+    /// A request seen so far, or one that does not exist.
+    fn request_id(&self, request: u8) -> String {
+        if self.requests.is_empty() {
+            return "00000000-0000-0000-0000-000000000000".to_string();
+        }
+
+        self.requests[request as usize % self.requests.len()].clone()
+    }
+
+    /// Sends a command, it has to fail if the user does not take part in the request.
+    async fn command(&mut self, user: u8, command: &str, data: Value, request_id: Option<String>) {
+        let user = user as usize % USERS;
+
+        let takes_part = match &request_id {
+            Some(request_id) => self.takes_part(user, request_id).await,
+            None => true,
+        };
+
+        self.next_id += 1;
+        let id = format!("fuzz-{}", self.next_id);
+        let message = json!({ "id": id, "command": command, "data": data });
+
+        let store = self.store.clone();
+        self.connection(user)
+            .text(store, &message.to_string())
+            .await;
+
+        if !takes_part {
+            self.expect_error(user, |reply| reply["data"]["id"] == id.as_str())
+                .await;
+        }
+    }
+
+    async fn binary_chunk(&mut self, user: u8, frame: ChunkFrame) {
+        let user = user as usize % USERS;
+        let takes_part = self.takes_part(user, &frame.request_id).await;
+
+        let bytes = match frame.encode() {
+            Ok(bytes) => bytes,
+            Err(_) => return,
+        };
+
+        let store = self.store.clone();
+        self.connection(user).binary(store, &bytes).await;
+
+        // binary chunks are only answered on failure
+        if !takes_part {
+            self.expect_error(user, |reply| reply["data"]["command"] == "add-chunk")
+                .await;
+        }
+    }
+
+    /// The reply is the next message of the user, sent before the listeners ran.
+    async fn expect_error(&mut self, user: usize, is_reply: impl Fn(&Value) -> bool) {
+        let messages = self.connection(user).received();
+        let replies = texts(&messages)
+            .into_iter()
+            .filter(|message| message["command"] == "ok" || message["command"] == "error")
+            .filter(|message| is_reply(message))
+            .collect::<Vec<Value>>();
+
+        assert_eq!(replies.len(), 1, "no reply: {:?}", messages);
+        assert_eq!(
+            replies[0]["command"], "error",
+            "command of a user not taking part in the request succeeded"
+        );
+
+        self.check(user, messages).await;
+    }
+
+    async fn settle(&self) {
+        for _ in 0..SETTLE_YIELDS {
+            tokio::task::yield_now().await;
+        }
+    }
+
+    /// Checks the messages every user received since the last check.
+    async fn check_messages(&mut self) {
+        self.discover_requests().await;
+
+        for user in 0..USERS {
+            let messages = match self.connections[user].as_mut() {
+                Some(connection) => connection.received(),
+                None => continue,
+            };
+            self.check(user, messages).await;
+        }
+    }
+
+    async fn check(&mut self, user: usize, messages: Vec<Message>) {
+        for message in messages {
+            let (request_id, chunk) = match message {
+                Message::Text(text) => {
+                    let message: Value = serde_json::from_str(&text).unwrap();
+                    let command = message["command"].as_str().unwrap_or_default();
+                    if matches!(command, "ok" | "error" | "hello") {
+                        continue;
+                    }
+
+                    let request_id = message["request_id"].as_str().unwrap_or_default();
+                    if request_id.is_empty() {
+                        continue;
+                    }
+
+                    let chunk = match command {
+                        "add-chunk" => {
+                            let chunk = message["data"]["chunk"].as_str().unwrap();
+                            Some(hex::decode(chunk).unwrap())
+                        }
+                        _ => None,
+                    };
+
+                    (request_id.to_string(), chunk)
+                }
+                Message::Binary(bytes) => {
+                    let frame = ChunkFrame::decode(&bytes).unwrap();
+                    (frame.request_id, Some(frame.chunk))
+                }
+                _ => continue,
+            };
+
+            if !self.requests.contains(&request_id) {
+                self.requests.push(request_id.clone());
+            }
+
+            assert!(
+                self.takes_part(user, &request_id).await,
+                "user received a message of a request it does not take part in"
+            );
+
+            if let Some(chunk) = chunk {
+                let added = self
+                    .chunks
+                    .get(&request_id)
+                    .is_some_and(|chunks| chunks.contains(&chunk));
+                assert!(added, "chunk was not added to the request");
+            }
+        }
+    }
+
+    /// Requests are known before their first message, from the transfers of the session.
+    async fn discover_requests(&mut self) {
+        let key = keys::transfers(&self.session_id);
+        for request_id in self.store.smembers(&key).await.unwrap_or_default() {
+            if !self.requests.contains(&request_id) {
+                self.requests.push(request_id);
+            }
+        }
+    }
+
+    async fn takes_part(&mut self, user: usize, request_id: &str) -> bool {
+        let key = keys::file_req_users(request_id);
+        let users = self.store.smembers(&key).await.unwrap_or_default();
+
+        let participants = self.participants.entry(request_id.to_string()).or_default();
+        participants.extend(users);
+
+        participants.contains(&self.user_ids[user])
+    }
+}
+
+fn texts(messages: &[Message]) -> Vec<Value> {
+    messages
+        .iter()
+        .filter_map(|message| match message {
+            Message::Text(text) => serde_json::from_str(text).ok(),
+            _ => None,
+        })
+        .collect()
+}
+
+/// Calls a route of the api, returns the `response` of the envelope.
+async fn call(
+    api: &Router,
+    method: &str,
+    path: &str,
+    authorization: Option<String>,
+    body: Option<Value>,
+) -> Value {
+    let mut request = Request::builder()
+        .method(method)
+        .uri(path)
+        .header("content-type", "application/json")
+        .extension(ConnectInfo(SocketAddr::from(([127, 0, 0, 1], 7878))));
+    if let Some(authorization) = authorization {
+        request = request.header("authorization", authorization);
+    }
+    let body = body.map(|body| body.to_string()).unwrap_or_default();
+
+    let response = api
+        .clone()
+        .oneshot(request.body(Body::from(body)).unwrap())
+        .await
+        .unwrap();
+    let bytes = axum::body::to_bytes(response.into_body(), usize::MAX)
+        .await
+        .unwrap();
+
+    let envelope: Value = serde_json::from_slice(&bytes).unwrap();
+    assert_eq!(envelope["success"], true, "{}", envelope);
+
+    envelope["response"].clone()
+}
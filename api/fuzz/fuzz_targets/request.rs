@@ -0,0 +1,100 @@
+//! Parses attacker controlled input like the transmittor and the api do.
+//!
+//! - text frames: `WsRequest::parse`, parsed requests serialize and parse again
+//! - binary frames: `ChunkFrame::decode`, decoded frames encode and decode to the same frame
+//! - chunks sent as text: `ChunkFrame::from_text`
+//! - headers: `get_header` and `decode_jwt`
+
+#![no_main]
+
+use arbitrary::Arbitrary;
+use http::{HeaderMap, HeaderValue};
+use libfuzzer_sys::fuzz_target;
+
+use utils::chunk_frame::ChunkFrame;
+use utils::protocol::WsRequest;
+
+#[derive(Arbitrary, Debug)]
+enum Input<'a> {
+    Text(&'a str),
+    Binary(&'a [u8]),
+    TextChunk {
+        request_id: &'a str,
+        chunk_nr: u32,
+        is_last_chunk: bool,
+        chunk: &'a str,
+        iv: &'a str,
+        digest: Option<&'a str>,
+    },
+    Header(&'a [u8]),
+}
+
+fuzz_target!(|input: Input| match input {
+    Input::Text(text) => text_frame(text),
+    Input::Binary(bytes) => binary_frame(bytes),
+    Input::TextChunk {
+        request_id,
+        chunk_nr,
+        is_last_chunk,
+        chunk,
+        iv,
+        digest,
+    } => {
+        if let Ok(frame) =
+            ChunkFrame::from_text(request_id, chunk_nr, is_last_chunk, chunk, iv, digest)
+        {
+            assert_eq!(frame.chunk_to_hex(), chunk.to_lowercase());
+            if let Ok(bytes) = frame.encode() {
+                binary_frame(&bytes);
+            }
+        }
+    }
+    Input::Header(bytes) => header(bytes),
+});
+
+fn text_frame(text: &str) {
+    let request = match WsRequest::parse(text) {
+        Ok(request) => request,
+        Err(_) => return,
+    };
+
+    let serialized = serde_json::to_string(&request).expect("parsed request serializes");
+    let reparsed = WsRequest::parse(&serialized).expect("serialized request parses");
+    assert_eq!(
+        serde_json::to_string(&reparsed).unwrap(),
+        serialized,
+        "request changed after a round trip"
+    );
+}
+
+fn binary_frame(bytes: &[u8]) {
+    let frame = match ChunkFrame::decode(bytes) {
+        Ok(frame) => frame,
+        Err(_) => return,
+    };
+
+    let encoded = frame.encode().expect("decoded frame encodes");
+    let decoded = ChunkFrame::decode(&encoded).expect("encoded frame decodes");
+
+    assert_eq!(decoded.request_id, frame.request_id);
+    assert_eq!(decoded.chunk_nr, frame.chunk_nr);
+    assert_eq!(decoded.iv, frame.iv);
+    assert_eq!(decoded.is_last_chunk, frame.is_last_chunk);
+    assert_eq!(decoded.digest, frame.digest);
+    assert_eq!(decoded.chunk, frame.chunk);
+}
+
+fn header(bytes: &[u8]) {
+    let value = match HeaderValue::from_bytes(bytes) {
+        Ok(value) => value,
+        Err(_) => return,
+    };
+
+    let mut headers = HeaderMap::new();
+    headers.insert("authorization", value);
+
+    if let Ok(authorization) = utils::get_header(&headers, "authorization") {
+        let jwt = authorization.split(' ').last().unwrap_or("");
+        assert!(utils::decode_jwt(jwt).is_err(), "forged jwt accepted");
+    }
+}
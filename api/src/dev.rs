@@ -0,0 +1,35 @@
+use tokio::net::TcpListener;
+
+use env_logger::Env;
+use log::info;
+
+use utils::store::{memory_store::MemoryStore, DynStore};
+
+/// Runs the api and the transmittor in a single process, keeping all state in memory.
+#[tokio::main]
+async fn main() {
+    env_logger::Builder::from_env(Env::default().default_filter_or("info")).init();
+
+    dotenvy::dotenv().ok();
+
+    let store: DynStore = MemoryStore::new();
+    info!("Using in-memory store");
+
+    tokio::spawn(utils::transmittor::dispatch_events(store.clone()));
+
+    let api_listener = TcpListener::bind("0.0.0.0:7878").await.unwrap();
+    info!("API listening on: {}", api_listener.local_addr().unwrap());
+
+    let transmittor_listener = TcpListener::bind("0.0.0.0:7879").await.unwrap();
+    info!(
+        "Transmittor listening on: {}",
+        transmittor_listener.local_addr().unwrap()
+    );
+
+    let api = utils::serve(api_listener, utils::api::router(store.clone()));
+    let transmittor = utils::serve(transmittor_listener, utils::transmittor::router(store));
+
+    let (api, transmittor) = tokio::join!(api, transmittor);
+    api.expect("Error serving api");
+    transmittor.expect("Error serving transmittor");
+}
@@ -0,0 +1,628 @@
+use axum::{
+    extract::{Path, State},
+    http::{HeaderMap, StatusCode},
+    routing::{get, post},
+    Json, Router,
+};
+
+use tower_http::cors::{Any, CorsLayer};
+
+use serde::{Deserialize, Serialize};
+use serde_json::json;
+
+use log::info;
+
+use crate::client_ip::ClientIp;
+use crate::error::AppError;
+use crate::keys;
+use crate::rate_limit::{Limit, RateLimitConfig, RateLimitLayer};
+use crate::redis_handler::Transaction;
+use crate::store::DynStore;
+
+/// Attempts to find a free session name before giving up.
+const CREATE_SESSION_ATTEMPTS: usize = 3;
+
+/// Routes of the API, served with `into_make_service_with_connect_info::<SocketAddr>`.
+/// Limits of the routes that are more expensive or guessable than the rest.
+const ROUTE_LIMITS: &[(&str, Limit)] = &[
+    ("POST /session", Limit::new(10, 60)),
+    ("GET /access/:session_id", Limit::new(20, 60)),
+];
+
+pub fn router(store: DynStore) -> Router {
+    let cors = CorsLayer::new()
+        .allow_origin(Any)
+        .allow_methods(Any)
+        .allow_headers(Any)
+        .expose_headers(Any);
+
+    Router::new()
+        .route("/", get(ping))
+        .route("/session", get(get_session).post(create_session))
+        .route("/idForName/:session_name", get(get_id_for_session_name))
+        .route("/access/:session_id", get(join_session))
+        .route(
+            "/session/:session_id",
+            get(get_session_metadata)
+                .put(update_session)
+                .delete(delete_session),
+        )
+        .route("/session/:session_id/refresh", post(refresh_session))
+        .route(
+            "/files/:session_id",
+            get(get_all_file_metadata_in_session).post(add_files),
+        )
+        .route(
+            "/files/:session_id/:file_name",
+            get(get_file_metadata).delete(delete_file),
+        )
+        .route_layer(RateLimitLayer::new(
+            store.clone(),
+            RateLimitConfig::from_env(ROUTE_LIMITS),
+        ))
+        .with_state(store)
+        .layer(cors)
+}
+
+async fn ping() -> Result<(StatusCode, String), AppError> {
+    let timestamp = crate::get_current_timestamp();
+
+    Ok((
+        StatusCode::OK,
+        json!({
+            "success": true,
+            "response": timestamp
+        })
+        .to_string(),
+    ))
+}
+
+async fn get_session(
+    rcm: State<DynStore>,
+    headers: HeaderMap,
+) -> Result<(StatusCode, String), AppError> {
+    let claims = crate::decode_jwt_from_header(&headers)?;
+    let session_id = claims.aud;
+
+    crate::check_session_exists(rcm.clone(), &session_id).await?;
+    crate::check_user_is_host(&headers, &session_id)?;
+
+    let key = keys::session(&session_id);
+    let session_name = crate::redis_handler::hget(rcm.clone(), &key, "name").await?;
+
+    let code = crate::get_random_six_digit_code();
+    let encrypted_code = crate::sha256(&code);
+
+    let items = [
+        ("name", session_name.as_str()),
+        ("code", encrypted_code.as_str()),
+    ];
+    crate::redis_handler::hset_multiple(rcm.clone(), &key, &items, None).await?;
+
+    let key = keys::session_name(&session_name);
+    crate::redis_handler::set(rcm, &key, &session_id, None).await?;
+
+    Ok((
+        StatusCode::ACCEPTED,
+        json!({
+            "success": true,
+            "response": {
+                "sessionName": session_name,
+                "sessionId": session_id,
+                "accessCode": code
+            }
+        })
+        .to_string(),
+    ))
+}
+
+async fn create_session(
+    rcm: State<DynStore>,
+    client_ip: ClientIp,
+) -> Result<(StatusCode, String), AppError> {
+    let session_id = crate::get_uuid();
+    let user_id = crate::get_uuid();
+    let jwt = crate::create_jwt(&session_id, Some(&user_id))?;
+
+    let code = crate::get_random_six_digit_code();
+    let encrypted_code = crate::sha256(&code);
+
+    // the IP, the name and the session are stored in slots of their own,
+    // the IP and the name are claimed one after the other
+    let created_key = keys::created_sessions(&client_ip.0.to_string());
+    if !crate::redis_handler::set_nx(rcm.clone(), &created_key, &session_id, None).await? {
+        return Err(AppError::Conflict(
+            "you have already created a session".to_string(),
+        ));
+    }
+
+    // the name might be taken in the meantime,
+    // the session exists before its name links to it
+    let key = keys::session(&session_id);
+    let mut session_name = String::new();
+    for _ in 0..CREATE_SESSION_ATTEMPTS {
+        session_name = crate::get_random_dragon_name(rcm.clone()).await?;
+
+        let items = [("name", session_name.as_str()), ("code", &encrypted_code)];
+        crate::redis_handler::hset_multiple(rcm.clone(), &key, &items, None).await?;
+
+        let name_key = keys::session_name(&session_name);
+        if crate::redis_handler::set_nx(rcm.clone(), &name_key, &session_id, None).await? {
+            break;
+        }
+
+        session_name.clear();
+    }
+
+    if session_name.is_empty() {
+        crate::redis_handler::del(rcm.clone(), &key).await?;
+        crate::redis_handler::del(rcm, &created_key).await?;
+
+        return Err(AppError::Conflict(
+            "failed to find a free session name".to_string(),
+        ));
+    }
+
+    Ok((
+        StatusCode::CREATED,
+        json!({
+            "success": true,
+            "response": {
+                "sessionName": session_name,
+                "sessionId": session_id,
+                "accessCode": code,
+                "jwt": jwt
+            }
+        })
+        .to_string(),
+    ))
+}
+
+async fn get_id_for_session_name(
+    rcm: State<DynStore>,
+    Path(session_name): Path<String>,
+) -> Result<(StatusCode, String), AppError> {
+    let key = keys::session_name(&session_name);
+
+    if !crate::redis_handler::exists(rcm.clone(), &key).await? {
+        return Err(AppError::NotFound("session name not found".to_string()));
+    }
+
+    let session_id = crate::redis_handler::get(rcm, &key).await?;
+
+    Ok((
+        StatusCode::OK,
+        json!({
+            "success": true,
+            "response": {
+                "sessionId": session_id,
+            }
+        })
+        .to_string(),
+    ))
+}
+
+async fn get_session_metadata(
+    rcm: State<DynStore>,
+    Path(session_id): Path<String>,
+) -> Result<(StatusCode, String), AppError> {
+    crate::check_session_exists(rcm.clone(), &session_id).await?;
+
+    let key = keys::session(&session_id);
+    let session_name = crate::redis_handler::hget(rcm.clone(), &key, "name").await?;
+
+    Ok((
+        StatusCode::OK,
+        json!({
+            "success": true,
+            "response": {
+                "sessionName": session_name
+            }
+        })
+        .to_string(),
+    ))
+}
+
+async fn join_session(
+    rcm: State<DynStore>,
+    client_ip: ClientIp,
+    headers: HeaderMap,
+    Path(session_id): Path<String>,
+) -> Result<(StatusCode, String), AppError> {
+    info!("Testing");
+    crate::check_session_exists(rcm.clone(), &session_id).await?;
+    let key = keys::access_attempts(&session_id, &client_ip.0.to_string());
+    if crate::redis_handler::get(rcm.clone(), &key).await? == "5" {
+        return Err(AppError::RateLimited("too many attempts".to_string()));
+    }
+
+    let encrypted_code = crate::get_header(&headers, "authorization")?;
+
+    let key = keys::session(&session_id);
+    let code = crate::redis_handler::hget(rcm.clone(), &key, "code").await?;
+
+    if encrypted_code != code {
+        let key = keys::access_attempts(&session_id, &client_ip.0.to_string());
+        crate::redis_handler::incr(rcm, &key, Some(10)).await?;
+
+        return Err(AppError::Unauthorized("invalid access code".to_string()));
+    }
+
+    let jwt = crate::create_jwt(&session_id, None)?;
+
+    Ok((
+        StatusCode::OK,
+        json!({
+            "success": true,
+            "response": {
+                "jwt": jwt
+            }
+        })
+        .to_string(),
+    ))
+}
+
+#[derive(Deserialize)]
+struct SessionNameBody {
+    name: String,
+}
+
+async fn update_session(
+    rcm: State<DynStore>,
+    headers: HeaderMap,
+    Path(session_id): Path<String>,
+    Json(session_name_body): Json<SessionNameBody>,
+) -> Result<(StatusCode, String), AppError> {
+    crate::check_session_exists(rcm.clone(), &session_id).await?;
+
+    crate::check_user_is_host(&headers, &session_id)?;
+
+    let key = keys::session(&session_id);
+    let old_session_name = crate::redis_handler::hget(rcm.clone(), &key, "name").await?;
+
+    let code = crate::get_random_six_digit_code();
+    let encrypted_code = crate::sha256(&code);
+
+    let new_name = session_name_body.name;
+    let items = [
+        ("name", new_name.as_str()),
+        ("code", encrypted_code.as_str()),
+    ];
+    let old_name_key = keys::session_name(&old_session_name);
+    let new_name_key = keys::session_name(&new_name);
+
+    let mut transaction = Transaction::new();
+    transaction
+        .require_hash_value(&key, "name", Some(&old_session_name))
+        .hset_multiple(&key, &items, None);
+
+    if !crate::redis_handler::exec_atomic(rcm.clone(), transaction).await? {
+        return Err(AppError::Conflict(
+            "session changed in the meantime".to_string(),
+        ));
+    }
+
+    // the names are stored in slots of their own, they follow the session
+    crate::redis_handler::del(rcm.clone(), &old_name_key).await?;
+    crate::redis_handler::set(rcm.clone(), &new_name_key, &session_id, None).await?;
+
+    crate::prolong_session(rcm, &session_id).await;
+
+    Ok((
+        StatusCode::OK,
+        json!({
+            "success": true,
+            "response": {
+                "accessCode": code
+            }
+        })
+        .to_string(),
+    ))
+}
+
+/// Keeps the session alive and hands out a new JWT, before the current one expires.
+async fn refresh_session(
+    rcm: State<DynStore>,
+    headers: HeaderMap,
+    Path(session_id): Path<String>,
+) -> Result<(StatusCode, String), AppError> {
+    crate::check_session_exists(rcm.clone(), &session_id).await?;
+
+    let user = crate::check_user_is_in_session(&headers, &session_id)?;
+    let jwt = crate::renew_jwt(&session_id, &user)?;
+
+    crate::prolong_session(rcm, &session_id).await;
+
+    Ok((
+        StatusCode::OK,
+        json!({
+            "success": true,
+            "response": {
+                "jwt": jwt
+            }
+        })
+        .to_string(),
+    ))
+}
+
+async fn delete_session(
+    rcm: State<DynStore>,
+    client_ip: ClientIp,
+    headers: HeaderMap,
+    Path(session_id): Path<String>,
+) -> Result<(StatusCode, String), AppError> {
+    crate::check_session_exists(rcm.clone(), &session_id).await?;
+
+    crate::check_user_is_host(&headers, &session_id)?;
+
+    let key = keys::session(&session_id);
+    let session_name = crate::redis_handler::hget(rcm.clone(), &key, "name").await?;
+
+    let mut transaction = Transaction::new();
+    transaction.require_exists(&key).del(&key);
+
+    let key = keys::files(&session_id);
+    let files = crate::redis_handler::smembers(rcm.clone(), &key).await?;
+    for file in files {
+        transaction.del(&keys::file(&session_id, &file));
+    }
+    transaction.del(&key);
+
+    if !crate::redis_handler::exec_atomic(rcm.clone(), transaction).await? {
+        return Err(AppError::NotFound("session id not found".to_string()));
+    }
+
+    // the IP and the name are stored in slots of their own
+    crate::redis_handler::del(
+        rcm.clone(),
+        &keys::created_sessions(&client_ip.0.to_string()),
+    )
+    .await?;
+    crate::redis_handler::del(rcm, &keys::session_name(&session_name)).await?;
+
+    Ok((
+        StatusCode::OK,
+        json!({
+            "success": true,
+            "response": "successfully deleted session"
+        })
+        .to_string(),
+    ))
+}
+
+#[derive(Serialize)]
+struct FileMetadataResponse {
+    name: String,
+    size: u64,
+    is_owner: bool,
+}
+
+async fn get_all_file_metadata_in_session(
+    rcm: State<DynStore>,
+    headers: HeaderMap,
+    Path(session_id): Path<String>,
+) -> Result<(StatusCode, String), AppError> {
+    crate::check_session_exists(rcm.clone(), &session_id).await?;
+
+    let user = crate::check_user_is_in_session(&headers, &session_id)?;
+
+    let mut files: Vec<FileMetadataResponse> = Vec::new();
+
+    let key = keys::files(&session_id);
+    let file_names = crate::redis_handler::smembers(rcm.clone(), &key).await?;
+
+    let keys = file_names
+        .iter()
+        .map(|file_name| keys::file(&session_id, file_name))
+        .collect::<Vec<String>>();
+    let file_hashes = crate::redis_handler::hgetall_many(rcm.clone(), &keys).await?;
+
+    for file in file_hashes {
+        if file.len() != 6 {
+            continue;
+        }
+
+        let filename = match crate::get_hash_value(&file, "name") {
+            None => continue,
+            Some(name) => name,
+        };
+
+        let size = match crate::get_hash_value(&file, "size") {
+            None => continue,
+            Some(size) => size,
+        };
+
+        let owner_id = match crate::get_hash_value(&file, "owner.id") {
+            None => continue,
+            Some(id) => id,
+        };
+
+        let file = FileMetadataResponse {
+            name: filename,
+            size: size.parse().unwrap_or(0),
+            is_owner: owner_id == user.id,
+        };
+        files.push(file);
+    }
+
+    Ok((
+        StatusCode::OK,
+        json!({
+            "success": true,
+            "response": files
+        })
+        .to_string(),
+    ))
+}
+
+struct FileMetadata {
+    name: String,
+    size: u64,
+    owner_id: String,
+}
+
+#[derive(Deserialize)]
+struct FileMetadataBody {
+    name: String,
+    size: u64,
+}
+
+async fn add_files(
+    rcm: State<DynStore>,
+    headers: HeaderMap,
+    Path(session_id): Path<String>,
+    Json(files): Json<Vec<FileMetadataBody>>,
+) -> Result<(StatusCode, String), AppError> {
+    crate::check_session_exists(rcm.clone(), &session_id).await?;
+
+    let user = crate::check_user_is_in_session(&headers, &session_id)?;
+
+    let mut new_files: Vec<FileMetadata> = Vec::new();
+
+    let key = keys::files(&session_id);
+
+    for file in files {
+        if crate::redis_handler::sismember(rcm.clone(), &key, &file.name).await? {
+            return Err(AppError::invalid_field(
+                "name",
+                format!("file \"{}\" already exists", &file.name),
+            ));
+        }
+
+        new_files.push(FileMetadata {
+            name: file.name,
+            size: file.size,
+            owner_id: user.id.clone(),
+        });
+    }
+
+    if new_files.is_empty() {
+        return Err(AppError::bad_request("no files provided"));
+    }
+
+    let mut transaction = Transaction::new();
+    transaction.require_exists(&keys::session(&session_id));
+
+    let key = keys::files(&session_id);
+    for file in new_files {
+        let file_key = keys::file(&session_id, &file.name);
+        let file_size = file.size.to_string();
+        let items = [
+            ("name", file.name.as_str()),
+            ("size", file_size.as_str()),
+            ("owner.id", file.owner_id.as_str()),
+        ];
+        transaction
+            .require_not_member(&key, &file.name)
+            .hset_multiple(&file_key, &items, None)
+            .sadd(&key, &file.name, None);
+    }
+
+    if !crate::redis_handler::exec_atomic(rcm, transaction).await? {
+        return Err(AppError::Conflict(
+            "files changed in the meantime".to_string(),
+        ));
+    }
+
+    Ok((
+        StatusCode::OK,
+        json!({
+            "success": true,
+            "response": "successfully added files"
+        })
+        .to_string(),
+    ))
+}
+
+async fn get_file_metadata(
+    rcm: State<DynStore>,
+    headers: HeaderMap,
+    Path((session_id, file_name)): Path<(String, String)>,
+) -> Result<(StatusCode, String), AppError> {
+    crate::check_session_exists(rcm.clone(), &session_id).await?;
+
+    let user = crate::check_user_is_in_session(&headers, &session_id)?;
+
+    let key = keys::file(&session_id, &file_name);
+    if !crate::redis_handler::exists(rcm.clone(), &key).await? {
+        return Err(AppError::NotFound("file not found".to_string()));
+    }
+
+    let file_data = crate::redis_handler::hgetall(rcm, &key).await?;
+
+    if file_data.len() != 6 {
+        return Err(AppError::NotFound("file not found".to_string()));
+    }
+
+    let filename = match crate::get_hash_value(&file_data, "name") {
+        None => return Err(AppError::NotFound("file not found".to_string())),
+        Some(name) => name,
+    };
+
+    let size = match crate::get_hash_value(&file_data, "size") {
+        None => return Err(AppError::NotFound("file not found".to_string())),
+        Some(size) => size,
+    };
+
+    let owner_id = match crate::get_hash_value(&file_data, "owner.id") {
+        None => return Err(AppError::NotFound("file not found".to_string())),
+        Some(id) => id,
+    };
+
+    let file = FileMetadataResponse {
+        name: filename,
+        size: size.parse().unwrap_or(0),
+        is_owner: owner_id == user.id,
+    };
+
+    Ok((
+        StatusCode::OK,
+        json!({
+            "success": true,
+            "response": file
+        })
+        .to_string(),
+    ))
+}
+
+async fn delete_file(
+    rcm: State<DynStore>,
+    headers: HeaderMap,
+    Path((session_id, file_name)): Path<(String, String)>,
+) -> Result<(StatusCode, String), AppError> {
+    crate::check_session_exists(rcm.clone(), &session_id).await?;
+
+    let user = crate::check_user_is_in_session(&headers, &session_id)?;
+
+    let key = keys::file(&session_id, &file_name);
+    if !crate::redis_handler::exists(rcm.clone(), &key).await? {
+        return Err(AppError::NotFound("file not found".to_string()));
+    }
+
+    let user_id = crate::redis_handler::hget(rcm.clone(), &key, "owner.id").await?;
+
+    if user.id != user_id && !user.is_host {
+        return Err(AppError::Forbidden(
+            "you are not allowed to delete this file".to_string(),
+        ));
+    }
+
+    let mut transaction = Transaction::new();
+    transaction
+        .require_hash_value(&key, "owner.id", Some(&user_id))
+        .del(&key)
+        .srem(&keys::files(&session_id), &file_name);
+
+    if !crate::redis_handler::exec_atomic(rcm, transaction).await? {
+        return Err(AppError::NotFound("file not found".to_string()));
+    }
+
+    Ok((
+        StatusCode::OK,
+        json!({
+            "success": true,
+            "response": "successfully deleted file"
+        })
+        .to_string(),
+    ))
+}
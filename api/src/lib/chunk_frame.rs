@@ -0,0 +1,247 @@
+use base64::{engine::general_purpose::STANDARD as BASE64, Engine};
+use uuid::Uuid;
+
+use crate::error::AppError;
+
+const REQUEST_ID_LEN: usize = 16;
+const CHUNK_NR_LEN: usize = 4;
+pub const IV_LEN: usize = 12;
+const FLAGS_LEN: usize = 1;
+const DIGEST_LEN_LEN: usize = 2;
+
+pub const HEADER_LEN: usize = REQUEST_ID_LEN + CHUNK_NR_LEN + IV_LEN + FLAGS_LEN;
+
+/// Upper bound of an encrypted chunk digest, the server does not look into it.
+pub const MAX_DIGEST_LEN: usize = 256;
+
+const FLAG_IS_LAST_CHUNK: u8 = 0b0000_0001;
+const FLAG_HAS_DIGEST: u8 = 0b0000_0010;
+
+/// Binary representation of an `add-chunk` command.
+///
+/// | bytes   | content                                   |
+/// | ------- | ----------------------------------------- |
+/// | 0..16   | request id (UUID)                         |
+/// | 16..20  | chunk nr (u32, big endian)                |
+/// | 20..32  | IV                                        |
+/// | 32      | flags (bit 0: last chunk, bit 1: digest)  |
+/// | 33..35  | digest length (u16, big endian), if bit 1 |
+/// | ..      | encrypted digest, if bit 1                |
+/// | ..      | encrypted chunk                           |
+pub struct ChunkFrame {
+    pub request_id: String,
+    pub chunk_nr: u32,
+    pub iv: [u8; IV_LEN],
+    pub is_last_chunk: bool,
+    /// Encrypted digest of the plaintext chunk, opaque to the server.
+    pub digest: Option<Vec<u8>>,
+    pub chunk: Vec<u8>,
+}
+
+impl ChunkFrame {
+    /// Builds a frame from the text representation used by the web app,
+    /// where the chunk is hex encoded and the IV and digest are base64 encoded.
+    pub fn from_text(
+        request_id: &str,
+        chunk_nr: u32,
+        is_last_chunk: bool,
+        chunk: &str,
+        iv: &str,
+        digest: Option<&str>,
+    ) -> Result<Self, AppError> {
+        let iv = BASE64
+            .decode(iv)
+            .ok()
+            .and_then(|iv| <[u8; IV_LEN]>::try_from(iv).ok())
+            .ok_or(AppError::bad_request("Wrong data format."))?;
+
+        let digest = match digest {
+            Some(digest) => Some(
+                BASE64
+                    .decode(digest)
+                    .map_err(|_| AppError::bad_request("Wrong data format."))?,
+            ),
+            None => None,
+        };
+        check_digest_len(&digest)?;
+
+        let chunk = hex::decode(chunk).map_err(|_| AppError::bad_request("Wrong data format."))?;
+
+        Ok(ChunkFrame {
+            request_id: request_id.to_string(),
+            chunk_nr,
+            iv,
+            is_last_chunk,
+            digest,
+            chunk,
+        })
+    }
+
+    pub fn decode(bytes: &[u8]) -> Result<Self, AppError> {
+        if bytes.len() < HEADER_LEN {
+            return Err(AppError::bad_request("Wrong data format."));
+        }
+
+        let (request_id, rest) = bytes.split_at(REQUEST_ID_LEN);
+        let (chunk_nr, rest) = rest.split_at(CHUNK_NR_LEN);
+        let (iv, rest) = rest.split_at(IV_LEN);
+        let (flags, rest) = rest.split_at(FLAGS_LEN);
+
+        let (digest, chunk) = match flags[0] & FLAG_HAS_DIGEST != 0 {
+            true => {
+                if rest.len() < DIGEST_LEN_LEN {
+                    return Err(AppError::bad_request("Wrong data format."));
+                }
+                let (digest_len, rest) = rest.split_at(DIGEST_LEN_LEN);
+                let digest_len = u16::from_be_bytes([digest_len[0], digest_len[1]]) as usize;
+                if rest.len() < digest_len {
+                    return Err(AppError::bad_request("Wrong data format."));
+                }
+                let (digest, chunk) = rest.split_at(digest_len);
+                (Some(digest.to_vec()), chunk)
+            }
+            false => (None, rest),
+        };
+        check_digest_len(&digest)?;
+
+        let request_id = Uuid::from_slice(request_id)
+            .map_err(|_| AppError::bad_request("Wrong data format."))?;
+
+        let mut chunk_nr_bytes = [0u8; CHUNK_NR_LEN];
+        chunk_nr_bytes.copy_from_slice(chunk_nr);
+
+        let mut iv_bytes = [0u8; IV_LEN];
+        iv_bytes.copy_from_slice(iv);
+
+        Ok(ChunkFrame {
+            request_id: request_id.to_string(),
+            chunk_nr: u32::from_be_bytes(chunk_nr_bytes),
+            iv: iv_bytes,
+            is_last_chunk: flags[0] & FLAG_IS_LAST_CHUNK != 0,
+            digest,
+            chunk: chunk.to_vec(),
+        })
+    }
+
+    pub fn encode(&self) -> Result<Vec<u8>, AppError> {
+        let request_id = Uuid::parse_str(&self.request_id)
+            .map_err(|_| AppError::bad_request("Invalid request ID."))?;
+
+        let mut flags = 0u8;
+        if self.is_last_chunk {
+            flags |= FLAG_IS_LAST_CHUNK;
+        }
+        if self.digest.is_some() {
+            flags |= FLAG_HAS_DIGEST;
+        }
+
+        let mut bytes = Vec::with_capacity(HEADER_LEN + self.chunk.len());
+        bytes.extend_from_slice(request_id.as_bytes());
+        bytes.extend_from_slice(&self.chunk_nr.to_be_bytes());
+        bytes.extend_from_slice(&self.iv);
+        bytes.push(flags);
+        if let Some(digest) = &self.digest {
+            bytes.extend_from_slice(&(digest.len() as u16).to_be_bytes());
+            bytes.extend_from_slice(digest);
+        }
+        bytes.extend_from_slice(&self.chunk);
+
+        Ok(bytes)
+    }
+
+    pub fn chunk_to_hex(&self) -> String {
+        hex::encode(&self.chunk)
+    }
+
+    pub fn iv_to_base64(&self) -> String {
+        BASE64.encode(self.iv)
+    }
+
+    pub fn digest_to_base64(&self) -> Option<String> {
+        self.digest.as_ref().map(|digest| BASE64.encode(digest))
+    }
+}
+
+fn check_digest_len(digest: &Option<Vec<u8>>) -> Result<(), AppError> {
+    match digest {
+        Some(digest) if digest.len() > MAX_DIGEST_LEN => {
+            Err(AppError::invalid_field("digest", "Digest too big."))
+        }
+        _ => Ok(()),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const REQUEST_ID: &str = "67e55044-10b1-426f-9247-bb680e5fe0c8";
+
+    fn frame(digest: Option<Vec<u8>>) -> ChunkFrame {
+        ChunkFrame {
+            request_id: REQUEST_ID.to_string(),
+            chunk_nr: 258,
+            iv: [7; IV_LEN],
+            is_last_chunk: true,
+            digest,
+            chunk: vec![1, 2, 3],
+        }
+    }
+
+    fn assert_round_trip(frame: ChunkFrame) {
+        let decoded = ChunkFrame::decode(&frame.encode().unwrap()).unwrap();
+
+        assert_eq!(decoded.request_id, frame.request_id);
+        assert_eq!(decoded.chunk_nr, frame.chunk_nr);
+        assert_eq!(decoded.iv, frame.iv);
+        assert_eq!(decoded.is_last_chunk, frame.is_last_chunk);
+        assert_eq!(decoded.digest, frame.digest);
+        assert_eq!(decoded.chunk, frame.chunk);
+    }
+
+    #[test]
+    fn round_trip() {
+        assert_round_trip(frame(None));
+        assert_round_trip(frame(Some(vec![9; 60])));
+        assert_round_trip(frame(Some(Vec::new())));
+    }
+
+    #[test]
+    fn text_frame_round_trip() {
+        let frame = ChunkFrame::from_text(
+            REQUEST_ID,
+            258,
+            false,
+            "010203",
+            "AAECAwQFBgcICQoL",
+            Some("r5BvThY0urfDdvfljdFE"),
+        )
+        .unwrap();
+
+        assert_eq!(frame.chunk_to_hex(), "010203");
+        assert_eq!(frame.iv_to_base64(), "AAECAwQFBgcICQoL");
+        assert_eq!(
+            frame.digest_to_base64().as_deref(),
+            Some("r5BvThY0urfDdvfljdFE")
+        );
+        assert_round_trip(frame);
+    }
+
+    #[test]
+    fn header_layout() {
+        let bytes = frame(Some(vec![9; 2])).encode().unwrap();
+
+        assert_eq!(&bytes[16..20], &[0, 0, 1, 2]);
+        assert_eq!(bytes[32], FLAG_IS_LAST_CHUNK | FLAG_HAS_DIGEST);
+        assert_eq!(&bytes[33..], &[0, 2, 9, 9, 1, 2, 3]);
+    }
+
+    #[test]
+    fn truncated_frames_are_rejected() {
+        let bytes = frame(Some(vec![9; 60])).encode().unwrap();
+
+        assert!(ChunkFrame::decode(&bytes[..HEADER_LEN - 1]).is_err());
+        assert!(ChunkFrame::decode(&bytes[..HEADER_LEN + 1]).is_err());
+        assert!(ChunkFrame::decode(&bytes[..HEADER_LEN + 10]).is_err());
+    }
+}
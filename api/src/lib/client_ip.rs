@@ -0,0 +1,289 @@
+//! IP of the client, as seen through the trusted proxies in front of the services.
+//!
+//! - `CLIENT_IP_SOURCE`: `connect-info` (default), `x-real-ip`, `x-forwarded-for` or `proxy-protocol`
+//! - `TRUSTED_PROXIES`: comma separated CIDRs of the proxies (default loopback)
+//!
+//! Headers are only read from trusted proxies, otherwise the peer address is the client.
+//! `x-forwarded-for` takes the rightmost address that is not a trusted proxy.
+
+use std::net::{IpAddr, SocketAddr};
+
+use async_trait::async_trait;
+use axum::{
+    extract::{ConnectInfo, FromRequestParts},
+    http::{request::Parts, Extensions, HeaderMap},
+};
+use once_cell::sync::Lazy;
+
+use log::error;
+
+use crate::error::AppError;
+
+const DEFAULT_TRUSTED_PROXIES: &str = "127.0.0.0/8,::1/128";
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum IpSource {
+    ConnectInfo,
+    XRealIp,
+    XForwardedFor,
+    /// The peer address is read from the PROXY protocol header of the connection, see [`crate::proxy_protocol`].
+    ProxyProtocol,
+}
+
+impl IpSource {
+    fn parse(source: &str) -> Option<Self> {
+        match source.trim().to_ascii_lowercase().as_str() {
+            "connect-info" => Some(IpSource::ConnectInfo),
+            "x-real-ip" => Some(IpSource::XRealIp),
+            "x-forwarded-for" => Some(IpSource::XForwardedFor),
+            "proxy-protocol" => Some(IpSource::ProxyProtocol),
+            _ => None,
+        }
+    }
+}
+
+/// Network in CIDR notation, e.g. `10.0.0.0/8`.
+#[derive(Clone, Copy, Debug)]
+pub struct Cidr {
+    addr: IpAddr,
+    prefix: u8,
+}
+
+impl Cidr {
+    /// Parses `<ip>/<prefix>`, a single IP is a network of one address.
+    pub fn parse(cidr: &str) -> Option<Self> {
+        let (addr, prefix) = match cidr.trim().split_once('/') {
+            Some((addr, prefix)) => (addr.parse::<IpAddr>().ok()?, Some(prefix.parse().ok()?)),
+            None => (cidr.trim().parse::<IpAddr>().ok()?, None),
+        };
+        let max_prefix = match addr {
+            IpAddr::V4(_) => 32,
+            IpAddr::V6(_) => 128,
+        };
+        let prefix = prefix.unwrap_or(max_prefix);
+        if prefix > max_prefix {
+            return None;
+        }
+
+        Some(Cidr { addr, prefix })
+    }
+
+    pub fn contains(&self, ip: &IpAddr) -> bool {
+        match (self.addr, ip.to_canonical()) {
+            (IpAddr::V4(net), IpAddr::V4(ip)) => {
+                let mask = u32::MAX.checked_shl(32 - self.prefix as u32).unwrap_or(0);
+                u32::from(net) & mask == u32::from(ip) & mask
+            }
+            (IpAddr::V6(net), IpAddr::V6(ip)) => {
+                let mask = u128::MAX.checked_shl(128 - self.prefix as u32).unwrap_or(0);
+                u128::from(net) & mask == u128::from(ip) & mask
+            }
+            _ => false,
+        }
+    }
+}
+
+#[derive(Clone, Debug)]
+pub struct ClientIpConfig {
+    pub source: IpSource,
+    pub trusted_proxies: Vec<Cidr>,
+}
+
+impl ClientIpConfig {
+    pub fn from_env() -> Self {
+        let source = match std::env::var("CLIENT_IP_SOURCE") {
+            Ok(source) => IpSource::parse(&source).unwrap_or_else(|| {
+                error!("Invalid CLIENT_IP_SOURCE: {}", source);
+                IpSource::ConnectInfo
+            }),
+            Err(_) => IpSource::ConnectInfo,
+        };
+
+        let trusted_proxies = std::env::var("TRUSTED_PROXIES")
+            .unwrap_or(DEFAULT_TRUSTED_PROXIES.to_string())
+            .split(',')
+            .filter(|cidr| !cidr.trim().is_empty())
+            .filter_map(|cidr| {
+                let parsed = Cidr::parse(cidr);
+                if parsed.is_none() {
+                    error!("Invalid TRUSTED_PROXIES entry: {}", cidr);
+                }
+                parsed
+            })
+            .collect();
+
+        ClientIpConfig {
+            source,
+            trusted_proxies,
+        }
+    }
+
+    pub fn is_trusted(&self, ip: &IpAddr) -> bool {
+        self.trusted_proxies.iter().any(|cidr| cidr.contains(ip))
+    }
+
+    /// IP of the client of a request coming from `peer`.
+    pub fn client_ip(&self, peer: IpAddr, headers: &HeaderMap) -> IpAddr {
+        if !self.is_trusted(&peer) {
+            return peer;
+        }
+
+        let ip = match self.source {
+            IpSource::ConnectInfo | IpSource::ProxyProtocol => None,
+            IpSource::XRealIp => headers
+                .get("x-real-ip")
+                .and_then(|ip| ip.to_str().ok())
+                .and_then(|ip| ip.trim().parse().ok()),
+            IpSource::XForwardedFor => self.rightmost_untrusted(headers),
+        };
+
+        ip.unwrap_or(peer)
+    }
+
+    /// Walks the proxies from the closest one, the first untrusted address is the client.
+    /// An entry that is no address ends the walk, the farthest trusted address before it is the client,
+    /// so garbage prepended by a client does not hide the address its proxy appended.
+    fn rightmost_untrusted(&self, headers: &HeaderMap) -> Option<IpAddr> {
+        let entries = headers
+            .get_all("x-forwarded-for")
+            .iter()
+            .map(|header| header.to_str().unwrap_or(""))
+            .flat_map(|header| header.split(','))
+            .collect::<Vec<&str>>();
+
+        let mut farthest_trusted = None;
+        for entry in entries.iter().rev() {
+            let ip = match entry.trim().parse::<IpAddr>() {
+                Ok(ip) => ip,
+                Err(_) => break,
+            };
+            if !self.is_trusted(&ip) {
+                return Some(ip);
+            }
+            farthest_trusted = Some(ip);
+        }
+
+        farthest_trusted
+    }
+}
+
+static CONFIG: Lazy<ClientIpConfig> = Lazy::new(ClientIpConfig::from_env);
+
+/// Configuration read from the environment on first use.
+pub fn config() -> &'static ClientIpConfig {
+    &CONFIG
+}
+
+/// IP of the client of a request, see [`ClientIpConfig::client_ip`].
+#[derive(Clone, Copy, Debug)]
+pub struct ClientIp(pub IpAddr);
+
+impl ClientIp {
+    pub fn from_request(headers: &HeaderMap, extensions: &Extensions) -> Option<Self> {
+        let ConnectInfo(peer) = extensions.get::<ConnectInfo<SocketAddr>>()?;
+
+        Some(ClientIp(config().client_ip(peer.ip(), headers)))
+    }
+}
+
+#[async_trait]
+impl<S> FromRequestParts<S> for ClientIp
+where
+    S: Send + Sync,
+{
+    type Rejection = AppError;
+
+    async fn from_request_parts(parts: &mut Parts, _state: &S) -> Result<Self, Self::Rejection> {
+        ClientIp::from_request(&parts.headers, &parts.extensions).ok_or(AppError::Internal(
+            "missing connect info of the client".to_string(),
+        ))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use axum::http::HeaderValue;
+
+    fn config() -> ClientIpConfig {
+        ClientIpConfig {
+            source: IpSource::XForwardedFor,
+            trusted_proxies: vec![Cidr::parse("10.0.0.0/8").unwrap()],
+        }
+    }
+
+    fn headers(values: &[&str]) -> HeaderMap {
+        let mut headers = HeaderMap::new();
+        for value in values {
+            headers.append("x-forwarded-for", HeaderValue::from_str(value).unwrap());
+        }
+
+        headers
+    }
+
+    fn client_ip(values: &[&str]) -> IpAddr {
+        config().client_ip("10.0.0.1".parse().unwrap(), &headers(values))
+    }
+
+    #[test]
+    fn rightmost_untrusted_address_is_the_client() {
+        assert_eq!(
+            client_ip(&["1.1.1.1, 2.2.2.2, 10.0.0.2"]),
+            "2.2.2.2".parse::<IpAddr>().unwrap()
+        );
+    }
+
+    #[test]
+    fn garbage_before_the_client_is_ignored() {
+        assert_eq!(
+            client_ip(&["garbage, 2.2.2.2, 10.0.0.2"]),
+            "2.2.2.2".parse::<IpAddr>().unwrap()
+        );
+        assert_eq!(
+            client_ip(&["1.1.1.1, garbage, 2.2.2.2"]),
+            "2.2.2.2".parse::<IpAddr>().unwrap()
+        );
+    }
+
+    #[test]
+    fn garbage_between_trusted_proxies_ends_the_walk() {
+        assert_eq!(
+            client_ip(&["1.1.1.1, garbage, 10.0.0.3, 10.0.0.2"]),
+            "10.0.0.3".parse::<IpAddr>().unwrap()
+        );
+    }
+
+    #[test]
+    fn garbage_from_the_proxy_falls_back_to_the_peer() {
+        assert_eq!(
+            client_ip(&["1.1.1.1, garbage"]),
+            "10.0.0.1".parse::<IpAddr>().unwrap()
+        );
+    }
+
+    #[test]
+    fn farthest_address_is_the_client_if_all_are_trusted() {
+        assert_eq!(
+            client_ip(&["10.0.0.3, 10.0.0.2"]),
+            "10.0.0.3".parse::<IpAddr>().unwrap()
+        );
+    }
+
+    #[test]
+    fn header_lines_are_read_in_order() {
+        assert_eq!(
+            client_ip(&["1.1.1.1", "2.2.2.2, 10.0.0.3", "10.0.0.2"]),
+            "2.2.2.2".parse::<IpAddr>().unwrap()
+        );
+        assert_eq!(
+            client_ip(&["garbage", "2.2.2.2", "10.0.0.2"]),
+            "2.2.2.2".parse::<IpAddr>().unwrap()
+        );
+    }
+
+    #[test]
+    fn headers_of_untrusted_peers_are_ignored() {
+        let peer: IpAddr = "3.3.3.3".parse().unwrap();
+        assert_eq!(config().client_ip(peer, &headers(&["2.2.2.2"])), peer);
+    }
+}
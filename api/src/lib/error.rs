@@ -0,0 +1,155 @@
+use std::fmt;
+
+use axum::{
+    http::StatusCode,
+    response::{IntoResponse, Response},
+};
+
+use log::{error, info};
+use serde_json::json;
+
+use crate::store::StoreError;
+
+const DB_ERROR_MSG: &str = "error connection to database";
+const INTERNAL_ERROR_MSG: &str = "internal error";
+
+/// Error of a handler, answered with a JSON envelope over HTTP
+/// and with an `error` message over the websocket.
+#[derive(Debug)]
+pub enum AppError {
+    BadRequest {
+        /// Field of the request that is invalid.
+        field: Option<String>,
+        message: String,
+    },
+    Unauthorized(String),
+    Forbidden(String),
+    NotFound(String),
+    Conflict(String),
+    RateLimited(String),
+    UnsupportedVersion(String),
+    /// The store failed, only logged.
+    Storage(StoreError),
+    /// Unexpected state, only logged.
+    Internal(String),
+}
+
+impl AppError {
+    pub fn bad_request(message: impl Into<String>) -> Self {
+        AppError::BadRequest {
+            field: None,
+            message: message.into(),
+        }
+    }
+
+    pub fn invalid_field(field: impl Into<String>, message: impl Into<String>) -> Self {
+        AppError::BadRequest {
+            field: Some(field.into()),
+            message: message.into(),
+        }
+    }
+
+    pub fn status(&self) -> StatusCode {
+        match self {
+            AppError::BadRequest { .. } => StatusCode::BAD_REQUEST,
+            AppError::Unauthorized(_) => StatusCode::UNAUTHORIZED,
+            AppError::Forbidden(_) => StatusCode::FORBIDDEN,
+            AppError::NotFound(_) => StatusCode::NOT_FOUND,
+            AppError::Conflict(_) => StatusCode::CONFLICT,
+            AppError::RateLimited(_) => StatusCode::TOO_MANY_REQUESTS,
+            // 505 is about the HTTP version, not the version of the protocol
+            AppError::UnsupportedVersion(_) => StatusCode::BAD_REQUEST,
+            AppError::Storage(_) | AppError::Internal(_) => StatusCode::INTERNAL_SERVER_ERROR,
+        }
+    }
+
+    /// Machine readable code, shared by HTTP and websocket errors.
+    pub fn code(&self) -> &'static str {
+        match self {
+            AppError::BadRequest { .. } => "bad_request",
+            AppError::Unauthorized(_) => "unauthorized",
+            AppError::Forbidden(_) => "forbidden",
+            AppError::NotFound(_) => "not_found",
+            AppError::Conflict(_) => "conflict",
+            AppError::RateLimited(_) => "too_many_requests",
+            AppError::UnsupportedVersion(_) => "unsupported_version",
+            AppError::Storage(_) | AppError::Internal(_) => "internal_error",
+        }
+    }
+
+    /// Message shown to the client, internal details are left out.
+    pub fn message(&self) -> &str {
+        match self {
+            AppError::BadRequest { message, .. } => message,
+            AppError::Unauthorized(message)
+            | AppError::Forbidden(message)
+            | AppError::NotFound(message)
+            | AppError::Conflict(message)
+            | AppError::RateLimited(message)
+            | AppError::UnsupportedVersion(message) => message,
+            AppError::Storage(_) => DB_ERROR_MSG,
+            AppError::Internal(_) => INTERNAL_ERROR_MSG,
+        }
+    }
+
+    pub fn field(&self) -> Option<&str> {
+        match self {
+            AppError::BadRequest { field, .. } => field.as_deref(),
+            _ => None,
+        }
+    }
+
+    /// Logs the error, server errors with their source.
+    pub fn log(&self) {
+        match self.status().is_server_error() {
+            true => error!("{} - {}", self.status().as_u16(), self),
+            false => info!("{} - {}", self.status().as_u16(), self),
+        }
+    }
+}
+
+impl fmt::Display for AppError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            AppError::BadRequest {
+                field: Some(field),
+                message,
+            } => write!(f, "{} ({})", message, field),
+            AppError::Storage(e) => write!(f, "{}: {}", DB_ERROR_MSG, e),
+            AppError::Internal(message) => write!(f, "{}: {}", INTERNAL_ERROR_MSG, message),
+            _ => write!(f, "{}", self.message()),
+        }
+    }
+}
+
+impl std::error::Error for AppError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            AppError::Storage(e) => Some(e),
+            _ => None,
+        }
+    }
+}
+
+impl From<StoreError> for AppError {
+    fn from(e: StoreError) -> Self {
+        AppError::Storage(e)
+    }
+}
+
+impl IntoResponse for AppError {
+    fn into_response(self) -> Response {
+        self.log();
+
+        let mut body = json!({
+            "success": false,
+            "code": self.code(),
+            "message": self.message(),
+        });
+        if let Some(field) = self.field() {
+            body["field"] = json!(field);
+        }
+
+        (self.status(), body.to_string()).into_response()
+    }
+}
@@ -0,0 +1,236 @@
+//! Keys of the store, see [database/README.md](../../../database/README.md).
+//!
+//! This is the only place keys are built.
+//! Every key and channel starts with the prefix set by `KEY_PREFIX`,
+//! so several deployments can share one Redis.
+//!
+//! Keys of a session, including the ones of its users, are hash tagged by the session id
+//! and keys of a file request by the request id, e.g. `chunk.acked:{<request.id>}`.
+//! A Redis Cluster spreads sessions and file requests over its slots,
+//! while the keys of one of them share a slot and can be written by a single atomic batch.
+
+use once_cell::sync::Lazy;
+
+static PREFIX: Lazy<String> = Lazy::new(|| match std::env::var("KEY_PREFIX") {
+    Ok(prefix) if !prefix.is_empty() => format!("{}:", prefix),
+    _ => String::new(),
+});
+
+/// Prefix of every key, empty or ending with `:`.
+pub fn prefix() -> &'static str {
+    &PREFIX
+}
+
+/// `HASH session:{<session.id>}`
+pub fn session(session_id: &str) -> String {
+    format!("{}session:{{{}}}", prefix(), session_id)
+}
+
+/// `session:<session.name>`
+pub fn session_name(session_name: &str) -> String {
+    format!("{}session:{}", prefix(), session_name)
+}
+
+/// `SET files:{<session.id>}`
+pub fn files(session_id: &str) -> String {
+    format!("{}files:{{{}}}", prefix(), session_id)
+}
+
+/// `HASH files:{<session.id>}:<filename>`
+pub fn file(session_id: &str, filename: &str) -> String {
+    format!("{}files:{{{}}}:{}", prefix(), session_id, filename)
+}
+
+/// `created.sessions:<ip>`
+pub fn created_sessions(ip: &str) -> String {
+    format!("{}created.sessions:{}", prefix(), ip)
+}
+
+/// `access.attempts:{<session.id>}:<ip>`
+pub fn access_attempts(session_id: &str, ip: &str) -> String {
+    format!("{}access.attempts:{{{}}}:{}", prefix(), session_id, ip)
+}
+
+/// `rate.limit:<client>:<window>:<route>`
+pub fn rate_limit(client: &str, window: u128, route: &str) -> String {
+    format!("{}rate.limit:{}:{}:{}", prefix(), client, window, route)
+}
+
+/// `CHANNEL events:<user.id>`
+pub fn events(user_id: &str) -> String {
+    format!("{}events:{}", prefix(), user_id)
+}
+
+/// Pattern matching the `events:<user.id>` channel of every user.
+pub fn events_pattern() -> String {
+    format!("{}events:*", prefix())
+}
+
+/// User id of an `events:<user.id>` channel.
+pub fn events_user(channel: &str) -> Option<&str> {
+    channel
+        .strip_prefix(prefix())
+        .and_then(|channel| channel.strip_prefix("events:"))
+}
+
+/// `SET transfers:{<session.id>}`
+pub fn transfers(session_id: &str) -> String {
+    format!("{}transfers:{{{}}}", prefix(), session_id)
+}
+
+/// `SET file.reqs:{<session.id>}`
+pub fn file_reqs(session_id: &str) -> String {
+    format!("{}file.reqs:{{{}}}", prefix(), session_id)
+}
+
+/// `SET file.reqs:{<session.id>}:<filename>`
+pub fn file_reqs_of_file(session_id: &str, filename: &str) -> String {
+    format!("{}file.reqs:{{{}}}:{}", prefix(), session_id, filename)
+}
+
+/// `file.reqs.since:{<session.id>}:<filename>`
+pub fn file_reqs_since(session_id: &str, filename: &str) -> String {
+    format!(
+        "{}file.reqs.since:{{{}}}:{}",
+        prefix(),
+        session_id,
+        filename
+    )
+}
+
+/// `LIST file.reqs.queue:{<session.id>}:<user.id>`
+pub fn file_reqs_queue(session_id: &str, user_id: &str) -> String {
+    format!("{}file.reqs.queue:{{{}}}:{}", prefix(), session_id, user_id)
+}
+
+/// `file.req:{<session.id>}:<filename>:<user.id>`
+pub fn file_req(session_id: &str, filename: &str, user_id: &str) -> String {
+    format!(
+        "{}file.req:{{{}}}:{}:{}",
+        prefix(),
+        session_id,
+        filename,
+        user_id
+    )
+}
+
+/// `file.req.capabilities:{<session.id>}:<filename>:<user.id>`
+pub fn file_req_capabilities(session_id: &str, filename: &str, user_id: &str) -> String {
+    format!(
+        "{}file.req.capabilities:{{{}}}:{}:{}",
+        prefix(),
+        session_id,
+        filename,
+        user_id
+    )
+}
+
+/// `SET file.req.users:{<request.id>}`
+pub fn file_req_users(request_id: &str) -> String {
+    format!("{}file.req.users:{{{}}}", prefix(), request_id)
+}
+
+/// `file.req.sender:{<request.id>}`
+pub fn file_req_sender(request_id: &str) -> String {
+    format!("{}file.req.sender:{{{}}}", prefix(), request_id)
+}
+
+/// `file.req.filename:{<request.id>}`
+pub fn file_req_filename(request_id: &str) -> String {
+    format!("{}file.req.filename:{{{}}}", prefix(), request_id)
+}
+
+/// `SET transfer.paused:{<request.id>}`
+pub fn transfer_paused(request_id: &str) -> String {
+    format!("{}transfer.paused:{{{}}}", prefix(), request_id)
+}
+
+/// `HASH transfer.paused.since:{<request.id>}`
+pub fn transfer_paused_since(request_id: &str) -> String {
+    format!("{}transfer.paused.since:{{{}}}", prefix(), request_id)
+}
+
+/// `SET file.reqs.sender:{<session.id>}:<user.id>`
+pub fn file_reqs_sender(session_id: &str, user_id: &str) -> String {
+    format!(
+        "{}file.reqs.sender:{{{}}}:{}",
+        prefix(),
+        session_id,
+        user_id
+    )
+}
+
+/// `SET file.reqs.receiver:{<session.id>}:<user.id>`
+pub fn file_reqs_receiver(session_id: &str, user_id: &str) -> String {
+    format!(
+        "{}file.reqs.receiver:{{{}}}:{}",
+        prefix(),
+        session_id,
+        user_id
+    )
+}
+
+/// `HASH file.req.prep:{<request.id>}`
+pub fn file_req_prep(request_id: &str) -> String {
+    format!("{}file.req.prep:{{{}}}", prefix(), request_id)
+}
+
+/// `HASH file.req.keys:{<request.id>}`
+pub fn file_req_keys(request_id: &str) -> String {
+    format!("{}file.req.keys:{{{}}}", prefix(), request_id)
+}
+
+/// `SET file.req.prepared:{<request.id>}`
+pub fn file_req_prepared(request_id: &str) -> String {
+    format!("{}file.req.prepared:{{{}}}", prefix(), request_id)
+}
+
+/// `chunk.window:{<request.id>}`
+pub fn chunk_window(request_id: &str) -> String {
+    format!("{}chunk.window:{{{}}}", prefix(), request_id)
+}
+
+/// `chunk.amount:{<request.id>}`
+pub fn chunk_amount(request_id: &str) -> String {
+    format!("{}chunk.amount:{{{}}}", prefix(), request_id)
+}
+
+/// `HASH chunk.acked:{<request.id>}`
+pub fn chunk_acked(request_id: &str) -> String {
+    format!("{}chunk.acked:{{{}}}", prefix(), request_id)
+}
+
+/// `chunk.req:{<request.id>}`
+pub fn chunk_req(request_id: &str) -> String {
+    format!("{}chunk.req:{{{}}}", prefix(), request_id)
+}
+
+/// `HASH chunk.sent:{<request.id>}`
+pub fn chunk_sent(request_id: &str) -> String {
+    format!("{}chunk.sent:{{{}}}", prefix(), request_id)
+}
+
+/// `HASH chunks:{<request.id>}`
+pub fn chunks(request_id: &str) -> String {
+    format!("{}chunks:{{{}}}", prefix(), request_id)
+}
+
+/// `HASH chunk.size:{<request.id>}`
+pub fn chunk_size(request_id: &str) -> String {
+    format!("{}chunk.size:{{{}}}", prefix(), request_id)
+}
+
+/// `HASH chunk.bytes:{<request.id>}`
+pub fn chunk_bytes(request_id: &str) -> String {
+    format!("{}chunk.bytes:{{{}}}", prefix(), request_id)
+}
+
+/// `transfer.started:{<request.id>}`
+pub fn transfer_started(request_id: &str) -> String {
+    format!("{}transfer.started:{{{}}}", prefix(), request_id)
+}
+
+/// `chunk.last:{<request.id>}`
+pub fn chunk_last(request_id: &str) -> String {
+    format!("{}chunk.last:{{{}}}", prefix(), request_id)
+}
@@ -0,0 +1,309 @@
+use std::collections::HashMap;
+
+use once_cell::sync::Lazy;
+use schemars::{schema_for, JsonSchema};
+use serde::{Deserialize, Serialize};
+use serde_json::json;
+
+/// Version of the websocket protocol spoken by the transmittor.
+/// Clients state the version they speak with `hello` or `register`.
+pub const PROTOCOL_VERSION: u32 = 1;
+
+pub const SUPPORTED_PROTOCOL_VERSIONS: &[u32] = &[PROTOCOL_VERSION];
+
+pub const CAPABILITY_BINARY_CHUNKS: &str = "binary-chunks";
+pub const CAPABILITY_BROADCAST: &str = "broadcast";
+pub const CAPABILITY_CHUNK_DIGESTS: &str = "chunk-digests";
+
+pub const CAPABILITIES: &[&str] = &[
+    CAPABILITY_BINARY_CHUNKS,
+    CAPABILITY_BROADCAST,
+    CAPABILITY_CHUNK_DIGESTS,
+];
+
+/// Request sent from a client to the transmittor.
+#[derive(Serialize, Deserialize, JsonSchema)]
+pub struct WsRequest {
+    /// Correlation ID, echoed in the `ok`/`error` reply.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub id: Option<String>,
+    /// The connection is authenticated when the websocket is opened.
+    /// A JWT of the same user extends the connection beyond the expiration of the first one.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub jwt: Option<String>,
+    #[serde(flatten)]
+    pub command: WsCommand,
+}
+
+impl WsRequest {
+    /// Parses a request.
+    /// `data` is either an object or, as sent by the web app, a stringified object.
+    pub fn parse(message: &str) -> Result<Self, serde_json::Error> {
+        let mut request: serde_json::Value = serde_json::from_str(message)?;
+
+        if let Some(data) = request.get_mut("data") {
+            if let Some(data_str) = data.as_str() {
+                *data = serde_json::from_str(data_str)?;
+            }
+        }
+
+        serde_json::from_value(request)
+    }
+}
+
+#[derive(Serialize, Deserialize, JsonSchema)]
+#[serde(tag = "command", content = "data", rename_all = "kebab-case")]
+pub enum WsCommand {
+    Hello(ReqHello),
+    Register(ReqRegister),
+    RequestFile(ReqRequestFile),
+    AcknowledgeFileRequest(ReqAcknowledgeFileRequest),
+    ReadyForFileTransfer(ReqReadyForFileRequest),
+    AddChunk(ReqAddChunk),
+    ReceivedChunk(ReqReceivedChunk),
+    ResumeTransfer(ReqResumeTransfer),
+    CancelTransfer(ReqCancelTransfer),
+}
+
+#[derive(Serialize, Deserialize, JsonSchema)]
+pub struct ReqHello {
+    pub version: u32,
+    #[serde(default)]
+    pub capabilities: Vec<String>,
+}
+
+#[derive(Serialize, Deserialize, JsonSchema)]
+pub struct ReqRegister {
+    /// Defaults to the version stated with `hello`, or 1.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub version: Option<u32>,
+    /// Defaults to the capabilities stated with `hello`.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub capabilities: Option<Vec<String>>,
+}
+
+#[derive(Serialize, Deserialize, JsonSchema)]
+pub struct ReqRequestFile {
+    pub public_key: String,
+    pub filename: String,
+}
+
+/// Content key of a file, encrypted for a single receiver.
+#[derive(Clone, Serialize, Deserialize, JsonSchema)]
+pub struct WrappedKey {
+    pub key: String,
+    pub iv: String,
+}
+
+/// Hash of the whole file, encrypted like the chunks so only the receivers can read it.
+#[derive(Clone, Serialize, Deserialize, JsonSchema)]
+pub struct ContentHash {
+    /// Base64 encoded.
+    pub hash: String,
+    /// Base64 encoded.
+    pub iv: String,
+}
+
+#[derive(Serialize, Deserialize, JsonSchema)]
+pub struct ReqAcknowledgeFileRequest {
+    pub request_id: String,
+    pub public_key: String,
+    pub amount_of_chunks: u32,
+    pub filename: String,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub window_size: Option<u32>,
+    /// Content key per receiver (user ID), required for broadcasts.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub wrapped_keys: Option<HashMap<String, WrappedKey>>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub content_hash: Option<ContentHash>,
+}
+
+#[derive(Serialize, Deserialize, JsonSchema)]
+pub struct ReqReadyForFileRequest {
+    pub request_id: String,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub window_size: Option<u32>,
+}
+
+#[derive(Serialize, Deserialize, JsonSchema)]
+pub struct ReqAddChunk {
+    pub request_id: String,
+    pub is_last_chunk: bool,
+    pub chunk_nr: u32,
+    /// Hex encoded.
+    pub chunk: String,
+    /// Base64 encoded.
+    pub iv: String,
+    /// Encrypted digest of the plaintext chunk, base64 encoded.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub digest: Option<String>,
+}
+
+#[derive(Serialize, Deserialize, JsonSchema)]
+pub struct ReqReceivedChunk {
+    pub request_id: String,
+    pub chunk_nr: u32,
+    /// Acknowledges every chunk from `chunk_nr` up to and including this one.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub last_chunk_nr: Option<u32>,
+}
+
+#[derive(Serialize, Deserialize, JsonSchema)]
+pub struct ReqResumeTransfer {
+    pub request_id: String,
+}
+
+#[derive(Serialize, Deserialize, JsonSchema)]
+pub struct ReqCancelTransfer {
+    pub request_id: String,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub reason: Option<String>,
+}
+
+/// Message sent from the transmittor to a client.
+#[derive(Serialize, Deserialize, JsonSchema)]
+pub struct WsMessage {
+    pub request_id: String,
+    #[serde(flatten)]
+    pub msg: WsMsg,
+}
+
+#[derive(Serialize, Deserialize, JsonSchema)]
+#[serde(tag = "command", content = "data", rename_all = "kebab-case")]
+pub enum WsMsg {
+    Hello(WsMsgHello),
+    Ok(WsMsgOk),
+    Error(WsMsgError),
+    AcknowledgeFileRequest(WsMsgAcknowledgeFileRequest),
+    QueuePosition(WsMsgQueuePosition),
+    PrepareForFileTransfer(WsMsgPrepareForFileTransfer),
+    SendNextChunk(WsMsgSendNextChunk),
+    AddChunk(WsMsgAddChunk),
+    TransferPaused(WsMsgTransferPaused),
+    TransferResumed(WsMsgTransferResumed),
+    TransferCancelled(WsMsgTransferCancelled),
+    TransferProgress(WsMsgTransferProgress),
+}
+
+#[derive(Serialize, Deserialize, JsonSchema)]
+pub struct WsMsgHello {
+    pub version: u32,
+    /// Capabilities supported by the transmittor.
+    pub capabilities: Vec<String>,
+}
+
+#[derive(Serialize, Deserialize, JsonSchema)]
+pub struct WsMsgOk {
+    pub id: Option<String>,
+    pub command: Option<String>,
+}
+
+#[derive(Serialize, Deserialize, JsonSchema)]
+pub struct WsMsgError {
+    pub id: Option<String>,
+    pub command: Option<String>,
+    pub code: String,
+    pub message: String,
+    /// Field of the request that is invalid.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub field: Option<String>,
+}
+
+#[derive(Serialize, Deserialize, JsonSchema)]
+pub struct WsMsgAcknowledgeFileRequest {
+    pub public_key: String,
+    pub filename: String,
+    /// Every receiver of a broadcast, including the one of `public_key`.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub receivers: Option<Vec<WsMsgReceiver>>,
+}
+
+#[derive(Serialize, Deserialize, JsonSchema)]
+pub struct WsMsgReceiver {
+    pub user_id: String,
+    pub public_key: String,
+}
+
+#[derive(Serialize, Deserialize, JsonSchema)]
+pub struct WsMsgQueuePosition {
+    pub filename: String,
+    pub position: usize,
+}
+
+#[derive(Serialize, Deserialize, JsonSchema)]
+pub struct WsMsgPrepareForFileTransfer {
+    pub public_key: String,
+    pub filename: String,
+    pub amount_of_chunks: u32,
+    pub window_size: u32,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub wrapped_key: Option<WrappedKey>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub content_hash: Option<ContentHash>,
+}
+
+#[derive(Serialize, Deserialize, JsonSchema)]
+pub struct WsMsgSendNextChunk {
+    pub chunk_nr: u32,
+}
+
+#[derive(Serialize, Deserialize, JsonSchema)]
+pub struct WsMsgAddChunk {
+    pub is_last_chunk: bool,
+    pub chunk_nr: u32,
+    /// Hex encoded.
+    pub chunk: String,
+    /// Base64 encoded.
+    pub iv: String,
+    /// Base64 encoded.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub digest: Option<String>,
+}
+
+#[derive(Serialize, Deserialize, JsonSchema)]
+pub struct WsMsgTransferPaused {
+    /// Seconds.
+    pub grace_period: i64,
+}
+
+#[derive(Serialize, Deserialize, JsonSchema)]
+pub struct WsMsgTransferResumed {
+    pub chunk_nr: u32,
+}
+
+#[derive(Serialize, Deserialize, JsonSchema)]
+pub struct WsMsgTransferCancelled {
+    pub reason: String,
+}
+
+/// Progress of a receiver, sent to the sender and the receiver whenever it acknowledged chunks.
+#[derive(Clone, Debug, Serialize, Deserialize, JsonSchema)]
+pub struct WsMsgTransferProgress {
+    /// Receiver the progress is about.
+    pub user_id: String,
+    /// Chunks acknowledged by the receiver.
+    pub chunks_done: u32,
+    pub amount_of_chunks: u32,
+    /// Encrypted bytes acknowledged by the receiver.
+    pub bytes: u64,
+    /// Bytes per second since the transfer started.
+    pub rate: u64,
+    /// Seconds until the transfer is done, estimated from the chunks done so far.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub eta: Option<u64>,
+}
+
+static JSON_SCHEMA: Lazy<String> = Lazy::new(|| {
+    json!({
+        "version": PROTOCOL_VERSION,
+        "request": schema_for!(WsRequest),
+        "message": schema_for!(WsMessage),
+    })
+    .to_string()
+});
+
+/// JSON Schema of the requests and messages of the protocol.
+pub fn json_schema() -> &'static str {
+    &JSON_SCHEMA
+}
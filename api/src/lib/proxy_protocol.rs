@@ -0,0 +1,251 @@
+//! Server for connections starting with a PROXY protocol header (v1 or v2),
+//! as sent by load balancers that pass the client address on the TCP level.
+//!
+//! Headers are only accepted from trusted proxies, see [`crate::client_ip`].
+
+use std::io;
+use std::net::{IpAddr, Ipv4Addr, Ipv6Addr, SocketAddr};
+use std::time::Duration;
+
+use axum::{extract::ConnectInfo, Router};
+use hyper::{body::Incoming, service::service_fn, Request};
+use hyper_util::{
+    rt::{TokioExecutor, TokioIo},
+    server::conn::auto::Builder,
+};
+use tokio::io::{AsyncRead, AsyncReadExt};
+use tokio::net::TcpListener;
+use tokio::time;
+use tower::Service;
+
+use log::{error, info};
+
+use crate::client_ip;
+
+const V2_SIGNATURE: &[u8; 12] = b"\r\n\r\n\0\r\nQUIT\n";
+const V1_MAX_LENGTH: usize = 107;
+/// Time a proxy has to send the header, before the connection is closed.
+const HEADER_TIMEOUT: Duration = Duration::from_secs(5);
+
+/// Reads the PROXY header at the start of a connection.
+/// Returns the source address, `None` for health checks of the proxy itself.
+pub async fn read_header<R: AsyncRead + Unpin>(stream: &mut R) -> io::Result<Option<SocketAddr>> {
+    let mut signature = [0u8; 12];
+    stream.read_exact(&mut signature).await?;
+
+    match &signature == V2_SIGNATURE {
+        true => read_v2(stream).await,
+        false => read_v1(stream, signature).await,
+    }
+}
+
+/// `PROXY TCP4 <src> <dst> <src port> <dst port>\r\n`
+async fn read_v1<R: AsyncRead + Unpin>(
+    stream: &mut R,
+    start: [u8; 12],
+) -> io::Result<Option<SocketAddr>> {
+    if !start.starts_with(b"PROXY ") {
+        return Err(invalid("missing PROXY header"));
+    }
+
+    let mut line = start.to_vec();
+    while !line.ends_with(b"\r\n") {
+        if line.len() >= V1_MAX_LENGTH {
+            return Err(invalid("PROXY header too long"));
+        }
+        line.push(stream.read_u8().await?);
+    }
+
+    let line = std::str::from_utf8(&line[..line.len() - 2])
+        .map_err(|_| invalid("PROXY header is not ASCII"))?;
+    let parts = line.split(' ').collect::<Vec<&str>>();
+
+    match parts.as_slice() {
+        ["PROXY", "UNKNOWN", ..] => Ok(None),
+        ["PROXY", "TCP4" | "TCP6", src, _, src_port, _] => {
+            let ip = src
+                .parse::<IpAddr>()
+                .map_err(|_| invalid("invalid PROXY source address"))?;
+            let port = src_port
+                .parse::<u16>()
+                .map_err(|_| invalid("invalid PROXY source port"))?;
+            Ok(Some(SocketAddr::new(ip, port)))
+        }
+        _ => Err(invalid("malformed PROXY header")),
+    }
+}
+
+/// Binary header after the signature: version and command, family, length, addresses.
+async fn read_v2<R: AsyncRead + Unpin>(stream: &mut R) -> io::Result<Option<SocketAddr>> {
+    let version_command = stream.read_u8().await?;
+    let family = stream.read_u8().await?;
+    let length = stream.read_u16().await? as usize;
+
+    let mut addresses = vec![0u8; length];
+    stream.read_exact(&mut addresses).await?;
+
+    if version_command >> 4 != 2 {
+        return Err(invalid("unsupported PROXY version"));
+    }
+    // LOCAL, the connection was opened by the proxy itself
+    if version_command & 0x0f == 0 {
+        return Ok(None);
+    }
+
+    match family >> 4 {
+        // AF_INET: src, dst, src port, dst port
+        1 if length >= 12 => {
+            let ip = Ipv4Addr::new(addresses[0], addresses[1], addresses[2], addresses[3]);
+            let port = u16::from_be_bytes([addresses[8], addresses[9]]);
+            Ok(Some(SocketAddr::new(IpAddr::V4(ip), port)))
+        }
+        // AF_INET6
+        2 if length >= 36 => {
+            let mut ip = [0u8; 16];
+            ip.copy_from_slice(&addresses[..16]);
+            let port = u16::from_be_bytes([addresses[32], addresses[33]]);
+            Ok(Some(SocketAddr::new(IpAddr::V6(Ipv6Addr::from(ip)), port)))
+        }
+        // AF_UNSPEC or AF_UNIX carry no usable address
+        0 | 3 => Ok(None),
+        _ => Err(invalid("malformed PROXY v2 addresses")),
+    }
+}
+
+fn invalid(message: &str) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, message.to_string())
+}
+
+/// Serves the router, taking the address of each connection from its PROXY header.
+pub async fn serve(listener: TcpListener, router: Router) -> io::Result<()> {
+    loop {
+        let (mut stream, peer) = match listener.accept().await {
+            Ok(connection) => connection,
+            Err(e) => {
+                error!("Failed to accept connection: {}", e);
+                continue;
+            }
+        };
+        let router = router.clone();
+
+        tokio::spawn(async move {
+            if !client_ip::config().is_trusted(&peer.ip()) {
+                info!("Refused connection of untrusted proxy: {}", peer);
+                return;
+            }
+
+            let header = time::timeout(HEADER_TIMEOUT, read_header(&mut stream))
+                .await
+                .unwrap_or_else(|_| Err(io::Error::new(io::ErrorKind::TimedOut, "timed out")));
+            let addr = match header {
+                Ok(addr) => addr.unwrap_or(peer),
+                Err(e) => {
+                    error!("Failed to read PROXY header of {}: {}", peer, e);
+                    return;
+                }
+            };
+
+            let service = service_fn(move |mut request: Request<Incoming>| {
+                request.extensions_mut().insert(ConnectInfo(addr));
+                router.clone().call(request)
+            });
+
+            match Builder::new(TokioExecutor::new())
+                .serve_connection_with_upgrades(TokioIo::new(stream), service)
+                .await
+            {
+                Ok(_) => (),
+                Err(e) => {
+                    info!("Connection closed with error: {} ({})", addr, e);
+                }
+            }
+        });
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    async fn read(header: &[u8]) -> io::Result<Option<SocketAddr>> {
+        let mut stream = header;
+        read_header(&mut stream).await
+    }
+
+    fn v2(version_command: u8, family: u8, addresses: &[u8]) -> Vec<u8> {
+        let mut header = V2_SIGNATURE.to_vec();
+        header.extend([version_command, family]);
+        header.extend((addresses.len() as u16).to_be_bytes());
+        header.extend(addresses);
+
+        header
+    }
+
+    #[tokio::test]
+    async fn v1_tcp4_source_is_read() {
+        assert_eq!(
+            read(b"PROXY TCP4 1.2.3.4 5.6.7.8 1234 80\r\nGET /")
+                .await
+                .unwrap(),
+            Some("1.2.3.4:1234".parse().unwrap())
+        );
+    }
+
+    #[tokio::test]
+    async fn v1_tcp6_source_is_read() {
+        assert_eq!(
+            read(b"PROXY TCP6 2001:db8::1 2001:db8::2 1234 80\r\n")
+                .await
+                .unwrap(),
+            Some("[2001:db8::1]:1234".parse().unwrap())
+        );
+    }
+
+    #[tokio::test]
+    async fn v1_unknown_has_no_source() {
+        assert_eq!(read(b"PROXY UNKNOWN\r\n").await.unwrap(), None);
+    }
+
+    #[tokio::test]
+    async fn v2_proxy_source_is_read() {
+        let addresses = [1, 2, 3, 4, 5, 6, 7, 8, 0x04, 0xd2, 0, 80];
+        assert_eq!(
+            read(&v2(0x21, 0x11, &addresses)).await.unwrap(),
+            Some("1.2.3.4:1234".parse().unwrap())
+        );
+
+        let mut addresses = [0u8; 36];
+        addresses[..16].copy_from_slice(&"2001:db8::1".parse::<Ipv6Addr>().unwrap().octets());
+        addresses[32..34].copy_from_slice(&1234u16.to_be_bytes());
+        assert_eq!(
+            read(&v2(0x21, 0x21, &addresses)).await.unwrap(),
+            Some("[2001:db8::1]:1234".parse().unwrap())
+        );
+    }
+
+    #[tokio::test]
+    async fn v2_local_has_no_source() {
+        assert_eq!(read(&v2(0x20, 0x00, &[])).await.unwrap(), None);
+    }
+
+    #[tokio::test]
+    async fn truncated_header_is_rejected() {
+        let header = v2(0x21, 0x11, &[1, 2, 3, 4, 5, 6, 7, 8, 0x04, 0xd2, 0, 80]);
+        let error = read(&header[..20]).await.unwrap_err();
+        assert_eq!(error.kind(), io::ErrorKind::UnexpectedEof);
+
+        let error = read(b"PROXY TCP4 1.2.3.4").await.unwrap_err();
+        assert_eq!(error.kind(), io::ErrorKind::UnexpectedEof);
+    }
+
+    #[tokio::test]
+    async fn bad_signature_is_rejected() {
+        let error = read(b"GET / HTTP/1.1\r\n\r\n").await.unwrap_err();
+        assert_eq!(error.kind(), io::ErrorKind::InvalidData);
+
+        let mut header = v2(0x21, 0x11, &[0; 12]);
+        header[11] = b'X';
+        let error = read(&header).await.unwrap_err();
+        assert_eq!(error.kind(), io::ErrorKind::InvalidData);
+    }
+}
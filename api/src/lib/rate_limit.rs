@@ -0,0 +1,298 @@
+//! Sliding window rate limiter, applied as a tower layer to the routes of both routers.
+//!
+//! Requests are counted per client and route in the store, so the limits hold across replicas.
+//! The client is the IP, or the JWT subject of authenticated requests if `RATE_LIMIT_BY_SUBJECT` is set.
+
+use std::collections::HashMap;
+use std::convert::Infallible;
+use std::sync::Arc;
+use std::task::{Context, Poll};
+
+use axum::{
+    extract::{MatchedPath, Request},
+    http::{header, HeaderMap, HeaderName, HeaderValue},
+    response::{IntoResponse, Response},
+};
+use futures::future::BoxFuture;
+use tower::{Layer, Service};
+
+use log::error;
+
+use crate::client_ip::ClientIp;
+use crate::error::AppError;
+use crate::keys;
+use crate::store::{DynStore, StoreResult};
+
+const DEFAULT_LIMIT: Limit = Limit {
+    requests: 120,
+    window: 60,
+};
+
+const X_RATELIMIT_LIMIT: HeaderName = HeaderName::from_static("x-ratelimit-limit");
+const X_RATELIMIT_REMAINING: HeaderName = HeaderName::from_static("x-ratelimit-remaining");
+const X_RATELIMIT_RESET: HeaderName = HeaderName::from_static("x-ratelimit-reset");
+
+/// Amount of requests allowed within a window.
+#[derive(Clone, Copy, Debug)]
+pub struct Limit {
+    pub requests: u64,
+    /// Seconds.
+    pub window: u64,
+}
+
+impl Limit {
+    pub const fn new(requests: u64, window: u64) -> Self {
+        Limit { requests, window }
+    }
+
+    /// Parses `<requests>/<seconds>`, `off` is no limit.
+    fn parse(limit: &str) -> Option<Option<Self>> {
+        let limit = limit.trim();
+        if limit == "off" {
+            return Some(None);
+        }
+
+        let (requests, window) = limit.split_once('/')?;
+        let requests = requests.trim().parse().ok()?;
+        let window = window.trim().parse().ok().filter(|window| *window > 0)?;
+
+        Some(Some(Limit::new(requests, window)))
+    }
+}
+
+/// Limits of a router.
+/// Routes are named by method and path, e.g. `GET /access/:session_id`.
+#[derive(Clone, Debug)]
+pub struct RateLimitConfig {
+    pub default: Option<Limit>,
+    pub routes: HashMap<String, Option<Limit>>,
+    pub by_subject: bool,
+}
+
+impl RateLimitConfig {
+    /// Reads the configuration from the environment, on top of the limits of the routes given.
+    ///
+    /// - `RATE_LIMIT`: default limit of every route, `<requests>/<seconds>` or `off` (default `120/60`)
+    /// - `RATE_LIMIT_ROUTES`: comma separated `<METHOD> <path>=<requests>/<seconds>` or `=off`
+    /// - `RATE_LIMIT_BY_SUBJECT`: `true` counts authenticated requests per JWT subject instead of per IP
+    pub fn from_env(routes: &[(&str, Limit)]) -> Self {
+        let mut config = RateLimitConfig {
+            default: Some(DEFAULT_LIMIT),
+            routes: routes
+                .iter()
+                .map(|(route, limit)| (route.to_string(), Some(*limit)))
+                .collect(),
+            by_subject: false,
+        };
+
+        if let Ok(limit) = std::env::var("RATE_LIMIT") {
+            match Limit::parse(&limit) {
+                Some(limit) => config.default = limit,
+                None => error!("Invalid RATE_LIMIT: {}", limit),
+            }
+        }
+
+        if let Ok(route_limits) = std::env::var("RATE_LIMIT_ROUTES") {
+            for route_limit in route_limits.split(',').filter(|r| !r.trim().is_empty()) {
+                match route_limit
+                    .rsplit_once('=')
+                    .and_then(|(route, limit)| Some((route.trim(), Limit::parse(limit)?)))
+                {
+                    Some((route, limit)) => {
+                        config.routes.insert(route.to_string(), limit);
+                    }
+                    None => error!("Invalid RATE_LIMIT_ROUTES entry: {}", route_limit),
+                }
+            }
+        }
+
+        config.by_subject = std::env::var("RATE_LIMIT_BY_SUBJECT")
+            .map(|by_subject| by_subject == "true" || by_subject == "1")
+            .unwrap_or(false);
+
+        config
+    }
+
+    fn limit(&self, route: &str) -> Option<Limit> {
+        match self.routes.get(route) {
+            Some(limit) => *limit,
+            None => self.default,
+        }
+    }
+}
+
+/// Counts the requests of a route, must be added with `Router::route_layer`.
+#[derive(Clone)]
+pub struct RateLimitLayer {
+    store: DynStore,
+    config: Arc<RateLimitConfig>,
+}
+
+impl RateLimitLayer {
+    pub fn new(store: DynStore, config: RateLimitConfig) -> Self {
+        RateLimitLayer {
+            store,
+            config: Arc::new(config),
+        }
+    }
+}
+
+impl<S> Layer<S> for RateLimitLayer {
+    type Service = RateLimitService<S>;
+
+    fn layer(&self, inner: S) -> Self::Service {
+        RateLimitService {
+            inner,
+            store: self.store.clone(),
+            config: self.config.clone(),
+        }
+    }
+}
+
+#[derive(Clone)]
+pub struct RateLimitService<S> {
+    inner: S,
+    store: DynStore,
+    config: Arc<RateLimitConfig>,
+}
+
+impl<S> Service<Request> for RateLimitService<S>
+where
+    S: Service<Request, Response = Response, Error = Infallible> + Clone + Send + 'static,
+    S::Future: Send + 'static,
+{
+    type Response = Response;
+    type Error = Infallible;
+    type Future = BoxFuture<'static, Result<Response, Infallible>>;
+
+    fn poll_ready(&mut self, cx: &mut Context<'_>) -> Poll<Result<(), Self::Error>> {
+        self.inner.poll_ready(cx)
+    }
+
+    fn call(&mut self, request: Request) -> Self::Future {
+        // the ready service handles the request, a clone waits for the next one
+        let clone = self.inner.clone();
+        let mut inner = std::mem::replace(&mut self.inner, clone);
+        let store = self.store.clone();
+        let config = self.config.clone();
+
+        Box::pin(async move {
+            let route = route_of(&request);
+            let limit = match config.limit(&route) {
+                Some(limit) => limit,
+                None => return inner.call(request).await,
+            };
+            let client = match client_of(&request, config.by_subject) {
+                Some(client) => client,
+                None => return inner.call(request).await,
+            };
+
+            // the limit is not enforced while the store is unavailable
+            let state = match hit(&store, &client, &route, limit).await {
+                Ok(state) => state,
+                Err(e) => {
+                    error!("Failed to check rate limit: {} ({})", &route, e);
+                    return inner.call(request).await;
+                }
+            };
+
+            let mut response = match state.is_limited() {
+                true => {
+                    let mut response =
+                        AppError::RateLimited("rate limit exceeded".to_string()).into_response();
+                    response
+                        .headers_mut()
+                        .insert(header::RETRY_AFTER, HeaderValue::from(state.reset));
+                    response
+                }
+                false => inner.call(request).await?,
+            };
+            state.add_headers(response.headers_mut());
+
+            Ok(response)
+        })
+    }
+}
+
+/// Counters of a client on a route.
+struct RateLimitState {
+    limit: u64,
+    /// Requests within the sliding window, including the current one.
+    count: u64,
+    /// Seconds until the current window ends.
+    reset: u64,
+}
+
+impl RateLimitState {
+    fn is_limited(&self) -> bool {
+        self.count > self.limit
+    }
+
+    fn add_headers(&self, headers: &mut HeaderMap) {
+        headers.insert(X_RATELIMIT_LIMIT, HeaderValue::from(self.limit));
+        headers.insert(
+            X_RATELIMIT_REMAINING,
+            HeaderValue::from(self.limit.saturating_sub(self.count)),
+        );
+        headers.insert(X_RATELIMIT_RESET, HeaderValue::from(self.reset));
+    }
+}
+
+/// Counts the request in the current window.
+/// The count of the previous window is weighted by how much of it still overlaps the sliding window.
+async fn hit(
+    store: &DynStore,
+    client: &str,
+    route: &str,
+    limit: Limit,
+) -> StoreResult<RateLimitState> {
+    let window = limit.window as u128 * 1000;
+    let now = crate::get_current_timestamp();
+    let window_nr = now / window;
+    let elapsed = now % window;
+
+    let key = keys::rate_limit(client, window_nr, route);
+    let current = store.incr_ex(&key, 2 * limit.window as i64).await?;
+
+    let previous_key = keys::rate_limit(client, window_nr.saturating_sub(1), route);
+    let previous = store
+        .get(&previous_key)
+        .await?
+        .and_then(|previous| String::from_utf8(previous).ok())
+        .and_then(|previous| previous.parse::<u128>().ok())
+        .unwrap_or(0);
+
+    let count = previous * (window - elapsed) / window + current.max(0) as u128;
+
+    Ok(RateLimitState {
+        limit: limit.requests,
+        count: count as u64,
+        reset: (window - elapsed).div_ceil(1000) as u64,
+    })
+}
+
+fn route_of(request: &Request) -> String {
+    let path = request
+        .extensions()
+        .get::<MatchedPath>()
+        .map(|path| path.as_str())
+        .unwrap_or(request.uri().path());
+
+    format!("{} {}", request.method(), path)
+}
+
+/// JWT subject of a bearer token if enabled, otherwise the IP.
+fn client_of(request: &Request, by_subject: bool) -> Option<String> {
+    if by_subject {
+        let subject = crate::get_header(request.headers(), "authorization")
+            .ok()
+            .filter(|auth| auth.starts_with("Bearer "))
+            .and_then(|_| crate::decode_jwt_from_header(request.headers()).ok())
+            .map(|claims| claims.sub);
+        if subject.is_some() {
+            return subject;
+        }
+    }
+
+    ClientIp::from_request(request.headers(), request.extensions()).map(|ip| ip.0.to_string())
+}
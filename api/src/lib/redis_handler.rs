@@ -1,412 +1,680 @@
-use axum::{extract::State, http::StatusCode};
-
-use redis::{aio::ConnectionManager, AsyncCommands};
+use axum::extract::State;
 
 use log::error;
-use serde_json::json;
+
+use crate::error::AppError;
+use crate::store::{DynStore, StoreCheck, StoreOp};
 
 const EXPIRATION_TIME: i64 = 300; // 5min
-const DB_ERROR_MSG: &str = "error connection to database";
 
-pub async fn expire(
-    mut rcm: State<ConnectionManager>,
-    ref key: &str,
-    seconds: Option<i64>,
-) -> Result<(), (StatusCode, String)> {
-    let mut exp_time = EXPIRATION_TIME;
+pub async fn expire(rcm: State<DynStore>, key: &str, seconds: Option<i64>) -> Result<(), AppError> {
+    match rcm.expire(key, seconds.unwrap_or(EXPIRATION_TIME)).await {
+        Ok(_) => Ok(()),
+        Err(e) => {
+            error!("expire: {}", e);
 
-    if seconds.is_some() {
-        exp_time = seconds.unwrap();
+            Err(AppError::Storage(e))
+        }
     }
+}
 
-    match rcm.expire::<&str, i64>(&key, exp_time).await {
+/// Refreshes the expiration of several keys in a single round trip.
+pub async fn expire_many(
+    rcm: State<DynStore>,
+    keys: &[String],
+    seconds: Option<i64>,
+) -> Result<(), AppError> {
+    match rcm
+        .expire_many(keys, seconds.unwrap_or(EXPIRATION_TIME))
+        .await
+    {
         Ok(_) => Ok(()),
         Err(e) => {
-            error!("expire: {:?}", e);
+            error!("expire_many: {}", e);
 
-            return Err((
-                StatusCode::INTERNAL_SERVER_ERROR,
-                json!({
-                    "success": false,
-                    "message": DB_ERROR_MSG
-                })
-                .to_string(),
-            ));
+            Err(AppError::Storage(e))
         }
     }
 }
 
-pub async fn exists(
-    mut rcm: State<ConnectionManager>,
-    ref key: &str,
-) -> Result<bool, (StatusCode, String)> {
-    match rcm.exists(&key).await {
+pub async fn exists(rcm: State<DynStore>, key: &str) -> Result<bool, AppError> {
+    match rcm.exists(key).await {
         Ok(v) => Ok(v),
         Err(e) => {
-            error!("exists: {:?}", e);
+            error!("exists: {}", e);
 
-            return Err((
-                StatusCode::INTERNAL_SERVER_ERROR,
-                json!({
-                    "success": false,
-                    "message": DB_ERROR_MSG
-                })
-                .to_string(),
-            ));
+            Err(AppError::Storage(e))
         }
     }
 }
 
 pub async fn set(
-    mut rcm: State<ConnectionManager>,
-    ref key: &str,
-    ref val: &str,
+    rcm: State<DynStore>,
+    key: &str,
+    val: &str,
     expiration_time: Option<i64>,
-) -> Result<(), (StatusCode, String)> {
-    let expiration_time = expiration_time.unwrap_or(EXPIRATION_TIME) as u64;
-
+) -> Result<(), AppError> {
     match rcm
-        .set_ex::<&str, &str, bool>(&key, &val, expiration_time)
+        .set(
+            key,
+            val.as_bytes(),
+            expiration_time.unwrap_or(EXPIRATION_TIME),
+        )
         .await
     {
         Ok(_) => Ok(()),
         Err(e) => {
-            error!("set: {:?}", e);
+            error!("set: {}", e);
+
+            Err(AppError::Storage(e))
+        }
+    }
+}
+
+/// Sets the key only if it does not exist yet.
+/// Returns whether the key was set.
+pub async fn set_nx(
+    rcm: State<DynStore>,
+    key: &str,
+    val: &str,
+    expiration_time: Option<i64>,
+) -> Result<bool, AppError> {
+    match rcm
+        .set_nx(
+            key,
+            val.as_bytes(),
+            expiration_time.unwrap_or(EXPIRATION_TIME),
+        )
+        .await
+    {
+        Ok(v) => Ok(v),
+        Err(e) => {
+            error!("set_nx: {}", e);
 
-            return Err((
-                StatusCode::INTERNAL_SERVER_ERROR,
-                json!({
-                    "success": false,
-                    "message": DB_ERROR_MSG
-                })
-                .to_string(),
-            ));
+            Err(AppError::Storage(e))
         }
     }
 }
 
 pub async fn incr(
-    mut rcm: State<ConnectionManager>,
-    ref key: &str,
+    rcm: State<DynStore>,
+    key: &str,
     expiration_time: Option<i64>,
-) -> Result<i64, (StatusCode, String)> {
-    match rcm.incr(&key, 1).await {
-        Ok(amount) => {
-            expire(rcm, &key, expiration_time).await?;
+) -> Result<i64, AppError> {
+    match rcm.incr(key).await {
+        Ok(v) => {
+            expire(rcm, key, expiration_time).await?;
 
-            return Ok(amount);
+            Ok(v)
         }
         Err(e) => {
-            error!("incr: {:?}", e);
+            error!("incr: {}", e);
 
-            return Err((
-                StatusCode::INTERNAL_SERVER_ERROR,
-                json!({
-                    "success": false,
-                    "message": DB_ERROR_MSG
-                })
-                .to_string(),
-            ));
+            Err(AppError::Storage(e))
         }
     }
 }
 
-pub async fn del(
-    mut rcm: State<ConnectionManager>,
-    ref key: &str,
-) -> Result<(), (StatusCode, String)> {
-    match rcm.del::<&str, String>(&key).await {
+pub async fn del(rcm: State<DynStore>, key: &str) -> Result<(), AppError> {
+    match rcm.del(key).await {
         Ok(_) => Ok(()),
         Err(e) => {
-            error!("del: {:?}", e);
+            error!("del: {}", e);
 
-            return Err((
-                StatusCode::INTERNAL_SERVER_ERROR,
-                json!({
-                    "success": false,
-                    "message": DB_ERROR_MSG
-                })
-                .to_string(),
-            ));
+            Err(AppError::Storage(e))
         }
     }
 }
 
-pub async fn get(
-    mut rcm: State<ConnectionManager>,
-    ref key: &str,
-) -> Result<String, (StatusCode, String)> {
-    if !exists(rcm.clone(), &key).await? {
-        return Ok("".to_string());
+/// Returns an empty string if the key does not exist.
+pub async fn get(rcm: State<DynStore>, key: &str) -> Result<String, AppError> {
+    match rcm.get(key).await {
+        Ok(v) => Ok(String::from_utf8_lossy(&v.unwrap_or_default()).to_string()),
+        Err(e) => {
+            error!("get: {}", e);
+
+            Err(AppError::Storage(e))
+        }
     }
+}
 
-    match rcm.get(&key).await {
-        Ok(v) => Ok(v),
+pub async fn set_bytes(
+    rcm: State<DynStore>,
+    key: &str,
+    val: &[u8],
+    expiration_time: Option<i64>,
+) -> Result<(), AppError> {
+    match rcm
+        .set(key, val, expiration_time.unwrap_or(EXPIRATION_TIME))
+        .await
+    {
+        Ok(_) => Ok(()),
         Err(e) => {
-            error!("get: {:?}", e);
+            error!("set_bytes: {}", e);
 
-            return Err((
-                StatusCode::INTERNAL_SERVER_ERROR,
-                json!({
-                    "success": false,
-                    "message": DB_ERROR_MSG
-                })
-                .to_string(),
-            ));
+            Err(AppError::Storage(e))
+        }
+    }
+}
+
+pub async fn get_bytes(rcm: State<DynStore>, key: &str) -> Result<Vec<u8>, AppError> {
+    match rcm.get(key).await {
+        Ok(v) => Ok(v.unwrap_or_default()),
+        Err(e) => {
+            error!("get_bytes: {}", e);
+
+            Err(AppError::Storage(e))
         }
     }
 }
 
 pub async fn sadd(
-    mut rcm: State<ConnectionManager>,
-    ref key: &str,
-    ref val: &str,
+    rcm: State<DynStore>,
+    key: &str,
+    val: &str,
     expiration_time: Option<i64>,
-) -> Result<(), (StatusCode, String)> {
-    match rcm.sadd::<&str, &str, String>(&key, &val).await {
+) -> Result<(), AppError> {
+    match rcm.sadd(key, val).await {
         Ok(_) => {
-            expire(rcm, &key, expiration_time).await?;
-            return Ok(());
+            expire(rcm, key, expiration_time).await?;
+
+            Ok(())
         }
         Err(e) => {
-            error!("sadd: {:?}", e);
+            error!("sadd: {}", e);
 
-            return Err((
-                StatusCode::INTERNAL_SERVER_ERROR,
-                json!({
-                    "success": false,
-                    "message": DB_ERROR_MSG
-                })
-                .to_string(),
-            ));
+            Err(AppError::Storage(e))
         }
     }
 }
 
-pub async fn sismember(
-    mut rcm: State<ConnectionManager>,
-    ref key: &str,
-    ref val: &str,
-) -> Result<bool, (StatusCode, String)> {
-    match rcm.sismember(&key, &val).await {
+pub async fn sismember(rcm: State<DynStore>, key: &str, val: &str) -> Result<bool, AppError> {
+    match rcm.sismember(key, val).await {
         Ok(v) => Ok(v),
         Err(e) => {
-            error!("sismember: {:?}", e);
+            error!("sismember: {}", e);
 
-            return Err((
-                StatusCode::INTERNAL_SERVER_ERROR,
-                json!({
-                    "success": false,
-                    "message": DB_ERROR_MSG
-                })
-                .to_string(),
-            ));
+            Err(AppError::Storage(e))
         }
     }
 }
 
-pub async fn smembers(
-    mut rcm: State<ConnectionManager>,
-    ref key: &str,
-) -> Result<Vec<String>, (StatusCode, String)> {
-    match rcm.smembers(&key).await {
+pub async fn smembers(rcm: State<DynStore>, key: &str) -> Result<Vec<String>, AppError> {
+    match rcm.smembers(key).await {
         Ok(v) => Ok(v),
         Err(e) => {
-            error!("smembers: {:?}", e);
+            error!("smembers: {}", e);
 
-            return Err((
-                StatusCode::INTERNAL_SERVER_ERROR,
-                json!({
-                    "success": false,
-                    "message": DB_ERROR_MSG
-                })
-                .to_string(),
-            ));
+            Err(AppError::Storage(e))
         }
     }
 }
 
-pub async fn srem(
-    mut rcm: State<ConnectionManager>,
-    ref key: &str,
-    ref val: &str,
-) -> Result<String, (StatusCode, String)> {
-    match rcm.srem(&key, &val).await {
-        Ok(v) => Ok(v),
+pub async fn srem(rcm: State<DynStore>, key: &str, val: &str) -> Result<(), AppError> {
+    match rcm.srem(key, val).await {
+        Ok(_) => Ok(()),
         Err(e) => {
-            error!("srem: {:?}", e);
+            error!("srem: {}", e);
 
-            return Err((
-                StatusCode::INTERNAL_SERVER_ERROR,
-                json!({
-                    "success": false,
-                    "message": DB_ERROR_MSG
-                })
-                .to_string(),
-            ));
+            Err(AppError::Storage(e))
         }
     }
 }
 
 pub async fn hset_multiple(
-    mut rcm: State<ConnectionManager>,
-    ref key: &str,
-    ref items: &[(&str, &str)],
+    rcm: State<DynStore>,
+    key: &str,
+    items: &[(&str, &str)],
     expiration_time: Option<i64>,
-) -> Result<(), (StatusCode, String)> {
-    match rcm
-        .hset_multiple::<&str, &str, &str, String>(&key, &items)
-        .await
-    {
+) -> Result<(), AppError> {
+    let items: Vec<(&str, &[u8])> = items
+        .iter()
+        .map(|(field, val)| (*field, val.as_bytes()))
+        .collect();
+
+    match rcm.hset(key, &items).await {
         Ok(_) => {
-            expire(rcm, &key, expiration_time).await?;
+            expire(rcm, key, expiration_time).await?;
 
-            return Ok(());
+            Ok(())
         }
         Err(e) => {
-            error!("hset_multiple: {:?}", e);
+            error!("hset_multiple: {}", e);
 
-            return Err((
-                StatusCode::INTERNAL_SERVER_ERROR,
-                json!({
-                    "success": false,
-                    "message": DB_ERROR_MSG
-                })
-                .to_string(),
-            ));
+            Err(AppError::Storage(e))
         }
     }
 }
 
-pub async fn hget(
-    mut rcm: State<ConnectionManager>,
-    ref key: &str,
-    ref field: &str,
-) -> Result<String, (StatusCode, String)> {
-    match rcm.hget(&key, &field).await {
-        Ok(v) => Ok(v),
+/// Fails if the field does not exist.
+pub async fn hget(rcm: State<DynStore>, key: &str, field: &str) -> Result<String, AppError> {
+    match rcm.hget(key, field).await {
+        Ok(Some(v)) => Ok(String::from_utf8_lossy(&v).to_string()),
+        Ok(None) => {
+            error!("hget: field {} of {} not found", field, key);
+
+            Err(AppError::Internal(format!(
+                "hget: field {} of {} not found",
+                field, key
+            )))
+        }
+        Err(e) => {
+            error!("hget: {}", e);
+
+            Err(AppError::Storage(e))
+        }
+    }
+}
+
+pub async fn hset_bytes(
+    rcm: State<DynStore>,
+    key: &str,
+    field: &str,
+    val: &[u8],
+    expiration_time: Option<i64>,
+) -> Result<(), AppError> {
+    match rcm.hset(key, &[(field, val)]).await {
+        Ok(_) => {
+            expire(rcm, key, expiration_time).await?;
+
+            Ok(())
+        }
         Err(e) => {
-            error!("hget: {:?}", e);
+            error!("hset_bytes: {}", e);
 
-            return Err((
-                StatusCode::INTERNAL_SERVER_ERROR,
-                json!({
-                    "success": false,
-                    "message": DB_ERROR_MSG
-                })
-                .to_string(),
-            ));
+            Err(AppError::Storage(e))
         }
     }
 }
 
-pub async fn hgetall(
-    mut rcm: State<ConnectionManager>,
-    ref key: &str,
-) -> Result<Vec<String>, (StatusCode, String)> {
-    match rcm.hgetall(&key).await {
+pub async fn hget_bytes(rcm: State<DynStore>, key: &str, field: &str) -> Result<Vec<u8>, AppError> {
+    match rcm.hget(key, field).await {
+        Ok(v) => Ok(v.unwrap_or_default()),
+        Err(e) => {
+            error!("hget_bytes: {}", e);
+
+            Err(AppError::Storage(e))
+        }
+    }
+}
+
+pub async fn hexists(rcm: State<DynStore>, key: &str, field: &str) -> Result<bool, AppError> {
+    match rcm.hexists(key, field).await {
         Ok(v) => Ok(v),
         Err(e) => {
-            error!("hgetall: {:?}", e);
+            error!("hexists: {}", e);
 
-            return Err((
-                StatusCode::INTERNAL_SERVER_ERROR,
-                json!({
-                    "success": false,
-                    "message": DB_ERROR_MSG
-                })
-                .to_string(),
-            ));
+            Err(AppError::Storage(e))
+        }
+    }
+}
+
+pub async fn hdel(rcm: State<DynStore>, key: &str, fields: &[String]) -> Result<(), AppError> {
+    if fields.is_empty() {
+        return Ok(());
+    }
+
+    match rcm.hdel(key, fields).await {
+        Ok(_) => Ok(()),
+        Err(e) => {
+            error!("hdel: {}", e);
+
+            Err(AppError::Storage(e))
+        }
+    }
+}
+
+/// Fields and values, alternating.
+pub async fn hgetall(rcm: State<DynStore>, key: &str) -> Result<Vec<String>, AppError> {
+    match rcm.hgetall(key).await {
+        Ok(v) => Ok(v
+            .into_iter()
+            .flat_map(|(field, val)| [field, String::from_utf8_lossy(&val).to_string()])
+            .collect()),
+        Err(e) => {
+            error!("hgetall: {}", e);
+
+            Err(AppError::Storage(e))
+        }
+    }
+}
+
+/// Field of several hashes in a single round trip, `None` for missing fields.
+pub async fn hget_many(
+    rcm: State<DynStore>,
+    keys: &[String],
+    field: &str,
+) -> Result<Vec<Option<String>>, AppError> {
+    match rcm.hget_many(keys, field).await {
+        Ok(v) => Ok(v
+            .into_iter()
+            .map(|val| val.map(|val| String::from_utf8_lossy(&val).to_string()))
+            .collect()),
+        Err(e) => {
+            error!("hget_many: {}", e);
+
+            Err(AppError::Storage(e))
+        }
+    }
+}
+
+/// Several hashes in a single round trip, each as fields and values, alternating.
+pub async fn hgetall_many(
+    rcm: State<DynStore>,
+    keys: &[String],
+) -> Result<Vec<Vec<String>>, AppError> {
+    match rcm.hgetall_many(keys).await {
+        Ok(v) => Ok(v
+            .into_iter()
+            .map(|hash| {
+                hash.into_iter()
+                    .flat_map(|(field, val)| [field, String::from_utf8_lossy(&val).to_string()])
+                    .collect()
+            })
+            .collect()),
+        Err(e) => {
+            error!("hgetall_many: {}", e);
+
+            Err(AppError::Storage(e))
         }
     }
 }
 
 pub async fn lpush(
-    mut rcm: State<ConnectionManager>,
-    ref key: &str,
-    ref val: &str,
+    rcm: State<DynStore>,
+    key: &str,
+    val: &str,
+    expiration_time: Option<i64>,
+) -> Result<(), AppError> {
+    match rcm.lpush(key, val).await {
+        Ok(_) => {
+            expire(rcm, key, expiration_time).await?;
+
+            Ok(())
+        }
+        Err(e) => {
+            error!("lpush: {}", e);
+
+            Err(AppError::Storage(e))
+        }
+    }
+}
+
+pub async fn rpush(
+    rcm: State<DynStore>,
+    key: &str,
+    val: &str,
     expiration_time: Option<i64>,
-) -> Result<(), (StatusCode, String)> {
-    match rcm.lpush::<&str, &str, String>(&key, &val).await {
+) -> Result<(), AppError> {
+    match rcm.rpush(key, val).await {
         Ok(_) => {
-            expire(rcm, &key, expiration_time).await?;
+            expire(rcm, key, expiration_time).await?;
 
-            return Ok(());
-        },
+            Ok(())
+        }
         Err(e) => {
-            error!("lpush: {:?}", e);
+            error!("rpush: {}", e);
 
-            return Err((
-                StatusCode::INTERNAL_SERVER_ERROR,
-                json!({
-                    "success": false,
-                    "message": DB_ERROR_MSG
-                })
-                .to_string(),
-            ));
+            Err(AppError::Storage(e))
         }
     }
 }
 
-pub async fn lpop(
-    mut rcm: State<ConnectionManager>,
-    ref key: &str,
-) -> Result<String, (StatusCode, String)> {
-    match rcm.lpop(&key, None).await {
+pub async fn lrange(rcm: State<DynStore>, key: &str) -> Result<Vec<String>, AppError> {
+    match rcm.lrange(key).await {
         Ok(v) => Ok(v),
         Err(e) => {
-            error!("lpop: {:?}", e);
+            error!("lrange: {}", e);
+
+            Err(AppError::Storage(e))
+        }
+    }
+}
+
+pub async fn lrem(rcm: State<DynStore>, key: &str, val: &str) -> Result<(), AppError> {
+    match rcm.lrem(key, val).await {
+        Ok(_) => Ok(()),
+        Err(e) => {
+            error!("lrem: {}", e);
 
-            return Err((
-                StatusCode::INTERNAL_SERVER_ERROR,
-                json!({
-                    "success": false,
-                    "message": DB_ERROR_MSG
-                })
-                .to_string(),
-            ));
+            Err(AppError::Storage(e))
         }
     }
 }
 
-pub async fn rpop(
-    mut rcm: State<ConnectionManager>,
-    ref key: &str,
-) -> Result<String, (StatusCode, String)> {
-    match rcm.rpop(&key, None).await {
+/// Fails if the list is empty.
+pub async fn lpop(rcm: State<DynStore>, key: &str) -> Result<String, AppError> {
+    match rcm.lpop(key).await {
+        Ok(Some(v)) => Ok(v),
+        Ok(None) => {
+            error!("lpop: {} is empty", key);
+
+            Err(AppError::Internal(format!("lpop: {} is empty", key)))
+        }
+        Err(e) => {
+            error!("lpop: {}", e);
+
+            Err(AppError::Storage(e))
+        }
+    }
+}
+
+/// Fails if the list is empty.
+pub async fn rpop(rcm: State<DynStore>, key: &str) -> Result<String, AppError> {
+    match rcm.rpop(key).await {
+        Ok(Some(v)) => Ok(v),
+        Ok(None) => {
+            error!("rpop: {} is empty", key);
+
+            Err(AppError::Internal(format!("rpop: {} is empty", key)))
+        }
+        Err(e) => {
+            error!("rpop: {}", e);
+
+            Err(AppError::Storage(e))
+        }
+    }
+}
+
+pub async fn llen(rcm: State<DynStore>, key: &str) -> Result<i64, AppError> {
+    match rcm.llen(key).await {
         Ok(v) => Ok(v),
         Err(e) => {
-            error!("rpop: {:?}", e);
+            error!("llen: {}", e);
+
+            Err(AppError::Storage(e))
+        }
+    }
+}
+
+pub async fn publish(rcm: State<DynStore>, channel: &str, message: &str) -> Result<(), AppError> {
+    match rcm.publish(channel, message).await {
+        Ok(_) => Ok(()),
+        Err(e) => {
+            error!("publish: {}", e);
 
-            return Err((
-                StatusCode::INTERNAL_SERVER_ERROR,
-                json!({
-                    "success": false,
-                    "message": DB_ERROR_MSG
-                })
-                .to_string(),
-            ));
+            Err(AppError::Storage(e))
         }
     }
 }
 
-pub async fn llen(
-    mut rcm: State<ConnectionManager>,
-    ref key: &str,
-) -> Result<i64, (StatusCode, String)> {
-    match rcm.llen(&key).await {
+/// Writes applied at once by [`exec_atomic`], if every check holds.
+/// Mirrors the functions of this module, including the expiration of the keys.
+#[derive(Clone, Default)]
+pub struct Transaction {
+    checks: Vec<StoreCheck>,
+    ops: Vec<StoreOp>,
+}
+
+impl Transaction {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn require_exists(&mut self, key: &str) -> &mut Self {
+        self.checks.push(StoreCheck::Exists {
+            key: key.to_string(),
+        });
+        self
+    }
+
+    pub fn require_not_exists(&mut self, key: &str) -> &mut Self {
+        self.checks.push(StoreCheck::NotExists {
+            key: key.to_string(),
+        });
+        self
+    }
+
+    pub fn require_member(&mut self, key: &str, val: &str) -> &mut Self {
+        self.checks.push(StoreCheck::SIsMember {
+            key: key.to_string(),
+            member: val.to_string(),
+        });
+        self
+    }
+
+    pub fn require_not_member(&mut self, key: &str, val: &str) -> &mut Self {
+        self.checks.push(StoreCheck::NotSIsMember {
+            key: key.to_string(),
+            member: val.to_string(),
+        });
+        self
+    }
+
+    /// Requires the set to hold no other member than `val`, or none.
+    pub fn require_only_member(&mut self, key: &str, val: &str) -> &mut Self {
+        self.checks.push(StoreCheck::SOnlyMember {
+            key: key.to_string(),
+            member: val.to_string(),
+        });
+        self
+    }
+
+    /// `None` requires the field to not exist.
+    pub fn require_hash_value(&mut self, key: &str, field: &str, val: Option<&str>) -> &mut Self {
+        self.checks.push(StoreCheck::HEquals {
+            key: key.to_string(),
+            field: field.to_string(),
+            val: val.map(|val| val.to_string()),
+        });
+        self
+    }
+
+    pub fn expire(&mut self, key: &str, seconds: Option<i64>) -> &mut Self {
+        self.ops.push(StoreOp::Expire {
+            key: key.to_string(),
+            seconds: seconds.unwrap_or(EXPIRATION_TIME),
+        });
+        self
+    }
+
+    pub fn set(&mut self, key: &str, val: &str, expiration_time: Option<i64>) -> &mut Self {
+        self.ops.push(StoreOp::Set {
+            key: key.to_string(),
+            val: val.as_bytes().to_vec(),
+            seconds: expiration_time.unwrap_or(EXPIRATION_TIME),
+        });
+        self
+    }
+
+    pub fn set_nx(&mut self, key: &str, val: &str, expiration_time: Option<i64>) -> &mut Self {
+        self.ops.push(StoreOp::SetNx {
+            key: key.to_string(),
+            val: val.as_bytes().to_vec(),
+            seconds: expiration_time.unwrap_or(EXPIRATION_TIME),
+        });
+        self
+    }
+
+    pub fn del(&mut self, key: &str) -> &mut Self {
+        self.ops.push(StoreOp::Del {
+            key: key.to_string(),
+        });
+        self
+    }
+
+    pub fn sadd(&mut self, key: &str, val: &str, expiration_time: Option<i64>) -> &mut Self {
+        self.ops.push(StoreOp::SAdd {
+            key: key.to_string(),
+            member: val.to_string(),
+        });
+        self.expire(key, expiration_time)
+    }
+
+    pub fn srem(&mut self, key: &str, val: &str) -> &mut Self {
+        self.ops.push(StoreOp::SRem {
+            key: key.to_string(),
+            member: val.to_string(),
+        });
+        self
+    }
+
+    pub fn hset_multiple(
+        &mut self,
+        key: &str,
+        items: &[(&str, &str)],
+        expiration_time: Option<i64>,
+    ) -> &mut Self {
+        self.ops.push(StoreOp::HSet {
+            key: key.to_string(),
+            items: items
+                .iter()
+                .map(|(field, val)| (field.to_string(), val.as_bytes().to_vec()))
+                .collect(),
+        });
+        self.expire(key, expiration_time)
+    }
+
+    pub fn hset_bytes(
+        &mut self,
+        key: &str,
+        field: &str,
+        val: &[u8],
+        expiration_time: Option<i64>,
+    ) -> &mut Self {
+        self.ops.push(StoreOp::HSet {
+            key: key.to_string(),
+            items: vec![(field.to_string(), val.to_vec())],
+        });
+        self.expire(key, expiration_time)
+    }
+
+    pub fn hdel(&mut self, key: &str, fields: &[String]) -> &mut Self {
+        if !fields.is_empty() {
+            self.ops.push(StoreOp::HDel {
+                key: key.to_string(),
+                fields: fields.to_vec(),
+            });
+        }
+        self
+    }
+
+    pub fn rpush(&mut self, key: &str, val: &str, expiration_time: Option<i64>) -> &mut Self {
+        self.ops.push(StoreOp::RPush {
+            key: key.to_string(),
+            val: val.to_string(),
+        });
+        self.expire(key, expiration_time)
+    }
+
+    pub fn lrem(&mut self, key: &str, val: &str) -> &mut Self {
+        self.ops.push(StoreOp::LRem {
+            key: key.to_string(),
+            val: val.to_string(),
+        });
+        self
+    }
+}
+
+/// Applies every write of the transaction or none of them (a Lua script on Redis).
+/// Returns `false` if a check of the transaction did not hold.
+pub async fn exec_atomic(rcm: State<DynStore>, transaction: Transaction) -> Result<bool, AppError> {
+    match rcm.exec_atomic(transaction.checks, transaction.ops).await {
         Ok(v) => Ok(v),
         Err(e) => {
-            error!("llen: {:?}", e);
+            error!("exec_atomic: {}", e);
 
-            return Err((
-                StatusCode::INTERNAL_SERVER_ERROR,
-                json!({
-                    "success": false,
-                    "message": DB_ERROR_MSG
-                })
-                .to_string(),
-            ));
+            Err(AppError::Storage(e))
         }
     }
-}
\ No newline at end of file
+}
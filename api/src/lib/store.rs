@@ -0,0 +1,171 @@
+pub mod memory_store;
+pub mod redis_config;
+pub mod redis_store;
+
+use std::fmt;
+use std::sync::Arc;
+
+use async_trait::async_trait;
+use futures::stream::BoxStream;
+
+/// Store shared by all handlers.
+pub type DynStore = Arc<dyn Store>;
+
+#[derive(Debug)]
+pub enum StoreError {
+    Redis(redis::RedisError),
+    /// Error of the in-memory store.
+    Memory(String),
+}
+
+impl fmt::Display for StoreError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            StoreError::Redis(e) => write!(f, "{}", e),
+            StoreError::Memory(message) => write!(f, "{}", message),
+        }
+    }
+}
+
+impl std::error::Error for StoreError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            StoreError::Redis(e) => Some(e),
+            StoreError::Memory(_) => None,
+        }
+    }
+}
+
+pub type StoreResult<T> = Result<T, StoreError>;
+
+/// Single write of an atomic batch, see [`Store::exec_atomic`].
+#[derive(Clone, Debug)]
+pub enum StoreOp {
+    Set {
+        key: String,
+        val: Vec<u8>,
+        seconds: i64,
+    },
+    /// Sets the key only if it does not exist yet.
+    SetNx {
+        key: String,
+        val: Vec<u8>,
+        seconds: i64,
+    },
+    Del {
+        key: String,
+    },
+    Expire {
+        key: String,
+        seconds: i64,
+    },
+    SAdd {
+        key: String,
+        member: String,
+    },
+    SRem {
+        key: String,
+        member: String,
+    },
+    HSet {
+        key: String,
+        items: Vec<(String, Vec<u8>)>,
+    },
+    HDel {
+        key: String,
+        fields: Vec<String>,
+    },
+    RPush {
+        key: String,
+        val: String,
+    },
+    LRem {
+        key: String,
+        val: String,
+    },
+}
+
+/// Precondition of an atomic batch, see [`Store::exec_atomic`].
+#[derive(Clone, Debug)]
+pub enum StoreCheck {
+    Exists {
+        key: String,
+    },
+    NotExists {
+        key: String,
+    },
+    SIsMember {
+        key: String,
+        member: String,
+    },
+    NotSIsMember {
+        key: String,
+        member: String,
+    },
+    /// The set holds no other member, it may be empty.
+    SOnlyMember {
+        key: String,
+        member: String,
+    },
+    /// The field holds the value, `None` if it must not exist.
+    HEquals {
+        key: String,
+        field: String,
+        val: Option<String>,
+    },
+}
+
+/// Key value store holding the state of the sessions and transfers.
+///
+/// Mirrors the subset of Redis used by the application.
+/// Keys expire after the amount of seconds given to `set` or `expire`,
+/// other writes keep the expiration of an existing key.
+#[async_trait]
+pub trait Store: Send + Sync {
+    async fn expire(&self, key: &str, seconds: i64) -> StoreResult<()>;
+    /// Pipelined `expire` of several keys.
+    async fn expire_many(&self, keys: &[String], seconds: i64) -> StoreResult<()>;
+    async fn exists(&self, key: &str) -> StoreResult<bool>;
+    async fn del(&self, key: &str) -> StoreResult<()>;
+
+    async fn set(&self, key: &str, val: &[u8], seconds: i64) -> StoreResult<()>;
+    /// Returns whether the key was set.
+    async fn set_nx(&self, key: &str, val: &[u8], seconds: i64) -> StoreResult<bool>;
+    async fn get(&self, key: &str) -> StoreResult<Option<Vec<u8>>>;
+    async fn incr(&self, key: &str) -> StoreResult<i64>;
+    /// `incr` that also sets the expiration, in one step.
+    async fn incr_ex(&self, key: &str, seconds: i64) -> StoreResult<i64>;
+
+    async fn sadd(&self, key: &str, member: &str) -> StoreResult<()>;
+    async fn sismember(&self, key: &str, member: &str) -> StoreResult<bool>;
+    async fn smembers(&self, key: &str) -> StoreResult<Vec<String>>;
+    async fn srem(&self, key: &str, member: &str) -> StoreResult<()>;
+
+    async fn hset(&self, key: &str, items: &[(&str, &[u8])]) -> StoreResult<()>;
+    async fn hget(&self, key: &str, field: &str) -> StoreResult<Option<Vec<u8>>>;
+    async fn hexists(&self, key: &str, field: &str) -> StoreResult<bool>;
+    async fn hdel(&self, key: &str, fields: &[String]) -> StoreResult<()>;
+    async fn hgetall(&self, key: &str) -> StoreResult<Vec<(String, Vec<u8>)>>;
+    /// Pipelined `hget` of the same field of several keys, in the order of the keys.
+    async fn hget_many(&self, keys: &[String], field: &str) -> StoreResult<Vec<Option<Vec<u8>>>>;
+    /// Pipelined `hgetall` of several keys, in the order of the keys.
+    async fn hgetall_many(&self, keys: &[String]) -> StoreResult<Vec<Vec<(String, Vec<u8>)>>>;
+
+    async fn lpush(&self, key: &str, val: &str) -> StoreResult<()>;
+    async fn rpush(&self, key: &str, val: &str) -> StoreResult<()>;
+    async fn lpop(&self, key: &str) -> StoreResult<Option<String>>;
+    async fn rpop(&self, key: &str) -> StoreResult<Option<String>>;
+    async fn llen(&self, key: &str) -> StoreResult<i64>;
+    async fn lrange(&self, key: &str) -> StoreResult<Vec<String>>;
+    async fn lrem(&self, key: &str, val: &str) -> StoreResult<()>;
+
+    /// Applies every operation if every check holds, without other operations in between.
+    /// Returns whether the operations were applied.
+    async fn exec_atomic(&self, checks: Vec<StoreCheck>, ops: Vec<StoreOp>) -> StoreResult<bool>;
+
+    async fn publish(&self, channel: &str, message: &str) -> StoreResult<()>;
+    /// Messages `(channel, payload)` of every channel matching the pattern.
+    /// Only a trailing `*` wildcard is supported.
+    /// If messages were dropped, the pattern itself is sent as channel with an empty payload.
+    async fn psubscribe(&self, pattern: &str) -> StoreResult<BoxStream<'static, (String, String)>>;
+}
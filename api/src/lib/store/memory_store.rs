@@ -0,0 +1,680 @@
+use std::collections::{HashMap, HashSet, VecDeque};
+use std::sync::{Arc, RwLock, Weak};
+use std::time::{Duration, Instant};
+
+use async_trait::async_trait;
+use dashmap::DashMap;
+use futures::stream::{self, BoxStream, StreamExt};
+use log::warn;
+use tokio::sync::broadcast;
+
+use super::{Store, StoreCheck, StoreError, StoreOp, StoreResult};
+
+const SWEEP_INTERVAL: Duration = Duration::from_secs(1);
+const EVENT_CAPACITY: usize = 1024;
+
+enum Value {
+    String(Vec<u8>),
+    Set(HashSet<String>),
+    Hash(HashMap<String, Vec<u8>>),
+    List(VecDeque<String>),
+}
+
+impl Value {
+    fn is_empty(&self) -> bool {
+        match self {
+            Value::String(_) => false,
+            Value::Set(set) => set.is_empty(),
+            Value::Hash(hash) => hash.is_empty(),
+            Value::List(list) => list.is_empty(),
+        }
+    }
+}
+
+struct Entry {
+    value: Value,
+    expires_at: Option<Instant>,
+}
+
+impl Entry {
+    fn is_expired(&self, now: Instant) -> bool {
+        self.expires_at.is_some_and(|expires_at| expires_at <= now)
+    }
+}
+
+fn wrong_type(key: &str) -> StoreError {
+    StoreError::Memory(format!(
+        "WRONGTYPE operation against a key holding the wrong kind of value: {}",
+        key
+    ))
+}
+
+/// Store keeping all state in memory, for development and tests.
+///
+/// Behaves like Redis for the operations the application uses:
+/// keys expire, empty sets, hashes and lists are removed
+/// and published messages are delivered to every matching subscriber.
+pub struct MemoryStore {
+    entries: DashMap<String, Entry>,
+    /// Single operations share the lock, atomic batches hold it exclusively.
+    lock: RwLock<()>,
+    events: broadcast::Sender<(String, String)>,
+}
+
+impl MemoryStore {
+    /// Creates the store and, within a tokio runtime, a task removing expired keys.
+    pub fn new() -> Arc<Self> {
+        let (events, _) = broadcast::channel(EVENT_CAPACITY);
+        let store = Arc::new(MemoryStore {
+            entries: DashMap::new(),
+            lock: RwLock::new(()),
+            events,
+        });
+
+        if tokio::runtime::Handle::try_current().is_ok() {
+            tokio::spawn(sweep(Arc::downgrade(&store)));
+        }
+
+        store
+    }
+
+    /// Keys that have not expired yet.
+    pub fn keys(&self) -> Vec<String> {
+        let now = Instant::now();
+        self.entries
+            .iter()
+            .filter(|entry| !entry.value().is_expired(now))
+            .map(|entry| entry.key().clone())
+            .collect()
+    }
+
+    fn remove_expired(&self) {
+        let now = Instant::now();
+        self.entries.retain(|_, entry| !entry.is_expired(now));
+    }
+
+    fn read<T>(
+        &self,
+        key: &str,
+        f: impl FnOnce(&Value) -> StoreResult<T>,
+    ) -> StoreResult<Option<T>> {
+        self.entries
+            .remove_if(key, |_, entry| entry.is_expired(Instant::now()));
+
+        match self.entries.get(key) {
+            Some(entry) => f(&entry.value).map(Some),
+            None => Ok(None),
+        }
+    }
+
+    /// Applies `f` to the value of the key, created with `init` if missing.
+    /// The key is removed if `f` leaves an empty collection behind.
+    fn write<T>(
+        &self,
+        key: &str,
+        init: impl FnOnce() -> Value,
+        f: impl FnOnce(&mut Value) -> StoreResult<T>,
+    ) -> StoreResult<T> {
+        self.entries
+            .remove_if(key, |_, entry| entry.is_expired(Instant::now()));
+
+        let result = {
+            let mut entry = self
+                .entries
+                .entry(key.to_string())
+                .or_insert_with(|| Entry {
+                    value: init(),
+                    expires_at: None,
+                });
+            f(&mut entry.value)
+        };
+
+        self.entries
+            .remove_if(key, |_, entry| entry.value.is_empty());
+
+        result
+    }
+
+    /// Like `write`, but leaves missing keys alone.
+    fn modify<T: Default>(
+        &self,
+        key: &str,
+        f: impl FnOnce(&mut Value) -> StoreResult<T>,
+    ) -> StoreResult<T> {
+        self.entries
+            .remove_if(key, |_, entry| entry.is_expired(Instant::now()));
+
+        let result = match self.entries.get_mut(key) {
+            Some(mut entry) => f(&mut entry.value),
+            None => return Ok(T::default()),
+        };
+
+        self.entries
+            .remove_if(key, |_, entry| entry.value.is_empty());
+
+        result
+    }
+
+    fn set_string(&self, key: &str, val: &[u8], seconds: i64) {
+        self.entries.insert(
+            key.to_string(),
+            Entry {
+                value: Value::String(val.to_vec()),
+                expires_at: Some(Instant::now() + Duration::from_secs(seconds.max(0) as u64)),
+            },
+        );
+    }
+
+    fn expire_key(&self, key: &str, seconds: i64) {
+        if seconds <= 0 {
+            self.entries.remove(key);
+            return;
+        }
+
+        if let Some(mut entry) = self.entries.get_mut(key) {
+            entry.expires_at = Some(Instant::now() + Duration::from_secs(seconds as u64));
+        }
+    }
+
+    fn incr_key(&self, key: &str) -> StoreResult<i64> {
+        self.write(
+            key,
+            || Value::String(b"0".to_vec()),
+            |value| match value {
+                Value::String(val) => {
+                    let amount = std::str::from_utf8(val)
+                        .ok()
+                        .and_then(|val| val.parse::<i64>().ok())
+                        .ok_or(StoreError::Memory("value is not an integer".to_string()))?
+                        + 1;
+                    *val = amount.to_string().into_bytes();
+                    Ok(amount)
+                }
+                _ => Err(wrong_type(key)),
+            },
+        )
+    }
+
+    fn sadd_member(&self, key: &str, member: &str) -> StoreResult<()> {
+        self.write(
+            key,
+            || Value::Set(HashSet::new()),
+            |value| match value {
+                Value::Set(set) => {
+                    set.insert(member.to_string());
+                    Ok(())
+                }
+                _ => Err(wrong_type(key)),
+            },
+        )
+    }
+
+    fn srem_member(&self, key: &str, member: &str) -> StoreResult<()> {
+        self.modify(key, |value| match value {
+            Value::Set(set) => {
+                set.remove(member);
+                Ok(())
+            }
+            _ => Err(wrong_type(key)),
+        })
+    }
+
+    fn hset_items<'a>(
+        &self,
+        key: &str,
+        items: impl Iterator<Item = (&'a str, &'a [u8])>,
+    ) -> StoreResult<()> {
+        self.write(
+            key,
+            || Value::Hash(HashMap::new()),
+            |value| match value {
+                Value::Hash(hash) => {
+                    for (field, val) in items {
+                        hash.insert(field.to_string(), val.to_vec());
+                    }
+                    Ok(())
+                }
+                _ => Err(wrong_type(key)),
+            },
+        )
+    }
+
+    fn hdel_fields(&self, key: &str, fields: &[String]) -> StoreResult<()> {
+        self.modify(key, |value| match value {
+            Value::Hash(hash) => {
+                for field in fields {
+                    hash.remove(field);
+                }
+                Ok(())
+            }
+            _ => Err(wrong_type(key)),
+        })
+    }
+
+    fn rpush_val(&self, key: &str, val: &str) -> StoreResult<()> {
+        self.write(
+            key,
+            || Value::List(VecDeque::new()),
+            |value| match value {
+                Value::List(list) => {
+                    list.push_back(val.to_string());
+                    Ok(())
+                }
+                _ => Err(wrong_type(key)),
+            },
+        )
+    }
+
+    fn lrem_val(&self, key: &str, val: &str) -> StoreResult<()> {
+        self.modify(key, |value| match value {
+            Value::List(list) => {
+                list.retain(|item| item != val);
+                Ok(())
+            }
+            _ => Err(wrong_type(key)),
+        })
+    }
+
+    fn holds(&self, check: &StoreCheck) -> StoreResult<bool> {
+        match check {
+            StoreCheck::Exists { key } => Ok(self.read(key, |_| Ok(()))?.is_some()),
+            StoreCheck::NotExists { key } => Ok(self.read(key, |_| Ok(()))?.is_none()),
+            StoreCheck::SIsMember { key, member } | StoreCheck::NotSIsMember { key, member } => {
+                let is_member = self
+                    .read(key, |value| match value {
+                        Value::Set(set) => Ok(set.contains(member)),
+                        _ => Err(wrong_type(key)),
+                    })?
+                    .unwrap_or(false);
+
+                Ok(is_member == matches!(check, StoreCheck::SIsMember { .. }))
+            }
+            StoreCheck::SOnlyMember { key, member } => Ok(self
+                .read(key, |value| match value {
+                    Value::Set(set) => Ok(set.iter().all(|other| other == member)),
+                    _ => Err(wrong_type(key)),
+                })?
+                .unwrap_or(true)),
+            StoreCheck::HEquals { key, field, val } => {
+                let current = self
+                    .read(key, |value| match value {
+                        Value::Hash(hash) => Ok(hash.get(field).cloned()),
+                        _ => Err(wrong_type(key)),
+                    })?
+                    .flatten();
+
+                Ok(current.as_deref() == val.as_ref().map(|val| val.as_bytes()))
+            }
+        }
+    }
+
+    /// Checks that an operation of a batch cannot fail, so a batch is never applied partially.
+    fn check_op(&self, op: &StoreOp) -> StoreResult<()> {
+        let (key, matches): (&str, fn(&Value) -> bool) = match op {
+            StoreOp::Set { .. }
+            | StoreOp::SetNx { .. }
+            | StoreOp::Del { .. }
+            | StoreOp::Expire { .. } => return Ok(()),
+            StoreOp::SAdd { key, .. } | StoreOp::SRem { key, .. } => {
+                (key, |value| matches!(value, Value::Set(_)))
+            }
+            StoreOp::HSet { key, .. } | StoreOp::HDel { key, .. } => {
+                (key, |value| matches!(value, Value::Hash(_)))
+            }
+            StoreOp::RPush { key, .. } | StoreOp::LRem { key, .. } => {
+                (key, |value| matches!(value, Value::List(_)))
+            }
+        };
+
+        match self.read(key, |value| Ok(matches(value)))? {
+            Some(false) => Err(wrong_type(key)),
+            _ => Ok(()),
+        }
+    }
+
+    fn apply_op(&self, op: StoreOp) -> StoreResult<()> {
+        match op {
+            StoreOp::Set { key, val, seconds } => {
+                self.set_string(&key, &val, seconds);
+                Ok(())
+            }
+            StoreOp::SetNx { key, val, seconds } => {
+                if self.read(&key, |_| Ok(()))?.is_none() {
+                    self.set_string(&key, &val, seconds);
+                }
+                Ok(())
+            }
+            StoreOp::Del { key } => {
+                self.entries.remove(&key);
+                Ok(())
+            }
+            StoreOp::Expire { key, seconds } => {
+                self.expire_key(&key, seconds);
+                Ok(())
+            }
+            StoreOp::SAdd { key, member } => self.sadd_member(&key, &member),
+            StoreOp::SRem { key, member } => self.srem_member(&key, &member),
+            StoreOp::HSet { key, items } => self.hset_items(
+                &key,
+                items
+                    .iter()
+                    .map(|(field, val)| (field.as_str(), val.as_slice())),
+            ),
+            StoreOp::HDel { key, fields } => self.hdel_fields(&key, &fields),
+            StoreOp::RPush { key, val } => self.rpush_val(&key, &val),
+            StoreOp::LRem { key, val } => self.lrem_val(&key, &val),
+        }
+    }
+}
+
+async fn sweep(store: Weak<MemoryStore>) {
+    let mut interval = tokio::time::interval(SWEEP_INTERVAL);
+    loop {
+        interval.tick().await;
+
+        match store.upgrade() {
+            Some(store) => store.remove_expired(),
+            None => return,
+        }
+    }
+}
+
+/// Only a trailing `*` is treated as a wildcard.
+fn matches_pattern(pattern: &str, channel: &str) -> bool {
+    match pattern.strip_suffix('*') {
+        Some(prefix) => channel.starts_with(prefix),
+        None => channel == pattern,
+    }
+}
+
+macro_rules! shared {
+    ($store:expr) => {
+        $store
+            .lock
+            .read()
+            .map_err(|_| StoreError::Memory("store lock poisoned".to_string()))?
+    };
+}
+
+#[async_trait]
+impl Store for MemoryStore {
+    async fn expire(&self, key: &str, seconds: i64) -> StoreResult<()> {
+        let _guard = shared!(self);
+        self.entries
+            .remove_if(key, |_, entry| entry.is_expired(Instant::now()));
+        self.expire_key(key, seconds);
+        Ok(())
+    }
+
+    async fn expire_many(&self, keys: &[String], seconds: i64) -> StoreResult<()> {
+        for key in keys {
+            self.expire(key, seconds).await?;
+        }
+        Ok(())
+    }
+
+    async fn exists(&self, key: &str) -> StoreResult<bool> {
+        let _guard = shared!(self);
+        Ok(self.read(key, |_| Ok(()))?.is_some())
+    }
+
+    async fn del(&self, key: &str) -> StoreResult<()> {
+        let _guard = shared!(self);
+        self.entries.remove(key);
+        Ok(())
+    }
+
+    async fn set(&self, key: &str, val: &[u8], seconds: i64) -> StoreResult<()> {
+        let _guard = shared!(self);
+        self.set_string(key, val, seconds);
+        Ok(())
+    }
+
+    async fn set_nx(&self, key: &str, val: &[u8], seconds: i64) -> StoreResult<bool> {
+        let _guard = shared!(self);
+        self.entries
+            .remove_if(key, |_, entry| entry.is_expired(Instant::now()));
+
+        match self.entries.entry(key.to_string()) {
+            dashmap::mapref::entry::Entry::Occupied(_) => Ok(false),
+            dashmap::mapref::entry::Entry::Vacant(entry) => {
+                entry.insert(Entry {
+                    value: Value::String(val.to_vec()),
+                    expires_at: Some(Instant::now() + Duration::from_secs(seconds.max(0) as u64)),
+                });
+                Ok(true)
+            }
+        }
+    }
+
+    async fn get(&self, key: &str) -> StoreResult<Option<Vec<u8>>> {
+        let _guard = shared!(self);
+        self.read(key, |value| match value {
+            Value::String(val) => Ok(val.clone()),
+            _ => Err(wrong_type(key)),
+        })
+    }
+
+    async fn incr(&self, key: &str) -> StoreResult<i64> {
+        let _guard = shared!(self);
+        self.incr_key(key)
+    }
+
+    async fn incr_ex(&self, key: &str, seconds: i64) -> StoreResult<i64> {
+        let _guard = shared!(self);
+        let amount = self.incr_key(key)?;
+        self.expire_key(key, seconds);
+        Ok(amount)
+    }
+
+    async fn sadd(&self, key: &str, member: &str) -> StoreResult<()> {
+        let _guard = shared!(self);
+        self.sadd_member(key, member)
+    }
+
+    async fn sismember(&self, key: &str, member: &str) -> StoreResult<bool> {
+        let _guard = shared!(self);
+        let is_member = self.read(key, |value| match value {
+            Value::Set(set) => Ok(set.contains(member)),
+            _ => Err(wrong_type(key)),
+        })?;
+
+        Ok(is_member.unwrap_or(false))
+    }
+
+    async fn smembers(&self, key: &str) -> StoreResult<Vec<String>> {
+        let _guard = shared!(self);
+        let members = self.read(key, |value| match value {
+            Value::Set(set) => Ok(set.iter().cloned().collect()),
+            _ => Err(wrong_type(key)),
+        })?;
+
+        Ok(members.unwrap_or_default())
+    }
+
+    async fn srem(&self, key: &str, member: &str) -> StoreResult<()> {
+        let _guard = shared!(self);
+        self.srem_member(key, member)
+    }
+
+    async fn hset(&self, key: &str, items: &[(&str, &[u8])]) -> StoreResult<()> {
+        let _guard = shared!(self);
+        self.hset_items(key, items.iter().copied())
+    }
+
+    async fn hget(&self, key: &str, field: &str) -> StoreResult<Option<Vec<u8>>> {
+        let _guard = shared!(self);
+        let val = self.read(key, |value| match value {
+            Value::Hash(hash) => Ok(hash.get(field).cloned()),
+            _ => Err(wrong_type(key)),
+        })?;
+
+        Ok(val.flatten())
+    }
+
+    async fn hexists(&self, key: &str, field: &str) -> StoreResult<bool> {
+        let _guard = shared!(self);
+        let exists = self.read(key, |value| match value {
+            Value::Hash(hash) => Ok(hash.contains_key(field)),
+            _ => Err(wrong_type(key)),
+        })?;
+
+        Ok(exists.unwrap_or(false))
+    }
+
+    async fn hdel(&self, key: &str, fields: &[String]) -> StoreResult<()> {
+        let _guard = shared!(self);
+        self.hdel_fields(key, fields)
+    }
+
+    async fn hgetall(&self, key: &str) -> StoreResult<Vec<(String, Vec<u8>)>> {
+        let _guard = shared!(self);
+        let items = self.read(key, |value| match value {
+            Value::Hash(hash) => Ok(hash
+                .iter()
+                .map(|(field, val)| (field.clone(), val.clone()))
+                .collect()),
+            _ => Err(wrong_type(key)),
+        })?;
+
+        Ok(items.unwrap_or_default())
+    }
+
+    async fn hget_many(&self, keys: &[String], field: &str) -> StoreResult<Vec<Option<Vec<u8>>>> {
+        let mut vals = Vec::with_capacity(keys.len());
+        for key in keys {
+            vals.push(self.hget(key, field).await?);
+        }
+        Ok(vals)
+    }
+
+    async fn hgetall_many(&self, keys: &[String]) -> StoreResult<Vec<Vec<(String, Vec<u8>)>>> {
+        let mut hashes = Vec::with_capacity(keys.len());
+        for key in keys {
+            hashes.push(self.hgetall(key).await?);
+        }
+        Ok(hashes)
+    }
+
+    async fn lpush(&self, key: &str, val: &str) -> StoreResult<()> {
+        let _guard = shared!(self);
+        self.write(
+            key,
+            || Value::List(VecDeque::new()),
+            |value| match value {
+                Value::List(list) => {
+                    list.push_front(val.to_string());
+                    Ok(())
+                }
+                _ => Err(wrong_type(key)),
+            },
+        )
+    }
+
+    async fn rpush(&self, key: &str, val: &str) -> StoreResult<()> {
+        let _guard = shared!(self);
+        self.rpush_val(key, val)
+    }
+
+    async fn lpop(&self, key: &str) -> StoreResult<Option<String>> {
+        let _guard = shared!(self);
+        self.modify(key, |value| match value {
+            Value::List(list) => Ok(list.pop_front()),
+            _ => Err(wrong_type(key)),
+        })
+    }
+
+    async fn rpop(&self, key: &str) -> StoreResult<Option<String>> {
+        let _guard = shared!(self);
+        self.modify(key, |value| match value {
+            Value::List(list) => Ok(list.pop_back()),
+            _ => Err(wrong_type(key)),
+        })
+    }
+
+    async fn llen(&self, key: &str) -> StoreResult<i64> {
+        let _guard = shared!(self);
+        let len = self.read(key, |value| match value {
+            Value::List(list) => Ok(list.len() as i64),
+            _ => Err(wrong_type(key)),
+        })?;
+
+        Ok(len.unwrap_or(0))
+    }
+
+    async fn lrange(&self, key: &str) -> StoreResult<Vec<String>> {
+        let _guard = shared!(self);
+        let list = self.read(key, |value| match value {
+            Value::List(list) => Ok(list.iter().cloned().collect()),
+            _ => Err(wrong_type(key)),
+        })?;
+
+        Ok(list.unwrap_or_default())
+    }
+
+    async fn lrem(&self, key: &str, val: &str) -> StoreResult<()> {
+        let _guard = shared!(self);
+        self.lrem_val(key, val)
+    }
+
+    async fn exec_atomic(&self, checks: Vec<StoreCheck>, ops: Vec<StoreOp>) -> StoreResult<bool> {
+        let _guard = self
+            .lock
+            .write()
+            .map_err(|_| StoreError::Memory("store lock poisoned".to_string()))?;
+
+        for check in &checks {
+            if !self.holds(check)? {
+                return Ok(false);
+            }
+        }
+
+        for op in &ops {
+            self.check_op(op)?;
+        }
+
+        for op in ops {
+            self.apply_op(op)?;
+        }
+
+        Ok(true)
+    }
+
+    async fn publish(&self, channel: &str, message: &str) -> StoreResult<()> {
+        // no subscribers is not an error
+        let _ = self.events.send((channel.to_string(), message.to_string()));
+        Ok(())
+    }
+
+    async fn psubscribe(&self, pattern: &str) -> StoreResult<BoxStream<'static, (String, String)>> {
+        let pattern = pattern.to_string();
+        let receiver = self.events.subscribe();
+
+        let messages = stream::unfold(receiver, move |mut receiver| {
+            let pattern = pattern.clone();
+            async move {
+                loop {
+                    match receiver.recv().await {
+                        Ok((channel, message)) => {
+                            if matches_pattern(&pattern, &channel) {
+                                return Some(((channel, message), receiver));
+                            }
+                        }
+                        // the subscriber does not know what it missed and has to resync
+                        Err(broadcast::error::RecvError::Lagged(skipped)) => {
+                            warn!(
+                                "psubscribe: {} lagged behind by {} messages",
+                                pattern, skipped
+                            );
+
+                            return Some(((pattern, String::new()), receiver));
+                        }
+                        Err(broadcast::error::RecvError::Closed) => return None,
+                    }
+                }
+            }
+        });
+
+        Ok(messages.boxed())
+    }
+}
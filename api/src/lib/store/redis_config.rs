@@ -0,0 +1,283 @@
+use redis::{
+    aio::{ConnectionManager, ConnectionManagerConfig},
+    cluster::ClusterClientBuilder,
+    sentinel::{Sentinel, SentinelNodeConnectionInfo},
+    ConnectionAddr, ConnectionInfo, ErrorKind, IntoConnectionInfo, RedisConnectionInfo, RedisError,
+    RedisResult, TlsMode,
+};
+
+use super::redis_store::{RedisStore, SentinelMaster};
+
+const DEFAULT_HOST: &str = "database";
+const DEFAULT_PORT: u16 = 6379;
+
+/// How the Redis deployment is reached.
+#[derive(Clone, Debug)]
+pub enum RedisTopology {
+    Standalone,
+    /// The master of the set is resolved through the sentinels, again after a failover.
+    Sentinel {
+        master_name: String,
+        nodes: Vec<String>,
+    },
+    Cluster {
+        nodes: Vec<String>,
+    },
+}
+
+/// Connection settings of Redis.
+///
+/// Nodes are given as `host:port` and share the credentials, db and TLS settings,
+/// or as full connection URLs.
+#[derive(Clone, Debug)]
+pub struct RedisConfig {
+    /// Full connection URL, takes precedence over host, port, db and credentials.
+    pub url: Option<String>,
+    pub host: String,
+    pub port: u16,
+    pub db: i64,
+    pub username: Option<String>,
+    pub password: Option<String>,
+    pub tls: bool,
+    /// Skips the verification of the server certificate.
+    pub tls_insecure: bool,
+    pub topology: RedisTopology,
+}
+
+impl Default for RedisConfig {
+    fn default() -> Self {
+        RedisConfig {
+            url: None,
+            host: DEFAULT_HOST.to_string(),
+            port: DEFAULT_PORT,
+            db: 0,
+            username: None,
+            password: None,
+            tls: false,
+            tls_insecure: false,
+            topology: RedisTopology::Standalone,
+        }
+    }
+}
+
+fn invalid_config(desc: &'static str, detail: String) -> RedisError {
+    RedisError::from((ErrorKind::InvalidClientConfig, desc, detail))
+}
+
+fn env_var(name: &str) -> Option<String> {
+    match std::env::var(name) {
+        Ok(v) if !v.trim().is_empty() => Some(v.trim().to_string()),
+        _ => None,
+    }
+}
+
+fn env_flag(name: &str) -> bool {
+    matches!(
+        env_var(name).as_deref(),
+        Some("1") | Some("true") | Some("yes")
+    )
+}
+
+fn env_list(name: &str) -> Vec<String> {
+    env_var(name)
+        .map(|v| {
+            v.split(',')
+                .map(|node| node.trim().to_string())
+                .filter(|node| !node.is_empty())
+                .collect()
+        })
+        .unwrap_or_default()
+}
+
+impl RedisConfig {
+    /// Reads the configuration from the environment.
+    ///
+    /// `REDIS_URL` or `REDIS_HOST`, `REDIS_PORT`, `REDIS_DB`, `REDIS_USERNAME`,
+    /// `REDIS_PASSWORD` (falls back to `DATABASE_PASSWORD`), `REDIS_TLS`, `REDIS_TLS_INSECURE`.
+    /// `REDIS_SENTINEL_MASTER` with `REDIS_SENTINEL_NODES` selects Sentinel,
+    /// `REDIS_CLUSTER_NODES` selects Cluster.
+    pub fn from_env() -> RedisResult<Self> {
+        let port = match env_var("REDIS_PORT") {
+            Some(port) => port
+                .parse()
+                .map_err(|_| invalid_config("Invalid REDIS_PORT", port))?,
+            None => DEFAULT_PORT,
+        };
+
+        let db = match env_var("REDIS_DB") {
+            Some(db) => db
+                .parse()
+                .map_err(|_| invalid_config("Invalid REDIS_DB", db))?,
+            None => 0,
+        };
+
+        let sentinel_nodes = env_list("REDIS_SENTINEL_NODES");
+        let cluster_nodes = env_list("REDIS_CLUSTER_NODES");
+
+        let topology = match env_var("REDIS_SENTINEL_MASTER") {
+            Some(master_name) => {
+                if sentinel_nodes.is_empty() {
+                    return Err(invalid_config(
+                        "REDIS_SENTINEL_NODES is required with REDIS_SENTINEL_MASTER",
+                        master_name,
+                    ));
+                }
+                RedisTopology::Sentinel {
+                    master_name,
+                    nodes: sentinel_nodes,
+                }
+            }
+            None if !cluster_nodes.is_empty() => RedisTopology::Cluster {
+                nodes: cluster_nodes,
+            },
+            None => RedisTopology::Standalone,
+        };
+
+        Ok(RedisConfig {
+            url: env_var("REDIS_URL"),
+            host: env_var("REDIS_HOST").unwrap_or_else(|| DEFAULT_HOST.to_string()),
+            port,
+            db,
+            username: env_var("REDIS_USERNAME"),
+            password: env_var("REDIS_PASSWORD").or_else(|| env_var("DATABASE_PASSWORD")),
+            tls: env_flag("REDIS_TLS"),
+            tls_insecure: env_flag("REDIS_TLS_INSECURE"),
+            topology,
+        })
+    }
+
+    fn tls_mode(&self) -> Option<TlsMode> {
+        match (self.tls, self.tls_insecure) {
+            (false, _) => None,
+            (true, false) => Some(TlsMode::Secure),
+            (true, true) => Some(TlsMode::Insecure),
+        }
+    }
+
+    fn redis_info(&self) -> RedisConnectionInfo {
+        RedisConnectionInfo {
+            db: self.db,
+            username: self.username.clone(),
+            password: self.password.clone(),
+            ..Default::default()
+        }
+    }
+
+    fn addr(&self, host: String, port: u16) -> ConnectionAddr {
+        match self.tls_mode() {
+            Some(mode) => ConnectionAddr::TcpTls {
+                host,
+                port,
+                insecure: mode == TlsMode::Insecure,
+                tls_params: None,
+            },
+            None => ConnectionAddr::Tcp(host, port),
+        }
+    }
+
+    /// Connection info of a `host:port` or URL node.
+    fn node_info(&self, node: &str, redis: RedisConnectionInfo) -> RedisResult<ConnectionInfo> {
+        if node.contains("://") {
+            return node.into_connection_info();
+        }
+
+        let (host, port) = match node.rsplit_once(':') {
+            Some((host, port)) => (
+                host,
+                port.parse()
+                    .map_err(|_| invalid_config("Invalid Redis node", node.to_string()))?,
+            ),
+            None => (node, DEFAULT_PORT),
+        };
+
+        Ok(ConnectionInfo {
+            addr: self.addr(host.to_string(), port),
+            redis,
+        })
+    }
+
+    /// Connection info of the standalone server.
+    pub fn connection_info(&self) -> RedisResult<ConnectionInfo> {
+        match &self.url {
+            Some(url) => url.as_str().into_connection_info(),
+            None => Ok(ConnectionInfo {
+                addr: self.addr(self.host.clone(), self.port),
+                redis: self.redis_info(),
+            }),
+        }
+    }
+
+    /// Client of the standalone server.
+    pub fn client(&self) -> RedisResult<redis::Client> {
+        redis::Client::open(self.connection_info()?)
+    }
+
+    /// Master of the Sentinel set, resolved whenever a connection is opened.
+    fn sentinel_master(&self, master_name: &str, nodes: &[String]) -> RedisResult<SentinelMaster> {
+        // Sentinels only share the address settings, credentials go in their URLs.
+        let sentinels = nodes
+            .iter()
+            .map(|node| self.node_info(node, RedisConnectionInfo::default()))
+            .collect::<RedisResult<Vec<_>>>()?;
+
+        let master_info = match &self.url {
+            Some(url) => url.as_str().into_connection_info()?.redis,
+            None => self.redis_info(),
+        };
+        let db = master_info.db;
+        let node_connection_info = SentinelNodeConnectionInfo {
+            tls_mode: self.tls_mode(),
+            redis_connection_info: Some(master_info),
+        };
+
+        Ok(SentinelMaster::new(
+            Sentinel::build(sentinels)?,
+            master_name.to_string(),
+            node_connection_info,
+            db,
+        ))
+    }
+
+    /// Connects to Redis in the configured topology.
+    ///
+    /// A Sentinel master is resolved again after a failover, for commands and Pub/Sub.
+    pub async fn connect(&self) -> RedisResult<RedisStore> {
+        match &self.topology {
+            RedisTopology::Sentinel { master_name, nodes } => {
+                let master = self.sentinel_master(master_name, nodes)?;
+
+                RedisStore::sentinel(master).await
+            }
+            RedisTopology::Cluster { nodes } => {
+                if nodes.is_empty() {
+                    return Err(invalid_config("No Redis cluster nodes", String::new()));
+                }
+
+                let nodes = nodes
+                    .iter()
+                    .map(|node| self.node_info(node, self.redis_info()))
+                    .collect::<RedisResult<Vec<_>>>()?;
+
+                let cluster = ClusterClientBuilder::new(nodes.clone()).build()?;
+                let con = cluster.get_async_connection().await?;
+
+                let clients = nodes
+                    .into_iter()
+                    .map(redis::Client::open)
+                    .collect::<RedisResult<Vec<_>>>()?;
+
+                Ok(RedisStore::cluster(con, clients))
+            }
+            RedisTopology::Standalone => {
+                let client = self.client()?;
+                let rcm = ConnectionManager::new_with_config(
+                    client.clone(),
+                    ConnectionManagerConfig::new(),
+                )
+                .await?;
+
+                Ok(RedisStore::new(rcm, client))
+            }
+        }
+    }
+}
@@ -0,0 +1,695 @@
+use async_trait::async_trait;
+use futures::stream::{BoxStream, StreamExt};
+use std::sync::Arc;
+
+use redis::{
+    aio::{ConnectionLike, ConnectionManager, MultiplexedConnection},
+    cluster_async::ClusterConnection,
+    cluster_routing::get_slot,
+    sentinel::{Sentinel, SentinelNodeConnectionInfo},
+    AsyncCommands, ErrorKind, RedisError, RedisFuture, RedisResult, Value,
+};
+use tokio::sync::Mutex;
+
+use log::{error, info};
+use once_cell::sync::Lazy;
+
+use super::{Store, StoreCheck, StoreError, StoreOp, StoreResult};
+
+/// Interprets the checks and operations of [`ScriptBatch`].
+/// Every entry is its name, followed by the index of its key and its arguments.
+static EXEC_ATOMIC_SCRIPT: Lazy<redis::Script> = Lazy::new(|| {
+    redis::Script::new(
+        r"
+local i = 0
+local function arg()
+    i = i + 1
+    return ARGV[i]
+end
+local function key()
+    return KEYS[tonumber(arg())]
+end
+
+for _ = 1, tonumber(arg()) do
+    local check = arg()
+    local k = key()
+    if check == 'exists' then
+        if redis.call('EXISTS', k) == 0 then return 0 end
+    elseif check == 'not_exists' then
+        if redis.call('EXISTS', k) == 1 then return 0 end
+    elseif check == 'sismember' then
+        if redis.call('SISMEMBER', k, arg()) == 0 then return 0 end
+    elseif check == 'not_sismember' then
+        if redis.call('SISMEMBER', k, arg()) == 1 then return 0 end
+    elseif check == 'only_member' then
+        local count = redis.call('SCARD', k)
+        if count > 1 or (count == 1 and redis.call('SISMEMBER', k, arg()) == 0) then return 0 end
+    elseif check == 'hequals' then
+        local val = redis.call('HGET', k, arg())
+        if arg() == '1' then
+            if val ~= arg() then return 0 end
+        elseif val then
+            return 0
+        end
+    end
+end
+
+for _ = 1, tonumber(arg()) do
+    local op = arg()
+    local k = key()
+    if op == 'set' then
+        local val = arg()
+        redis.call('SET', k, val, 'EX', arg())
+    elseif op == 'set_nx' then
+        local val = arg()
+        redis.call('SET', k, val, 'NX', 'EX', arg())
+    elseif op == 'del' then
+        redis.call('DEL', k)
+    elseif op == 'expire' then
+        redis.call('EXPIRE', k, arg())
+    elseif op == 'sadd' then
+        redis.call('SADD', k, arg())
+    elseif op == 'srem' then
+        redis.call('SREM', k, arg())
+    elseif op == 'hset' then
+        for _ = 1, tonumber(arg()) do
+            local field = arg()
+            redis.call('HSET', k, field, arg())
+        end
+    elseif op == 'hdel' then
+        for _ = 1, tonumber(arg()) do
+            redis.call('HDEL', k, arg())
+        end
+    elseif op == 'rpush' then
+        redis.call('RPUSH', k, arg())
+    elseif op == 'lrem' then
+        redis.call('LREM', k, 0, arg())
+    end
+end
+
+return 1
+",
+    )
+});
+
+/// Check or operation of [`EXEC_ATOMIC_SCRIPT`].
+struct ScriptCall {
+    name: &'static str,
+    key: String,
+    args: Vec<Vec<u8>>,
+}
+
+impl ScriptCall {
+    fn arg(mut self, arg: impl Into<Vec<u8>>) -> Self {
+        self.args.push(arg.into());
+        self
+    }
+}
+
+/// Keys and arguments of an invocation of [`EXEC_ATOMIC_SCRIPT`].
+/// Keys are passed as `KEYS`, so a cluster can route the script.
+#[derive(Default)]
+struct ScriptBatch {
+    keys: Vec<String>,
+    checks: Vec<Vec<u8>>,
+    nr_checks: usize,
+    ops: Vec<Vec<u8>>,
+    nr_ops: usize,
+}
+
+impl ScriptBatch {
+    fn push(keys: &mut Vec<String>, args: &mut Vec<Vec<u8>>, call: ScriptCall) {
+        let idx = match keys.iter().position(|k| *k == call.key) {
+            Some(idx) => idx,
+            None => {
+                keys.push(call.key);
+                keys.len() - 1
+            }
+        };
+
+        args.push(call.name.into());
+        args.push((idx + 1).to_string().into());
+        args.extend(call.args);
+    }
+
+    fn check(&mut self, call: ScriptCall) {
+        Self::push(&mut self.keys, &mut self.checks, call);
+        self.nr_checks += 1;
+    }
+
+    fn op(&mut self, call: ScriptCall) {
+        Self::push(&mut self.keys, &mut self.ops, call);
+        self.nr_ops += 1;
+    }
+
+    async fn invoke(self, con: &mut RedisConnection) -> StoreResult<bool> {
+        let mut invocation = EXEC_ATOMIC_SCRIPT.prepare_invoke();
+        for key in self.keys {
+            invocation.key(key);
+        }
+        invocation.arg(self.nr_checks.to_string());
+        for arg in self.checks {
+            invocation.arg(arg);
+        }
+        invocation.arg(self.nr_ops.to_string());
+        for arg in self.ops {
+            invocation.arg(arg);
+        }
+
+        let applied: i64 = invocation.invoke_async(con).await?;
+        Ok(applied == 1)
+    }
+}
+
+impl From<redis::RedisError> for StoreError {
+    fn from(e: redis::RedisError) -> Self {
+        StoreError::Redis(e)
+    }
+}
+
+/// Master of a Sentinel set, resolved again for every new connection.
+pub struct SentinelMaster {
+    sentinel: Mutex<Sentinel>,
+    master_name: String,
+    node_connection_info: SentinelNodeConnectionInfo,
+    db: i64,
+}
+
+impl SentinelMaster {
+    pub fn new(
+        sentinel: Sentinel,
+        master_name: String,
+        node_connection_info: SentinelNodeConnectionInfo,
+        db: i64,
+    ) -> Self {
+        SentinelMaster {
+            sentinel: Mutex::new(sentinel),
+            master_name,
+            node_connection_info,
+            db,
+        }
+    }
+
+    /// Client of the current master.
+    pub async fn client(&self) -> RedisResult<redis::Client> {
+        self.sentinel
+            .lock()
+            .await
+            .async_master_for(&self.master_name, Some(&self.node_connection_info))
+            .await
+    }
+}
+
+/// Connection to the master of a Sentinel set.
+/// Errors hinting at a failover drop the connection, the next command resolves the master again.
+#[derive(Clone)]
+struct SentinelConnection {
+    master: Arc<SentinelMaster>,
+    con: Arc<Mutex<Option<MultiplexedConnection>>>,
+}
+
+impl SentinelConnection {
+    async fn connect(master: Arc<SentinelMaster>) -> RedisResult<Self> {
+        let connection = SentinelConnection {
+            master,
+            con: Arc::new(Mutex::new(None)),
+        };
+        connection.connection().await?;
+
+        Ok(connection)
+    }
+
+    async fn connection(&self) -> RedisResult<MultiplexedConnection> {
+        let mut con = self.con.lock().await;
+        if let Some(con) = con.as_ref() {
+            return Ok(con.clone());
+        }
+
+        let client = self.master.client().await?;
+        info!(
+            "Connecting to Sentinel master: {}",
+            client.get_connection_info().addr
+        );
+        let new_con = client.get_multiplexed_async_connection().await?;
+        *con = Some(new_con.clone());
+
+        Ok(new_con)
+    }
+
+    async fn check<T>(&self, result: RedisResult<T>) -> RedisResult<T> {
+        if let Err(e) = &result {
+            // a demoted master answers writes with READONLY
+            if e.is_io_error()
+                || e.is_connection_dropped()
+                || e.is_connection_refusal()
+                || e.is_timeout()
+                || e.kind() == ErrorKind::ReadOnly
+            {
+                error!("Lost Sentinel master ({})", e);
+                *self.con.lock().await = None;
+            }
+        }
+
+        result
+    }
+}
+
+/// Connection to a single server, to the master of a Sentinel set or to a cluster.
+#[derive(Clone)]
+enum RedisConnection {
+    Single(Box<ConnectionManager>),
+    Sentinel(SentinelConnection),
+    Cluster(ClusterConnection),
+}
+
+impl ConnectionLike for RedisConnection {
+    fn req_packed_command<'a>(&'a mut self, cmd: &'a redis::Cmd) -> RedisFuture<'a, Value> {
+        match self {
+            RedisConnection::Single(con) => con.req_packed_command(cmd),
+            RedisConnection::Sentinel(con) => Box::pin(async move {
+                let mut master = con.connection().await?;
+                let result = master.req_packed_command(cmd).await;
+                con.check(result).await
+            }),
+            RedisConnection::Cluster(con) => con.req_packed_command(cmd),
+        }
+    }
+
+    fn req_packed_commands<'a>(
+        &'a mut self,
+        cmd: &'a redis::Pipeline,
+        offset: usize,
+        count: usize,
+    ) -> RedisFuture<'a, Vec<Value>> {
+        match self {
+            RedisConnection::Single(con) => con.req_packed_commands(cmd, offset, count),
+            RedisConnection::Sentinel(con) => Box::pin(async move {
+                let mut master = con.connection().await?;
+                let result = master.req_packed_commands(cmd, offset, count).await;
+                con.check(result).await
+            }),
+            RedisConnection::Cluster(con) => con.req_packed_commands(cmd, offset, count),
+        }
+    }
+
+    fn get_db(&self) -> i64 {
+        match self {
+            RedisConnection::Single(con) => con.get_db(),
+            RedisConnection::Sentinel(con) => con.master.db,
+            RedisConnection::Cluster(con) => con.get_db(),
+        }
+    }
+}
+
+/// Where Pub/Sub connections, which are dedicated, are opened.
+#[derive(Clone)]
+enum PubSubSource {
+    /// Tried in turn until one subscribes,
+    /// messages published to any node of a cluster reach the subscribers of every node.
+    Nodes(Vec<redis::Client>),
+    Sentinel(Arc<SentinelMaster>),
+}
+
+/// Store backed by Redis.
+///
+/// In Cluster mode the keys of a session or a file request share a slot, see [`crate::keys`].
+/// Pipelines are split by slot, atomic batches spanning several slots are rejected.
+#[derive(Clone)]
+pub struct RedisStore {
+    con: RedisConnection,
+    pubsub: PubSubSource,
+}
+
+impl RedisStore {
+    pub fn new(rcm: ConnectionManager, client: redis::Client) -> Self {
+        RedisStore {
+            con: RedisConnection::Single(Box::new(rcm)),
+            pubsub: PubSubSource::Nodes(vec![client]),
+        }
+    }
+
+    /// Connects to the current master, after a failover the new master is resolved.
+    pub async fn sentinel(master: SentinelMaster) -> RedisResult<Self> {
+        let master = Arc::new(master);
+        let con = SentinelConnection::connect(master.clone()).await?;
+
+        Ok(RedisStore {
+            con: RedisConnection::Sentinel(con),
+            pubsub: PubSubSource::Sentinel(master),
+        })
+    }
+
+    pub fn cluster(con: ClusterConnection, clients: Vec<redis::Client>) -> Self {
+        RedisStore {
+            con: RedisConnection::Cluster(con),
+            pubsub: PubSubSource::Nodes(clients),
+        }
+    }
+
+    async fn pubsub(&self) -> RedisResult<redis::aio::PubSub> {
+        let clients = match &self.pubsub {
+            PubSubSource::Nodes(clients) => clients.clone(),
+            PubSubSource::Sentinel(master) => vec![master.client().await?],
+        };
+
+        let mut last_error = RedisError::from((ErrorKind::ClientError, "No Redis nodes"));
+        for client in clients {
+            match client.get_async_pubsub().await {
+                Ok(pubsub) => return Ok(pubsub),
+                Err(e) => {
+                    error!(
+                        "Error opening Pub/Sub on: {} ({})",
+                        client.get_connection_info().addr,
+                        e
+                    );
+                    last_error = e;
+                }
+            }
+        }
+
+        Err(last_error)
+    }
+
+    fn is_cluster(&self) -> bool {
+        matches!(self.con, RedisConnection::Cluster(_))
+    }
+
+    fn slot(&self, key: &str) -> u16 {
+        if self.is_cluster() {
+            get_slot(key.as_bytes())
+        } else {
+            0
+        }
+    }
+
+    fn call(&self, name: &'static str, key: &str) -> ScriptCall {
+        ScriptCall {
+            name,
+            key: key.to_string(),
+            args: Vec::new(),
+        }
+    }
+
+    /// Indexes of the keys, grouped by slot.
+    fn slot_groups(&self, keys: &[String]) -> Vec<Vec<usize>> {
+        let mut groups: Vec<(u16, Vec<usize>)> = Vec::new();
+        for (idx, key) in keys.iter().enumerate() {
+            let slot = self.slot(key);
+            match groups.iter_mut().find(|(s, _)| *s == slot) {
+                Some((_, group)) => group.push(idx),
+                None => groups.push((slot, vec![idx])),
+            }
+        }
+
+        groups.into_iter().map(|(_, group)| group).collect()
+    }
+}
+
+#[async_trait]
+impl Store for RedisStore {
+    async fn expire(&self, key: &str, seconds: i64) -> StoreResult<()> {
+        let mut con = self.con.clone();
+        con.expire::<&str, i64>(key, seconds).await?;
+        Ok(())
+    }
+
+    async fn expire_many(&self, keys: &[String], seconds: i64) -> StoreResult<()> {
+        for group in self.slot_groups(keys) {
+            let mut pipe = redis::pipe();
+            for idx in group {
+                pipe.expire(&keys[idx], seconds).ignore();
+            }
+
+            let mut con = self.con.clone();
+            pipe.query_async::<()>(&mut con).await?;
+        }
+        Ok(())
+    }
+
+    async fn exists(&self, key: &str) -> StoreResult<bool> {
+        let mut con = self.con.clone();
+        Ok(con.exists(key).await?)
+    }
+
+    async fn del(&self, key: &str) -> StoreResult<()> {
+        let mut con = self.con.clone();
+        con.del::<&str, i64>(key).await?;
+        Ok(())
+    }
+
+    async fn set(&self, key: &str, val: &[u8], seconds: i64) -> StoreResult<()> {
+        let mut con = self.con.clone();
+        con.set_ex::<&str, &[u8], ()>(key, val, seconds as u64)
+            .await?;
+        Ok(())
+    }
+
+    async fn set_nx(&self, key: &str, val: &[u8], seconds: i64) -> StoreResult<bool> {
+        let mut con = self.con.clone();
+        let v = redis::cmd("SET")
+            .arg(key)
+            .arg(val)
+            .arg("NX")
+            .arg("EX")
+            .arg(seconds)
+            .query_async::<Option<String>>(&mut con)
+            .await?;
+        Ok(v.is_some())
+    }
+
+    async fn get(&self, key: &str) -> StoreResult<Option<Vec<u8>>> {
+        let mut con = self.con.clone();
+        Ok(con.get(key).await?)
+    }
+
+    async fn incr(&self, key: &str) -> StoreResult<i64> {
+        let mut con = self.con.clone();
+        Ok(con.incr(key, 1).await?)
+    }
+
+    async fn incr_ex(&self, key: &str, seconds: i64) -> StoreResult<i64> {
+        let mut con = self.con.clone();
+        let (amount,) = redis::pipe()
+            .atomic()
+            .incr(key, 1)
+            .expire(key, seconds)
+            .ignore()
+            .query_async::<(i64,)>(&mut con)
+            .await?;
+        Ok(amount)
+    }
+
+    async fn sadd(&self, key: &str, member: &str) -> StoreResult<()> {
+        let mut con = self.con.clone();
+        con.sadd::<&str, &str, i64>(key, member).await?;
+        Ok(())
+    }
+
+    async fn sismember(&self, key: &str, member: &str) -> StoreResult<bool> {
+        let mut con = self.con.clone();
+        Ok(con.sismember(key, member).await?)
+    }
+
+    async fn smembers(&self, key: &str) -> StoreResult<Vec<String>> {
+        let mut con = self.con.clone();
+        Ok(con.smembers(key).await?)
+    }
+
+    async fn srem(&self, key: &str, member: &str) -> StoreResult<()> {
+        let mut con = self.con.clone();
+        con.srem::<&str, &str, i64>(key, member).await?;
+        Ok(())
+    }
+
+    async fn hset(&self, key: &str, items: &[(&str, &[u8])]) -> StoreResult<()> {
+        let mut con = self.con.clone();
+        con.hset_multiple::<&str, &str, &[u8], ()>(key, items)
+            .await?;
+        Ok(())
+    }
+
+    async fn hget(&self, key: &str, field: &str) -> StoreResult<Option<Vec<u8>>> {
+        let mut con = self.con.clone();
+        Ok(con.hget(key, field).await?)
+    }
+
+    async fn hexists(&self, key: &str, field: &str) -> StoreResult<bool> {
+        let mut con = self.con.clone();
+        Ok(con.hexists(key, field).await?)
+    }
+
+    async fn hdel(&self, key: &str, fields: &[String]) -> StoreResult<()> {
+        let mut con = self.con.clone();
+        con.hdel::<&str, &[String], i64>(key, fields).await?;
+        Ok(())
+    }
+
+    async fn hgetall(&self, key: &str) -> StoreResult<Vec<(String, Vec<u8>)>> {
+        let mut con = self.con.clone();
+        Ok(con.hgetall(key).await?)
+    }
+
+    async fn hget_many(&self, keys: &[String], field: &str) -> StoreResult<Vec<Option<Vec<u8>>>> {
+        let mut values = vec![None; keys.len()];
+        for group in self.slot_groups(keys) {
+            let mut pipe = redis::pipe();
+            for &idx in &group {
+                pipe.hget(&keys[idx], field);
+            }
+
+            let mut con = self.con.clone();
+            let group_values: Vec<Option<Vec<u8>>> = pipe.query_async(&mut con).await?;
+            for (idx, value) in group.into_iter().zip(group_values) {
+                values[idx] = value;
+            }
+        }
+        Ok(values)
+    }
+
+    async fn hgetall_many(&self, keys: &[String]) -> StoreResult<Vec<Vec<(String, Vec<u8>)>>> {
+        let mut values = vec![Vec::new(); keys.len()];
+        for group in self.slot_groups(keys) {
+            let mut pipe = redis::pipe();
+            for &idx in &group {
+                pipe.hgetall(&keys[idx]);
+            }
+
+            let mut con = self.con.clone();
+            let group_values: Vec<Vec<(String, Vec<u8>)>> = pipe.query_async(&mut con).await?;
+            for (idx, value) in group.into_iter().zip(group_values) {
+                values[idx] = value;
+            }
+        }
+        Ok(values)
+    }
+
+    async fn lpush(&self, key: &str, val: &str) -> StoreResult<()> {
+        let mut con = self.con.clone();
+        con.lpush::<&str, &str, i64>(key, val).await?;
+        Ok(())
+    }
+
+    async fn rpush(&self, key: &str, val: &str) -> StoreResult<()> {
+        let mut con = self.con.clone();
+        con.rpush::<&str, &str, i64>(key, val).await?;
+        Ok(())
+    }
+
+    async fn lpop(&self, key: &str) -> StoreResult<Option<String>> {
+        let mut con = self.con.clone();
+        Ok(con.lpop(key, None).await?)
+    }
+
+    async fn rpop(&self, key: &str) -> StoreResult<Option<String>> {
+        let mut con = self.con.clone();
+        Ok(con.rpop(key, None).await?)
+    }
+
+    async fn llen(&self, key: &str) -> StoreResult<i64> {
+        let mut con = self.con.clone();
+        Ok(con.llen(key).await?)
+    }
+
+    async fn lrange(&self, key: &str) -> StoreResult<Vec<String>> {
+        let mut con = self.con.clone();
+        Ok(con.lrange(key, 0, -1).await?)
+    }
+
+    async fn lrem(&self, key: &str, val: &str) -> StoreResult<()> {
+        let mut con = self.con.clone();
+        con.lrem::<&str, &str, i64>(key, 0, val).await?;
+        Ok(())
+    }
+
+    /// Runs the checks and operations as a single script.
+    /// A script only runs on a single slot, a batch spanning several slots is rejected
+    /// instead of being applied in parts.
+    async fn exec_atomic(&self, checks: Vec<StoreCheck>, ops: Vec<StoreOp>) -> StoreResult<bool> {
+        let mut batch = ScriptBatch::default();
+
+        for check in checks {
+            let call = match check {
+                StoreCheck::Exists { key } => self.call("exists", &key),
+                StoreCheck::NotExists { key } => self.call("not_exists", &key),
+                StoreCheck::SIsMember { key, member } => self.call("sismember", &key).arg(member),
+                StoreCheck::NotSIsMember { key, member } => {
+                    self.call("not_sismember", &key).arg(member)
+                }
+                StoreCheck::SOnlyMember { key, member } => {
+                    self.call("only_member", &key).arg(member)
+                }
+                StoreCheck::HEquals { key, field, val } => match val {
+                    Some(val) => self.call("hequals", &key).arg(field).arg("1").arg(val),
+                    None => self.call("hequals", &key).arg(field).arg("0"),
+                },
+            };
+            batch.check(call);
+        }
+
+        for op in ops {
+            let call = match op {
+                StoreOp::Set { key, val, seconds } => {
+                    self.call("set", &key).arg(val).arg(seconds.to_string())
+                }
+                StoreOp::SetNx { key, val, seconds } => {
+                    self.call("set_nx", &key).arg(val).arg(seconds.to_string())
+                }
+                StoreOp::Del { key } => self.call("del", &key),
+                StoreOp::Expire { key, seconds } => {
+                    self.call("expire", &key).arg(seconds.to_string())
+                }
+                StoreOp::SAdd { key, member } => self.call("sadd", &key).arg(member),
+                StoreOp::SRem { key, member } => self.call("srem", &key).arg(member),
+                StoreOp::HSet { key, items } => {
+                    let mut call = self.call("hset", &key).arg(items.len().to_string());
+                    for (field, val) in items {
+                        call = call.arg(field).arg(val);
+                    }
+                    call
+                }
+                StoreOp::HDel { key, fields } => {
+                    let mut call = self.call("hdel", &key).arg(fields.len().to_string());
+                    for field in fields {
+                        call = call.arg(field);
+                    }
+                    call
+                }
+                StoreOp::RPush { key, val } => self.call("rpush", &key).arg(val),
+                StoreOp::LRem { key, val } => self.call("lrem", &key).arg(val),
+            };
+            batch.op(call);
+        }
+
+        let mut slots = batch.keys.iter().map(|key| self.slot(key));
+        if let Some(slot) = slots.next() {
+            if slots.any(|other| other != slot) {
+                return Err(StoreError::Redis(redis::RedisError::from((
+                    redis::ErrorKind::CrossSlot,
+                    "atomic batch spans several slots",
+                ))));
+            }
+        }
+
+        let mut con = self.con.clone();
+        batch.invoke(&mut con).await
+    }
+
+    async fn publish(&self, channel: &str, message: &str) -> StoreResult<()> {
+        let mut con = self.con.clone();
+        con.publish::<&str, &str, i64>(channel, message).await?;
+        Ok(())
+    }
+
+    async fn psubscribe(&self, pattern: &str) -> StoreResult<BoxStream<'static, (String, String)>> {
+        let mut pubsub = self.pubsub().await?;
+        pubsub.psubscribe(pattern).await?;
+
+        let messages = pubsub.into_on_message().map(|message| {
+            let channel = message.get_channel_name().to_string();
+            let payload: String = message.get_payload().unwrap_or_default();
+            (channel, payload)
+        });
+
+        Ok(messages.boxed())
+    }
+}
@@ -0,0 +1,2608 @@
+use axum::{
+    extract::{
+        ws::{close_code, CloseFrame, Message, WebSocket, WebSocketUpgrade},
+        Path, Query, State,
+    },
+    http::{header, HeaderMap, StatusCode},
+    response::{IntoResponse, Response},
+    routing::get,
+    Router,
+};
+
+use once_cell::sync::Lazy;
+use std::collections::HashMap;
+use std::time::Duration;
+use tokio::sync::{mpsc, watch, Notify};
+use tokio::time;
+use tokio_stream::StreamExt;
+
+use dashmap::{mapref::entry::Entry, DashMap};
+use std::sync::Arc;
+
+use serde::{Deserialize, Serialize};
+use serde_json::json;
+
+use tower_http::cors::{Any, CorsLayer};
+
+use crate::error::AppError;
+use crate::keys;
+use crate::rate_limit::{Limit, RateLimitConfig, RateLimitLayer};
+use crate::redis_handler::Transaction;
+use crate::store::DynStore;
+
+use crate::chunk_frame::ChunkFrame;
+use crate::protocol::{
+    self, ContentHash, ReqAcknowledgeFileRequest, ReqAddChunk, ReqCancelTransfer, ReqHello,
+    ReqReadyForFileRequest, ReqReceivedChunk, ReqRegister, ReqRequestFile, ReqResumeTransfer,
+    WrappedKey, WsCommand, WsMessage, WsMsg, WsMsgAcknowledgeFileRequest, WsMsgAddChunk,
+    WsMsgError, WsMsgHello, WsMsgOk, WsMsgPrepareForFileTransfer, WsMsgQueuePosition,
+    WsMsgReceiver, WsMsgSendNextChunk, WsMsgTransferCancelled, WsMsgTransferPaused,
+    WsMsgTransferProgress, WsMsgTransferResumed, WsRequest,
+};
+
+use log::{error, info, warn};
+
+const MAX_CHUNK_SIZE: usize = 70_000;
+
+const DEFAULT_MAX_WINDOW_SIZE: u32 = 16;
+
+/// Maximum amount of chunks in flight per file request.
+/// Configured per deployment using `MAX_CHUNK_WINDOW_SIZE`.
+static MAX_WINDOW_SIZE: Lazy<u32> = Lazy::new(|| {
+    std::env::var("MAX_CHUNK_WINDOW_SIZE")
+        .ok()
+        .and_then(|window_size| window_size.parse().ok())
+        .filter(|window_size| *window_size > 0)
+        .unwrap_or(DEFAULT_MAX_WINDOW_SIZE)
+});
+
+const DEFAULT_TRANSFER_GRACE_PERIOD: i64 = 600; // 10min
+
+/// Time in seconds a transfer is kept alive after a participant disconnected.
+/// Configured per deployment using `TRANSFER_GRACE_PERIOD`.
+static TRANSFER_GRACE_PERIOD: Lazy<i64> = Lazy::new(|| {
+    std::env::var("TRANSFER_GRACE_PERIOD")
+        .ok()
+        .and_then(|grace_period| grace_period.parse().ok())
+        .filter(|grace_period| *grace_period > 0)
+        .unwrap_or(DEFAULT_TRANSFER_GRACE_PERIOD)
+});
+
+const DEFAULT_BROADCAST_JOIN_WINDOW: u64 = 5; // 5s
+const MAX_BROADCAST_JOIN_WINDOW: u64 = 60; // 1min
+
+/// Time in seconds receivers can join a broadcast after the first request for a file.
+/// Configured per deployment using `BROADCAST_JOIN_WINDOW`, at most `MAX_BROADCAST_JOIN_WINDOW`.
+static BROADCAST_JOIN_WINDOW: Lazy<u64> = Lazy::new(|| {
+    std::env::var("BROADCAST_JOIN_WINDOW")
+        .ok()
+        .and_then(|join_window| join_window.parse::<u64>().ok())
+        .map(|join_window| join_window.min(MAX_BROADCAST_JOIN_WINDOW))
+        .unwrap_or(DEFAULT_BROADCAST_JOIN_WINDOW)
+});
+
+const DEFAULT_MAX_CONCURRENT_TRANSFERS: usize = 3;
+
+/// Maximum amount of files a user receives at once, further requests are queued.
+/// Configured per deployment using `MAX_CONCURRENT_TRANSFERS`.
+static MAX_CONCURRENT_TRANSFERS: Lazy<usize> = Lazy::new(|| {
+    std::env::var("MAX_CONCURRENT_TRANSFERS")
+        .ok()
+        .and_then(|max_transfers| max_transfers.parse().ok())
+        .filter(|max_transfers| *max_transfers > 0)
+        .unwrap_or(DEFAULT_MAX_CONCURRENT_TRANSFERS)
+});
+
+#[derive(Clone)]
+struct Listener {
+    notify: Arc<Notify>,
+    tx: mpsc::Sender<Message>,
+}
+
+static LISTENERS: Lazy<Arc<DashMap<String, Listener>>> = Lazy::new(|| Arc::new(DashMap::new()));
+
+/// Routes of the transmittor, served with `into_make_service_with_connect_info::<SocketAddr>`.
+/// Requires [`dispatch_events`] to run on the same store.
+/// Opening a websocket is limited, its messages are not.
+const ROUTE_LIMITS: &[(&str, Limit)] = &[("GET /session/:session_id", Limit::new(30, 60))];
+
+pub fn router(store: DynStore) -> Router {
+    let cors = CorsLayer::new()
+        .allow_origin(Any)
+        .allow_methods(Any)
+        .allow_headers(Any)
+        .expose_headers(Any);
+
+    Router::new()
+        .route("/session/:session_id", get(ws_handler))
+        .route("/session/:session_id/transfers", get(transfers_handler))
+        .route("/schema", get(schema_handler))
+        .route_layer(RateLimitLayer::new(
+            store.clone(),
+            RateLimitConfig::from_env(ROUTE_LIMITS),
+        ))
+        .with_state(store)
+        .layer(cors)
+}
+
+/// Wakes the listener of a user whenever an event is published on `events:<user.id>`.
+/// Events with a payload are forwarded to the websocket of the user as is.
+pub async fn dispatch_events(store: DynStore) {
+    let pattern = keys::events_pattern();
+    loop {
+        match store.psubscribe(&pattern).await {
+            Ok(mut messages) => {
+                info!("Subscribed to events");
+
+                // events might have been missed while not subscribed
+                notify_all_listeners();
+
+                while let Some((channel, payload)) = messages.next().await {
+                    // events were dropped, the listeners check their state again
+                    if channel == pattern {
+                        notify_all_listeners();
+                        continue;
+                    }
+
+                    let user_id = match keys::events_user(&channel) {
+                        Some(user_id) => user_id,
+                        None => continue,
+                    };
+
+                    let listener = match LISTENERS.get(user_id) {
+                        Some(listener) => listener.value().clone(),
+                        None => continue,
+                    };
+
+                    if payload.is_empty() {
+                        listener.notify.notify_one();
+                    } else if listener.tx.try_send(Message::Text(payload)).is_err() {
+                        error!("Failed to forward event to user: {}", user_id);
+                    }
+                }
+
+                error!("Event subscription lost");
+            }
+            Err(e) => error!("Error subscribing to events: {}", e),
+        }
+
+        time::sleep(Duration::from_secs(1)).await;
+    }
+}
+
+fn notify_all_listeners() {
+    for listener in LISTENERS.iter() {
+        listener.value().notify.notify_one();
+    }
+}
+
+/// Fields of a request that are echoed, even if the request itself is malformed.
+#[derive(Deserialize, Default)]
+struct RequestHeader {
+    #[serde(default)]
+    id: Option<String>,
+    #[serde(default)]
+    command: Option<String>,
+}
+
+/// Optional features a client announces when registering.
+#[derive(Clone, Copy, Default)]
+struct Capabilities {
+    /// Chunks are exchanged as binary frames instead of JSON.
+    binary_chunks: bool,
+    /// Requests for the same file are served by a single transfer.
+    broadcast: bool,
+    /// Binary chunks may carry the digest of the chunk.
+    chunk_digests: bool,
+}
+
+impl Capabilities {
+    fn from_names(names: &[String]) -> Self {
+        Capabilities {
+            binary_chunks: names
+                .iter()
+                .any(|name| name == protocol::CAPABILITY_BINARY_CHUNKS),
+            broadcast: names
+                .iter()
+                .any(|name| name == protocol::CAPABILITY_BROADCAST),
+            chunk_digests: names
+                .iter()
+                .any(|name| name == protocol::CAPABILITY_CHUNK_DIGESTS),
+        }
+    }
+
+    fn to_names(self) -> Vec<&'static str> {
+        [
+            (protocol::CAPABILITY_BINARY_CHUNKS, self.binary_chunks),
+            (protocol::CAPABILITY_BROADCAST, self.broadcast),
+            (protocol::CAPABILITY_CHUNK_DIGESTS, self.chunk_digests),
+        ]
+        .into_iter()
+        .filter(|(_, enabled)| *enabled)
+        .map(|(name, _)| name)
+        .collect()
+    }
+}
+
+/// State of a single websocket connection.
+struct Connection {
+    /// Authenticated when the websocket was opened.
+    user_id: String,
+    /// Expiration of the JWT (ms), extended by requests carrying a newer JWT.
+    jwt_expires_at: u128,
+    is_registered: bool,
+    /// Set once the client stated its version with `hello` or `register`.
+    protocol_version: Option<u32>,
+    capabilities: Capabilities,
+}
+
+impl Connection {
+    fn new(claims: &crate::Claims) -> Self {
+        Connection {
+            user_id: claims.sub.clone(),
+            jwt_expires_at: claims.exp,
+            is_registered: false,
+            protocol_version: None,
+            capabilities: Capabilities::default(),
+        }
+    }
+}
+
+/// Subprotocol the transmittor answers with,
+/// offered by clients that authenticate using the `bearer.<jwt>` subprotocol.
+const SUBPROTOCOL: &str = "drag-n-share";
+const SUBPROTOCOL_BEARER_PREFIX: &str = "bearer.";
+
+#[derive(Deserialize)]
+struct WsAuthQuery {
+    #[serde(default)]
+    token: Option<String>,
+}
+
+/// Takes the JWT from the `Authorization` header, the `bearer.<jwt>` subprotocol
+/// or the deprecated `token` query parameter, in that order.
+fn get_upgrade_jwt(headers: &HeaderMap, query: &WsAuthQuery) -> Option<String> {
+    if let Ok(auth) = crate::get_header(headers, "authorization") {
+        if let Some(jwt) = auth.strip_prefix("Bearer ") {
+            return Some(jwt.to_string());
+        }
+    }
+
+    if let Ok(protocols) = crate::get_header(headers, "sec-websocket-protocol") {
+        let jwt = protocols
+            .split(',')
+            .map(|protocol| protocol.trim())
+            .find_map(|protocol| protocol.strip_prefix(SUBPROTOCOL_BEARER_PREFIX));
+        if let Some(jwt) = jwt {
+            return Some(jwt.to_string());
+        }
+    }
+
+    // query strings end up in the logs of proxies
+    if query.token.is_some() {
+        warn!("JWT passed as deprecated token query parameter");
+    }
+
+    query.token.clone()
+}
+
+fn authenticate(
+    headers: &HeaderMap,
+    query: &WsAuthQuery,
+    session_id: &String,
+) -> Result<crate::Claims, AppError> {
+    let jwt = match get_upgrade_jwt(headers, query) {
+        Some(jwt) => jwt,
+        None => return Err(AppError::Unauthorized("Missing JWT.".to_string())),
+    };
+
+    let claims = crate::decode_jwt(&jwt)?;
+    if &claims.aud != session_id {
+        return Err(AppError::Unauthorized("Invalid session ID.".to_string()));
+    }
+
+    Ok(claims)
+}
+
+async fn schema_handler() -> impl IntoResponse {
+    (
+        [(header::CONTENT_TYPE, "application/json")],
+        protocol::json_schema(),
+    )
+}
+
+/// State of a file request, as listed by [`transfers_handler`].
+#[derive(Serialize)]
+struct TransferStatus {
+    request_id: String,
+    filename: String,
+    sender_id: String,
+    /// `requested`, `preparing`, `transferring` or `paused`.
+    state: &'static str,
+    receivers: Vec<WsMsgTransferProgress>,
+}
+
+/// Lists the active transfers of a session.
+/// Hosts see every transfer, other users the ones they take part in.
+async fn transfers_handler(
+    rcm: State<DynStore>,
+    headers: HeaderMap,
+    Path(session_id): Path<String>,
+) -> Result<(StatusCode, String), AppError> {
+    crate::check_session_exists(rcm.clone(), &session_id).await?;
+    let user = crate::check_user_is_in_session(&headers, &session_id)?;
+
+    let key = keys::transfers(&session_id);
+    let request_ids = crate::redis_handler::smembers(rcm.clone(), &key).await?;
+
+    let mut transfers: Vec<TransferStatus> = Vec::new();
+    for request_id in request_ids {
+        let transfer = match get_transfer_status(rcm.clone(), &request_id).await? {
+            Some(transfer) => transfer,
+            None => {
+                // the state of the file request expired
+                crate::redis_handler::srem(rcm.clone(), &key, &request_id).await?;
+                continue;
+            }
+        };
+
+        let takes_part = transfer.sender_id == user.id
+            || transfer
+                .receivers
+                .iter()
+                .any(|receiver| receiver.user_id == user.id);
+        if user.is_host || takes_part {
+            transfers.push(transfer);
+        }
+    }
+
+    Ok((
+        StatusCode::OK,
+        json!({
+            "success": true,
+            "response": transfers
+        })
+        .to_string(),
+    ))
+}
+
+/// Returns `None` if the file request does not exist anymore.
+async fn get_transfer_status(
+    rcm: State<DynStore>,
+    request_id: &str,
+) -> Result<Option<TransferStatus>, AppError> {
+    let key = keys::file_req_sender(request_id);
+    let sender_id = crate::redis_handler::get(rcm.clone(), &key).await?;
+    if sender_id.is_empty() {
+        return Ok(None);
+    }
+
+    let key = keys::file_req_filename(request_id);
+    let filename = crate::redis_handler::get(rcm.clone(), &key).await?;
+
+    let receiver_ids = get_receiver_ids(rcm.clone(), request_id, &sender_id).await?;
+
+    let key = keys::chunk_acked(request_id);
+    let acked_chunk_nrs = crate::redis_handler::hgetall(rcm.clone(), &key).await?;
+
+    let key = keys::chunk_bytes(request_id);
+    let acked_bytes = crate::redis_handler::hgetall(rcm.clone(), &key).await?;
+
+    let mut receivers: Vec<WsMsgTransferProgress> = Vec::new();
+    for receiver_id in &receiver_ids {
+        let chunks_done = crate::get_hash_value(&acked_chunk_nrs, receiver_id)
+            .and_then(|chunk_nr| chunk_nr.parse().ok())
+            .unwrap_or(0);
+        let bytes = crate::get_hash_value(&acked_bytes, receiver_id)
+            .and_then(|bytes| bytes.parse().ok())
+            .unwrap_or(0);
+
+        receivers.push(
+            get_transfer_progress(rcm.clone(), request_id, receiver_id, chunks_done, bytes).await,
+        );
+    }
+
+    // a broadcast goes on while only some of its receivers are paused
+    let key = keys::transfer_paused(request_id);
+    let paused_ids = crate::redis_handler::smembers(rcm.clone(), &key).await?;
+    let is_paused = !paused_ids.is_empty()
+        && (paused_ids.contains(&sender_id)
+            || receiver_ids
+                .iter()
+                .all(|receiver_id| paused_ids.contains(receiver_id)));
+    let key = keys::file_req_prep(request_id);
+    let is_acknowledged = crate::redis_handler::exists(rcm.clone(), &key).await?;
+    let is_transferring = get_min_acked_chunk_nr(&acked_chunk_nrs, &receiver_ids)?.is_some();
+
+    let state = if is_paused {
+        "paused"
+    } else if is_transferring {
+        "transferring"
+    } else if is_acknowledged {
+        "preparing"
+    } else {
+        "requested"
+    };
+
+    Ok(Some(TransferStatus {
+        request_id: request_id.to_string(),
+        filename,
+        sender_id,
+        state,
+        receivers,
+    }))
+}
+
+async fn ws_handler(
+    rcm: State<DynStore>,
+    Path(session_id): Path<String>,
+    Query(query): Query<WsAuthQuery>,
+    headers: HeaderMap,
+    ws: WebSocketUpgrade,
+) -> Response {
+    let claims = match authenticate(&headers, &query, &session_id) {
+        Ok(claims) => claims,
+        Err(e) => return e.into_response(),
+    };
+
+    info!("Websocket connection opened");
+    let connection = Connection::new(&claims);
+    ws.protocols([SUBPROTOCOL])
+        .on_upgrade(|ws| ws_handler_inner(rcm, session_id, connection, ws))
+}
+
+async fn ws_handler_inner(
+    rcm: State<DynStore>,
+    session_id: String,
+    mut connection: Connection,
+    mut socket: WebSocket,
+) {
+    let (tx, mut rx) = mpsc::channel::<Message>(1024);
+    let (shutdown_tx, shutdown_rx) = watch::channel(());
+    // Use tokio::select! to handle reading from the WebSocket and sending messages concurrently
+
+    let tx_clone = tx.clone();
+    let shutdown_rx_clone = shutdown_rx.clone();
+    loop {
+        let jwt_expires_in = connection
+            .jwt_expires_at
+            .saturating_sub(crate::get_current_timestamp());
+
+        tokio::select! {
+            // Handle receiving from the WebSocket
+            message = socket.next() => {
+                match message {
+                    Some(Ok(Message::Text(text))) => {
+                        // info!("Received message from client: {}", text);
+
+                        handle_incomming_message(
+                            tx_clone.clone(),
+                            shutdown_rx_clone.clone(),
+                            rcm.clone(),
+                            &mut connection,
+                            &session_id,
+                            &text,
+                        ).await;
+                    }
+                    Some(Ok(Message::Binary(bytes))) => {
+                        handle_incomming_binary_message(
+                            tx_clone.clone(),
+                            rcm.clone(),
+                            &connection,
+                            &bytes,
+                        ).await;
+                    }
+                    Some(Ok(_)) => {} // Handle other message types if needed
+                    Some(Err(e)) => {
+                        error!("Error reading from socket: {}", e);
+                        break;
+                    }
+                    None => {
+                        error!("Client disconnected");
+                        break;
+                    }
+                }
+            }
+
+            // Handle sending messages from the channel to the WebSocket
+            Some(message) = rx.recv() => {
+                if socket.send(message).await.is_err() {
+                    error!("Client disconnected");
+                    break;
+                }
+            }
+
+            // Close the connection once the JWT expired
+            _ = time::sleep(Duration::from_millis(jwt_expires_in as u64)) => {
+                info!("JWT expired");
+
+                let close_frame = CloseFrame {
+                    code: close_code::POLICY,
+                    reason: "JWT expired".into(),
+                };
+                if socket.send(Message::Close(Some(close_frame))).await.is_err() {
+                    error!("Failed to close socket");
+                }
+                break;
+            }
+        }
+    }
+
+    shutdown_tx.send(()).unwrap();
+    info!("Websocket connection closed");
+}
+
+async fn handle_incomming_message(
+    tx: mpsc::Sender<Message>,
+    shutdown_rx: watch::Receiver<()>,
+    rcm: State<DynStore>,
+    connection: &mut Connection,
+    session_id: &String,
+    message: &str,
+) {
+    let reply_tx = tx.clone();
+
+    let header = serde_json::from_str::<RequestHeader>(message).unwrap_or_default();
+
+    let response = match WsRequest::parse(message) {
+        Ok(request) => {
+            // frames may carry a newer JWT of the same user
+            let identity = match &request.jwt {
+                Some(jwt) => match crate::decode_jwt(jwt) {
+                    Ok(claims) if &claims.aud == session_id && claims.sub == connection.user_id => {
+                        connection.jwt_expires_at = connection.jwt_expires_at.max(claims.exp);
+                        Ok(())
+                    }
+                    Ok(_) => Err(AppError::Unauthorized(
+                        "JWT does not match the connection.".to_string(),
+                    )),
+                    Err(e) => Err(e),
+                },
+                None => Ok(()),
+            };
+
+            let user_id = connection.user_id.clone();
+
+            // handle commands
+            match (identity, request.command) {
+                (Err(e), _) => Err(e),
+                (Ok(_), WsCommand::Hello(data)) => hello(tx, connection, data).await,
+                (Ok(_), WsCommand::Register(data)) => {
+                    register(
+                        tx,
+                        shutdown_rx,
+                        rcm.clone(),
+                        connection,
+                        session_id,
+                        &user_id,
+                        data,
+                    )
+                    .await
+                }
+                (Ok(_), WsCommand::RequestFile(data)) => {
+                    request_file(
+                        rcm.clone(),
+                        session_id,
+                        &user_id,
+                        connection.capabilities,
+                        data,
+                    )
+                    .await
+                }
+                (Ok(_), WsCommand::AcknowledgeFileRequest(data)) => {
+                    acknowledge_file_request(rcm.clone(), &user_id, data).await
+                }
+                (Ok(_), WsCommand::ReadyForFileTransfer(data)) => {
+                    ready_for_file_transfer(rcm.clone(), &user_id, data).await
+                }
+                (Ok(_), WsCommand::AddChunk(data)) => {
+                    add_text_chunk(rcm.clone(), &user_id, data).await
+                }
+                (Ok(_), WsCommand::ResumeTransfer(data)) => {
+                    resume_transfer(tx, rcm.clone(), session_id, &user_id, data).await
+                }
+                (Ok(_), WsCommand::ReceivedChunk(data)) => {
+                    received_chunk(rcm.clone(), session_id, &user_id, data).await
+                }
+                (Ok(_), WsCommand::CancelTransfer(data)) => {
+                    cancel_transfer(rcm.clone(), session_id, &user_id, data).await
+                }
+            }
+        }
+        Err(e) => {
+            error!("Failed to deserialize incoming message: {}", e);
+            Err(AppError::bad_request(format!(
+                "Wrong message format: {}",
+                e
+            )))
+        }
+    };
+
+    send_reply(reply_tx, header.id, header.command, response).await;
+}
+
+/// Lets the client know whether its command succeeded.
+async fn send_reply(
+    tx: mpsc::Sender<Message>,
+    id: Option<String>,
+    command: Option<String>,
+    response: Result<(), AppError>,
+) {
+    let message = match response {
+        Ok(_) => WsMessage {
+            request_id: "".to_string(),
+            msg: WsMsg::Ok(WsMsgOk { id, command }),
+        },
+        Err(e) => {
+            e.log();
+
+            WsMessage {
+                request_id: "".to_string(),
+                msg: WsMsg::Error(WsMsgError {
+                    id,
+                    command,
+                    code: e.code().to_string(),
+                    message: e.message().to_string(),
+                    field: e.field().map(|field| field.to_string()),
+                }),
+            }
+        }
+    };
+
+    let message_str = match to_json(&message) {
+        Ok(message_str) => message_str,
+        Err(e) => return e.log(),
+    };
+    if tx.send(Message::Text(message_str)).await.is_err() {
+        error!("Failed to send reply");
+    }
+}
+
+/// Serializes a message sent to or stored for the clients.
+fn to_json<T: Serialize + ?Sized>(value: &T) -> Result<String, AppError> {
+    serde_json::to_string(value)
+        .map_err(|e| AppError::Internal(format!("failed to serialize message: {}", e)))
+}
+
+async fn handle_incomming_binary_message(
+    tx: mpsc::Sender<Message>,
+    rcm: State<DynStore>,
+    connection: &Connection,
+    message: &[u8],
+) {
+    let response = if !connection.is_registered {
+        Err(AppError::Unauthorized("Not registered.".to_string()))
+    } else {
+        match ChunkFrame::decode(message) {
+            Ok(frame) => add_chunk(rcm, &connection.user_id, frame).await,
+            Err(e) => Err(e),
+        }
+    };
+
+    // binary chunks are only answered on failure
+    if response.is_err() {
+        send_reply(tx, None, Some("add-chunk".to_string()), response).await;
+    }
+}
+
+/// States the protocol version and capabilities of the client.
+async fn hello(
+    tx: mpsc::Sender<Message>,
+    connection: &mut Connection,
+    data: ReqHello,
+) -> Result<(), AppError> {
+    check_protocol_version(data.version)?;
+
+    connection.protocol_version = Some(data.version);
+    connection.capabilities = Capabilities::from_names(&data.capabilities);
+
+    let message = WsMessage {
+        request_id: "".to_string(),
+        msg: WsMsg::Hello(WsMsgHello {
+            version: protocol::PROTOCOL_VERSION,
+            capabilities: protocol::CAPABILITIES
+                .iter()
+                .map(|capability| capability.to_string())
+                .collect(),
+        }),
+    };
+
+    let message_str = to_json(&message)?;
+    if tx.send(Message::Text(message_str)).await.is_err() {
+        error!("Failed to send hello");
+    }
+
+    Ok(())
+}
+
+fn check_protocol_version(version: u32) -> Result<(), AppError> {
+    if !protocol::SUPPORTED_PROTOCOL_VERSIONS.contains(&version) {
+        return Err(AppError::UnsupportedVersion(format!(
+            "Unsupported protocol version: {}",
+            version
+        )));
+    }
+
+    Ok(())
+}
+
+async fn register(
+    tx: mpsc::Sender<Message>,
+    shutdown_rx: watch::Receiver<()>,
+    rcm: State<DynStore>,
+    connection: &mut Connection,
+    session_id: &String,
+    user_id: &String,
+    data: ReqRegister,
+) -> Result<(), AppError> {
+    // clients predating the handshake speak version 1
+    let version = data
+        .version
+        .or(connection.protocol_version)
+        .unwrap_or(protocol::PROTOCOL_VERSION);
+    check_protocol_version(version)?;
+
+    let capabilities = match data.capabilities {
+        Some(capabilities) => Capabilities::from_names(&capabilities),
+        None => connection.capabilities,
+    };
+
+    start_listeners(tx, shutdown_rx, rcm, session_id, user_id, capabilities).await?;
+
+    connection.is_registered = true;
+    connection.protocol_version = Some(version);
+    connection.capabilities = capabilities;
+
+    Ok(())
+}
+
+async fn start_listeners(
+    tx: mpsc::Sender<Message>,
+    mut shutdown_rx: watch::Receiver<()>,
+    rcm: State<DynStore>,
+    session_id: &String,
+    user_id: &String,
+    capabilities: Capabilities,
+) -> Result<(), AppError> {
+    info!("Start listening.");
+    let notify = Arc::new(Notify::new());
+    if let Entry::Vacant(entry) = LISTENERS.entry(user_id.to_string()) {
+        entry.insert(Listener {
+            notify: notify.clone(),
+            tx: tx.clone(),
+        });
+
+        let session_id = session_id.to_string();
+        let user_id = user_id.to_string();
+
+        // handle everything that happened before the listener was registered
+        notify.notify_one();
+
+        tokio::spawn(async move {
+            let mut queue_positions: HashMap<String, usize> = HashMap::new();
+
+            loop {
+                tokio::select! {
+                        _ = notify.notified() => {
+                        // acknowledge-file-request \\
+                        match msg_acknowledge_file_request(
+                            tx.clone(),
+                            rcm.clone(),
+                            &session_id,
+                            &user_id,
+                            capabilities.broadcast,
+                        )
+                        .await
+                        {
+                            Ok(_) => (),
+                            Err(e) => {
+                                error!("Message acknowledge-file-request failed: {}", e);
+                                continue;
+                            }
+                        }
+
+                        // queue-position \\
+                        match msg_queue_position(tx.clone(), rcm.clone(), &session_id, &user_id, &mut queue_positions)
+                            .await
+                        {
+                            Ok(_) => (),
+                            Err(e) => {
+                                error!("Message queue-position failed: {}", e);
+                                continue;
+                            }
+                        }
+
+                        // prepare-for-file-transfer \\
+                        match msg_prepare_for_file_request(tx.clone(), rcm.clone(), &session_id, &user_id)
+                            .await
+                        {
+                            Ok(_) => (),
+                            Err(e) => {
+                                error!("Message prepare-for-file-transfer failed: {}", e);
+                                continue;
+                            }
+                        }
+
+                        // send-next-chunk \\
+                        match msg_send_next_chunk(tx.clone(), rcm.clone(), &session_id, &user_id).await {
+                            Ok(_) => (),
+                            Err(e) => {
+                                error!("Message send-next-chunk failed: {}", e);
+                                continue;
+                            }
+                        }
+
+                        // add-chunk \\
+                        match msg_add_chunk(tx.clone(), rcm.clone(), &session_id, &user_id, capabilities.binary_chunks, capabilities.chunk_digests).await {
+                            Ok(_) => (),
+                            Err(e) => {
+                                error!("Message add-chunk failed: {}", e);
+                                continue;
+                            }
+                        }
+                    }
+                    _ = shutdown_rx.changed() => {
+                        info!("Listener shutdown signal received.");
+                        LISTENERS.remove(&user_id);
+                        pause_transfers(rcm.clone(), &session_id, &user_id).await;
+                        break;
+                    }
+                }
+            }
+
+            info!("Listener terminated.");
+        });
+    } else {
+        return Err(AppError::Conflict("Listener already running.".to_string()));
+    }
+
+    Ok(())
+}
+
+/// The capabilities of the receiver are kept with the request,
+/// only receivers supporting broadcasts are grouped into one.
+async fn request_file(
+    rcm: State<DynStore>,
+    session_id: &String,
+    user_id: &String,
+    capabilities: Capabilities,
+    data: ReqRequestFile,
+) -> Result<(), AppError> {
+    info!("request_file");
+
+    let key = keys::files(session_id);
+    if !crate::redis_handler::sismember(rcm.clone(), &key, &data.filename).await? {
+        return Err(AppError::NotFound("File not found.".to_string()));
+    }
+
+    let key = keys::file(session_id, &data.filename);
+    let owner_id = crate::redis_handler::hget(rcm.clone(), &key, "owner.id").await?;
+    if &owner_id == user_id {
+        return Err(AppError::bad_request("You cannot request your own file."));
+    }
+
+    let key = keys::file_reqs_queue(session_id, user_id);
+    let queued_files = crate::redis_handler::lrange(rcm.clone(), &key).await?;
+
+    if queued_files.contains(&data.filename)
+        || is_receiving_file(rcm.clone(), session_id, user_id, &data.filename).await?
+    {
+        return Err(AppError::Conflict(
+            "You have already requested this file.".to_string(),
+        ));
+    }
+
+    // the first request for a file opens the join window of a broadcast
+    let key = keys::file_reqs_since(session_id, &data.filename);
+    let timestamp = crate::get_current_timestamp().to_string();
+    if crate::redis_handler::set_nx(rcm.clone(), &key, &timestamp, None).await? {
+        let rcm = rcm.clone();
+        let owner_id = owner_id.clone();
+        tokio::spawn(async move {
+            time::sleep(Duration::from_secs(*BROADCAST_JOIN_WINDOW)).await;
+            crate::publish_user_event(rcm, &owner_id).await;
+        });
+    }
+
+    let key = keys::file_reqs_of_file(session_id, &data.filename);
+    let mut transaction = Transaction::new();
+    transaction
+        .require_member(&keys::files(session_id), &data.filename)
+        .require_not_member(&key, user_id)
+        .sadd(&keys::file_reqs(session_id), &data.filename, None)
+        .sadd(&key, user_id, None)
+        .set(
+            &keys::file_req(session_id, &data.filename, user_id),
+            &data.public_key,
+            None,
+        )
+        .set(
+            &keys::file_req_capabilities(session_id, &data.filename, user_id),
+            &capabilities.to_names().join(","),
+            None,
+        )
+        .rpush(
+            &keys::file_reqs_queue(session_id, user_id),
+            &data.filename,
+            None,
+        );
+
+    if !crate::redis_handler::exec_atomic(rcm.clone(), transaction).await? {
+        return Err(AppError::Conflict(
+            "You have already requested this file.".to_string(),
+        ));
+    }
+
+    crate::prolong_session(rcm.clone(), session_id).await;
+
+    crate::publish_user_event(rcm.clone(), &owner_id).await;
+    crate::publish_user_event(rcm, user_id).await;
+
+    Ok(())
+}
+
+/// Whether the user is already receiving the file in one of its file requests.
+async fn is_receiving_file(
+    rcm: State<DynStore>,
+    session_id: &str,
+    user_id: &str,
+    filename: &String,
+) -> Result<bool, AppError> {
+    let key = keys::file_reqs_receiver(session_id, user_id);
+    let request_ids = crate::redis_handler::smembers(rcm.clone(), &key).await?;
+
+    for request_id in request_ids {
+        let key = keys::file_req_filename(&request_id);
+        if &crate::redis_handler::get(rcm.clone(), &key).await? == filename {
+            return Ok(true);
+        }
+    }
+
+    Ok(false)
+}
+
+/// Position of each queued file of a user.
+/// Files at position 0 start as soon as the sender acknowledges them,
+/// the others wait for running transfers of the user to finish.
+async fn get_queue_positions(
+    rcm: State<DynStore>,
+    session_id: &str,
+    user_id: &str,
+) -> Result<Vec<(String, usize)>, AppError> {
+    let key = keys::file_reqs_receiver(session_id, user_id);
+    let running_transfers = crate::redis_handler::smembers(rcm.clone(), &key)
+        .await?
+        .len();
+    let free_slots = MAX_CONCURRENT_TRANSFERS.saturating_sub(running_transfers);
+
+    let key = keys::file_reqs_queue(session_id, user_id);
+    let queued_files = crate::redis_handler::lrange(rcm, &key).await?;
+
+    Ok(queued_files
+        .into_iter()
+        .enumerate()
+        .map(|(index, filename)| (filename, (index + 1).saturating_sub(free_slots)))
+        .collect())
+}
+
+/// Wakes the senders of the files a user is waiting for, as well as the user itself.
+async fn wake_queue(rcm: State<DynStore>, session_id: &str, user_id: &String) {
+    let key = keys::file_reqs_queue(session_id, user_id);
+    let queued_files: Vec<String> = crate::redis_handler::lrange(rcm.clone(), &key)
+        .await
+        .unwrap_or_default();
+
+    for filename in queued_files {
+        let key = keys::file(session_id, &filename);
+        match crate::redis_handler::hget(rcm.clone(), &key, "owner.id").await {
+            Ok(owner_id) => crate::publish_user_event(rcm.clone(), &owner_id).await,
+            Err(e) => {
+                error!("Failed to get owner of queued file: {} ({})", &filename, e);
+            }
+        }
+    }
+
+    crate::publish_user_event(rcm, user_id).await;
+}
+
+async fn acknowledge_file_request(
+    rcm: State<DynStore>,
+    user_id: &String,
+    data: ReqAcknowledgeFileRequest,
+) -> Result<(), AppError> {
+    info!("acknowledge_file_request");
+
+    let key = keys::file_req_sender(&data.request_id);
+    if &crate::redis_handler::get(rcm.clone(), &key).await? != user_id {
+        return Err(AppError::Unauthorized(
+            "User is not the sender of the file request.".to_string(),
+        ));
+    }
+
+    // written at once, unless the file request was cancelled in the meantime
+    let mut transaction = Transaction::new();
+    transaction.require_member(&keys::file_req_users(&data.request_id), user_id);
+
+    let receiver_ids = get_receiver_ids(rcm.clone(), &data.request_id, user_id).await?;
+    if receiver_ids.len() > 1 || data.wrapped_keys.is_some() {
+        let wrapped_keys = data.wrapped_keys.unwrap_or_default();
+
+        let mut items: Vec<(String, String)> = Vec::new();
+        for receiver_id in receiver_ids {
+            let wrapped_key = match wrapped_keys.get(&receiver_id) {
+                Some(wrapped_key) => wrapped_key,
+                None => {
+                    return Err(AppError::invalid_field(
+                        "wrapped_keys",
+                        "Missing wrapped key.",
+                    ));
+                }
+            };
+
+            items.push((receiver_id, to_json(wrapped_key)?));
+        }
+
+        let items = items
+            .iter()
+            .map(|(receiver_id, wrapped_key)| (receiver_id.as_str(), wrapped_key.as_str()))
+            .collect::<Vec<(&str, &str)>>();
+
+        transaction.hset_multiple(&keys::file_req_keys(&data.request_id), &items, None);
+    }
+
+    let window_size = negotiate_window_size(*MAX_WINDOW_SIZE, data.window_size);
+
+    let amount_of_chunks = data.amount_of_chunks.to_string();
+    let window_size_str = window_size.to_string();
+    let mut items = vec![
+        ("filename", data.filename.as_str()),
+        ("public.key", data.public_key.as_str()),
+        ("amount.of.chunks", amount_of_chunks.as_str()),
+        ("window.size", window_size_str.as_str()),
+    ];
+
+    // forwarded as is, only the receivers can decrypt it
+    let content_hash = data.content_hash.as_ref().map(to_json).transpose()?;
+    if let Some(content_hash) = &content_hash {
+        items.push(("content.hash", content_hash.as_str()));
+    }
+
+    transaction
+        .hset_multiple(&keys::file_req_prep(&data.request_id), &items, None)
+        .set(
+            &keys::chunk_amount(&data.request_id),
+            &amount_of_chunks,
+            None,
+        )
+        .set(
+            &keys::chunk_window(&data.request_id),
+            &window_size_str,
+            None,
+        );
+
+    if !crate::redis_handler::exec_atomic(rcm.clone(), transaction).await? {
+        return Err(AppError::NotFound("File request not found.".to_string()));
+    }
+
+    crate::publish_request_event(rcm, &data.request_id).await;
+
+    Ok(())
+}
+
+/// Users receiving the file of a request, i.e. everyone but the sender.
+async fn get_receiver_ids(
+    rcm: State<DynStore>,
+    request_id: &str,
+    sender_id: &String,
+) -> Result<Vec<String>, AppError> {
+    let key = keys::file_req_users(request_id);
+    let user_ids = crate::redis_handler::smembers(rcm, &key).await?;
+
+    Ok(user_ids
+        .into_iter()
+        .filter(|user_id| user_id != sender_id)
+        .collect())
+}
+
+/// Lowest chunk number acknowledged by all receivers of a request.
+/// Paused receivers of a broadcast do not hold back the others, unless every receiver is paused.
+/// Returns `None` until every receiver is ready for the transfer.
+async fn get_acked_chunk_nr(
+    rcm: State<DynStore>,
+    request_id: &str,
+) -> Result<Option<u32>, AppError> {
+    let key = keys::file_req_sender(request_id);
+    let sender_id = crate::redis_handler::get(rcm.clone(), &key).await?;
+
+    let mut receiver_ids = get_receiver_ids(rcm.clone(), request_id, &sender_id).await?;
+    if receiver_ids.is_empty() {
+        return Ok(None);
+    }
+
+    let key = keys::transfer_paused(request_id);
+    let paused_ids = crate::redis_handler::smembers(rcm.clone(), &key).await?;
+    if receiver_ids
+        .iter()
+        .any(|receiver_id| !paused_ids.contains(receiver_id))
+    {
+        receiver_ids.retain(|receiver_id| !paused_ids.contains(receiver_id));
+    }
+
+    let key = keys::chunk_acked(request_id);
+    let acked_chunk_nrs = crate::redis_handler::hgetall(rcm, &key).await?;
+
+    get_min_acked_chunk_nr(&acked_chunk_nrs, &receiver_ids)
+}
+
+/// Lowest chunk number of `chunk.acked` among the receivers,
+/// `None` if there are none or one of them has none.
+fn get_min_acked_chunk_nr(
+    acked_chunk_nrs: &[String],
+    receiver_ids: &[String],
+) -> Result<Option<u32>, AppError> {
+    let mut acked_chunk_nr: Option<u32> = None;
+    for receiver_id in receiver_ids {
+        let chunk_nr = match crate::get_hash_value(acked_chunk_nrs, receiver_id) {
+            Some(chunk_nr) => chunk_nr,
+            None => return Ok(None),
+        };
+        let chunk_nr: u32 = chunk_nr.parse().map_err(|e| {
+            AppError::Internal(format!(
+                "invalid acked chunk nr {} of {} ({})",
+                chunk_nr, receiver_id, e
+            ))
+        })?;
+
+        acked_chunk_nr = Some(acked_chunk_nr.map_or(chunk_nr, |min| min.min(chunk_nr)));
+    }
+
+    Ok(acked_chunk_nr)
+}
+
+/// Picks the smaller of the offered and the requested window size.
+/// Clients that do not request a window size transfer one chunk at a time.
+fn negotiate_window_size(offered: u32, requested: Option<u32>) -> u32 {
+    requested.unwrap_or(1).clamp(1, offered.max(1))
+}
+
+async fn ready_for_file_transfer(
+    rcm: State<DynStore>,
+    user_id: &str,
+    data: ReqReadyForFileRequest,
+) -> Result<(), AppError> {
+    info!("ready_for_file_transfer");
+
+    crate::check_user_is_in_file_request(rcm.clone(), &data.request_id, user_id).await?;
+
+    let key = keys::chunk_window(&data.request_id);
+    let offered_window_size = crate::redis_handler::get(rcm.clone(), &key).await?;
+    let window_size =
+        negotiate_window_size(offered_window_size.parse().unwrap_or(1), data.window_size);
+
+    // the transfer starts once every receiver is ready
+    let timestamp = crate::get_current_timestamp().to_string();
+    let mut transaction = Transaction::new();
+    transaction
+        .require_member(&keys::file_req_users(&data.request_id), user_id)
+        .set(&key, &window_size.to_string(), None)
+        .hset_multiple(
+            &keys::chunk_acked(&data.request_id),
+            &[(user_id, "0")],
+            None,
+        )
+        .hset_multiple(&keys::chunk_sent(&data.request_id), &[(user_id, "0")], None)
+        .set_nx(&keys::chunk_req(&data.request_id), "0", None)
+        .set_nx(&keys::transfer_started(&data.request_id), &timestamp, None);
+
+    if !crate::redis_handler::exec_atomic(rcm.clone(), transaction).await? {
+        return Err(AppError::NotFound("File request not found.".to_string()));
+    }
+
+    crate::publish_request_event(rcm, &data.request_id).await;
+
+    Ok(())
+}
+
+async fn add_text_chunk(
+    rcm: State<DynStore>,
+    user_id: &str,
+    data: ReqAddChunk,
+) -> Result<(), AppError> {
+    if data.chunk.len() > MAX_CHUNK_SIZE {
+        error!("chunk size: {}", data.chunk.len());
+        return Err(AppError::invalid_field("chunk", "Chunk too big."));
+    }
+
+    let frame = ChunkFrame::from_text(
+        &data.request_id,
+        data.chunk_nr,
+        data.is_last_chunk,
+        &data.chunk,
+        &data.iv,
+        data.digest.as_deref(),
+    )?;
+
+    add_chunk(rcm, user_id, frame).await
+}
+
+async fn add_chunk(rcm: State<DynStore>, user_id: &str, frame: ChunkFrame) -> Result<(), AppError> {
+    info!("add_chunk");
+
+    crate::check_user_is_in_file_request(rcm.clone(), &frame.request_id, user_id).await?;
+
+    if frame.chunk.len() > MAX_CHUNK_SIZE {
+        error!("chunk size: {}", frame.chunk.len());
+        return Err(AppError::invalid_field("chunk", "Chunk too big."));
+    }
+
+    let acked_chunk_nr = get_acked_chunk_nr(rcm.clone(), &frame.request_id)
+        .await?
+        .unwrap_or(0);
+
+    let key = keys::chunk_req(&frame.request_id);
+    let requested_chunk_nr: u32 = crate::redis_handler::get(rcm.clone(), &key)
+        .await?
+        .parse()
+        .unwrap_or(0);
+
+    if frame.chunk_nr <= acked_chunk_nr || frame.chunk_nr > requested_chunk_nr {
+        return Err(AppError::invalid_field("chunk_nr", "Wrong chunk number."));
+    }
+
+    let field = frame.chunk_nr.to_string();
+    let size = frame.chunk.len().to_string();
+    let chunks_key = keys::chunks(&frame.request_id);
+    let chunk_size_key = keys::chunk_size(&frame.request_id);
+
+    let mut transaction = Transaction::new();
+    transaction.require_hash_value(&chunk_size_key, &field, None);
+
+    // chunks are buffered in order, after the last one acknowledged by every receiver
+    let previous_chunk_nr = frame.chunk_nr - 1;
+    if previous_chunk_nr > acked_chunk_nr {
+        let chunk_sizes = crate::redis_handler::hgetall(rcm.clone(), &chunk_size_key).await?;
+        let previous_field = previous_chunk_nr.to_string();
+        let previous_size = match crate::get_hash_value(&chunk_sizes, &previous_field) {
+            Some(previous_size) => previous_size,
+            None => {
+                return Err(AppError::invalid_field("chunk_nr", "Chunk out of order."));
+            }
+        };
+        transaction.require_hash_value(&chunk_size_key, &previous_field, Some(&previous_size));
+    }
+
+    transaction
+        .hset_bytes(&chunks_key, &field, &frame.encode()?, None)
+        .hset_multiple(&chunk_size_key, &[(field.as_str(), size.as_str())], None);
+
+    if frame.is_last_chunk {
+        transaction.set(&keys::chunk_last(&frame.request_id), &field, None);
+    }
+
+    if !crate::redis_handler::exec_atomic(rcm.clone(), transaction).await? {
+        if crate::redis_handler::hexists(rcm.clone(), &chunks_key, &field).await? {
+            return Err(AppError::Conflict("Chunk already added.".to_string()));
+        }
+        return Err(AppError::invalid_field("chunk_nr", "Chunk out of order."));
+    }
+
+    crate::publish_request_event(rcm, &frame.request_id).await;
+
+    Ok(())
+}
+
+async fn received_chunk(
+    rcm: State<DynStore>,
+    session_id: &str,
+    user_id: &String,
+    data: ReqReceivedChunk,
+) -> Result<(), AppError> {
+    info!("received_chunk");
+
+    crate::check_user_is_in_file_request(rcm.clone(), &data.request_id, user_id).await?;
+
+    let key = keys::chunk_sent(&data.request_id);
+    let sent_chunk_nrs = crate::redis_handler::hgetall(rcm.clone(), &key).await?;
+    let sent_chunk_nr: u32 = match crate::get_hash_value(&sent_chunk_nrs, user_id) {
+        Some(chunk_nr) => chunk_nr.parse().unwrap_or(0),
+        None => return Err(AppError::NotFound("Chunk not found.".to_string())),
+    };
+
+    let acked_key = keys::chunk_acked(&data.request_id);
+    let mut acked_chunk_nrs = crate::redis_handler::hgetall(rcm.clone(), &acked_key).await?;
+    let prev_acked_chunk_nr = crate::get_hash_value(&acked_chunk_nrs, user_id);
+    let acked_chunk_nr: u32 = prev_acked_chunk_nr
+        .as_ref()
+        .and_then(|chunk_nr| chunk_nr.parse().ok())
+        .unwrap_or(0);
+
+    let last_acked_chunk_nr = data.last_chunk_nr.unwrap_or(data.chunk_nr);
+
+    if data.chunk_nr != acked_chunk_nr + 1
+        || last_acked_chunk_nr < data.chunk_nr
+        || last_acked_chunk_nr > sent_chunk_nr
+    {
+        return Err(AppError::Conflict("Chunk number mismatch.".to_string()));
+    }
+
+    let key = keys::file_req_sender(&data.request_id);
+    let sender_id = crate::redis_handler::get(rcm.clone(), &key).await?;
+    let mut receiver_ids = get_receiver_ids(rcm.clone(), &data.request_id, &sender_id).await?;
+
+    let buffered_from_chunk_nr =
+        get_min_acked_chunk_nr(&acked_chunk_nrs, &receiver_ids)?.unwrap_or(0);
+
+    let key = keys::chunk_last(&data.request_id);
+    let was_last_chunk = match crate::redis_handler::get(rcm.clone(), &key).await {
+        Ok(last_chunk_nr) => last_chunk_nr == last_acked_chunk_nr.to_string(),
+        Err(_) => false,
+    };
+
+    let key = keys::chunk_size(&data.request_id);
+    let chunk_sizes = crate::redis_handler::hgetall(rcm.clone(), &key).await?;
+    let acked_bytes: u64 = ((acked_chunk_nr + 1)..=last_acked_chunk_nr)
+        .filter_map(|chunk_nr| crate::get_hash_value(&chunk_sizes, &chunk_nr.to_string()))
+        .map(|size| size.parse::<u64>().unwrap_or(0))
+        .sum();
+
+    let bytes_key = keys::chunk_bytes(&data.request_id);
+    let bytes = crate::redis_handler::hgetall(rcm.clone(), &bytes_key).await?;
+    let bytes = crate::get_hash_value(&bytes, user_id)
+        .and_then(|bytes| bytes.parse::<u64>().ok())
+        .unwrap_or(0)
+        + acked_bytes;
+
+    // acknowledgements of the same receiver must not interleave
+    let users_key = keys::file_req_users(&data.request_id);
+    let mut transaction = Transaction::new();
+    transaction
+        .require_member(&users_key, user_id)
+        .require_hash_value(&acked_key, user_id, prev_acked_chunk_nr.as_deref());
+
+    let last_acked_chunk_nr_str = last_acked_chunk_nr.to_string();
+    if was_last_chunk {
+        finish_transfer_of_receiver(&mut transaction, &data.request_id, user_id);
+        receiver_ids.retain(|receiver_id| receiver_id != user_id);
+    } else {
+        transaction
+            .hset_multiple(
+                &acked_key,
+                &[(user_id.as_str(), last_acked_chunk_nr_str.as_str())],
+                None,
+            )
+            .hset_multiple(&bytes_key, &[(user_id.as_str(), &bytes.to_string())], None);
+        match acked_chunk_nrs.iter().position(|field| field == user_id) {
+            Some(idx) => acked_chunk_nrs[idx + 1] = last_acked_chunk_nr_str.clone(),
+            None => acked_chunk_nrs.extend([user_id.clone(), last_acked_chunk_nr_str.clone()]),
+        }
+    }
+
+    // the sets of the session are updated once the state of the request is
+    let mut session_transaction = Transaction::new();
+    if was_last_chunk {
+        session_transaction.srem(
+            &keys::file_reqs_receiver(session_id, user_id),
+            &data.request_id,
+        );
+    }
+
+    let is_finished = receiver_ids.is_empty();
+    if is_finished {
+        for key in get_transfer_keys(&data.request_id) {
+            transaction.del(&key);
+        }
+        transaction.del(&users_key);
+        session_transaction
+            .srem(
+                &keys::file_reqs_sender(session_id, &sender_id),
+                &data.request_id,
+            )
+            .srem(&keys::transfers(session_id), &data.request_id);
+    } else {
+        // chunks are buffered until every receiver acknowledged them,
+        // paused receivers of a broadcast included so they can resume
+        let acked_by_all_chunk_nr = get_min_acked_chunk_nr(&acked_chunk_nrs, &receiver_ids)?
+            .unwrap_or(buffered_from_chunk_nr);
+
+        let chunk_nrs = ((buffered_from_chunk_nr + 1)..=acked_by_all_chunk_nr)
+            .map(|chunk_nr| chunk_nr.to_string())
+            .collect::<Vec<String>>();
+        transaction
+            .hdel(&keys::chunks(&data.request_id), &chunk_nrs)
+            .hdel(&keys::chunk_size(&data.request_id), &chunk_nrs);
+        session_transaction.expire(&keys::transfers(session_id), None);
+    }
+
+    // measured before the state of a finished transfer is deleted
+    let progress = get_transfer_progress(
+        rcm.clone(),
+        &data.request_id,
+        user_id,
+        last_acked_chunk_nr,
+        bytes,
+    )
+    .await;
+
+    if !crate::redis_handler::exec_atomic(rcm.clone(), transaction).await? {
+        return Err(AppError::Conflict("Chunk number mismatch.".to_string()));
+    }
+    crate::redis_handler::exec_atomic(rcm.clone(), session_transaction).await?;
+
+    publish_progress(rcm.clone(), &data.request_id, &sender_id, progress).await;
+
+    if was_last_chunk {
+        wake_queue(rcm.clone(), session_id, user_id).await;
+    }
+
+    if !is_finished {
+        crate::publish_request_event(rcm, &data.request_id).await;
+    }
+
+    Ok(())
+}
+
+/// Detaches a receiver that got the whole file from the transfer.
+fn finish_transfer_of_receiver(transaction: &mut Transaction, request_id: &str, user_id: &str) {
+    let fields = [user_id.to_string()];
+    transaction
+        .srem(&keys::file_req_users(request_id), user_id)
+        .hdel(&keys::chunk_acked(request_id), &fields)
+        .hdel(&keys::chunk_sent(request_id), &fields)
+        .hdel(&keys::chunk_bytes(request_id), &fields)
+        .hdel(&keys::file_req_keys(request_id), &fields);
+}
+
+/// Keys holding the state of a single file request, except for `file.req.users`.
+fn get_transfer_keys(request_id: &str) -> Vec<String> {
+    vec![
+        keys::file_req_sender(request_id),
+        keys::file_req_filename(request_id),
+        keys::file_req_prep(request_id),
+        keys::file_req_keys(request_id),
+        keys::file_req_prepared(request_id),
+        keys::transfer_paused(request_id),
+        keys::transfer_paused_since(request_id),
+        keys::chunk_window(request_id),
+        keys::chunk_amount(request_id),
+        keys::chunk_acked(request_id),
+        keys::chunk_req(request_id),
+        keys::chunk_sent(request_id),
+        keys::chunk_last(request_id),
+        keys::chunks(request_id),
+        keys::chunk_size(request_id),
+        keys::chunk_bytes(request_id),
+        keys::transfer_started(request_id),
+    ]
+}
+
+/// Progress of a receiver that acknowledged every chunk up to `chunks_done`, holding `bytes` bytes.
+/// Rate and ETA are measured from the moment the first receiver was ready.
+async fn get_transfer_progress(
+    rcm: State<DynStore>,
+    request_id: &str,
+    user_id: &str,
+    chunks_done: u32,
+    bytes: u64,
+) -> WsMsgTransferProgress {
+    let key = keys::chunk_amount(request_id);
+    let amount_of_chunks: u32 = match crate::redis_handler::get(rcm.clone(), &key).await {
+        Ok(amount_of_chunks) => amount_of_chunks.parse().unwrap_or(0),
+        Err(_) => 0,
+    };
+
+    let key = keys::transfer_started(request_id);
+    let elapsed = match crate::redis_handler::get(rcm, &key).await {
+        Ok(started_at) => started_at
+            .parse::<u128>()
+            .map(|started_at| crate::get_current_timestamp().saturating_sub(started_at))
+            .unwrap_or(0),
+        Err(_) => 0,
+    };
+
+    let rate = match elapsed {
+        0 => 0,
+        elapsed => (bytes as u128 * 1000 / elapsed) as u64,
+    };
+
+    let eta = match (chunks_done, amount_of_chunks, elapsed) {
+        (0, _, _) | (_, 0, _) | (_, _, 0) => None,
+        (chunks_done, amount_of_chunks, elapsed) => {
+            let remaining_chunks = amount_of_chunks.saturating_sub(chunks_done) as u128;
+            Some((elapsed * remaining_chunks / chunks_done as u128 / 1000) as u64)
+        }
+    };
+
+    WsMsgTransferProgress {
+        user_id: user_id.to_string(),
+        chunks_done,
+        amount_of_chunks,
+        bytes,
+        rate,
+        eta,
+    }
+}
+
+/// Lets the sender and the receiver know how far the receiver got.
+async fn publish_progress(
+    rcm: State<DynStore>,
+    request_id: &str,
+    sender_id: &String,
+    progress: WsMsgTransferProgress,
+) {
+    let receiver_id = progress.user_id.clone();
+    let message = WsMessage {
+        request_id: request_id.to_string(),
+        msg: WsMsg::TransferProgress(progress),
+    };
+    let message_str = match to_json(&message) {
+        Ok(message_str) => message_str,
+        Err(e) => return e.log(),
+    };
+
+    crate::publish_user_message(rcm.clone(), sender_id, &message_str).await;
+    crate::publish_user_message(rcm, &receiver_id, &message_str).await;
+}
+
+/// Keeps the file requests of a disconnected user alive for the grace period
+/// and lets the other participants know that their transfers are paused.
+async fn pause_transfers(rcm: State<DynStore>, session_id: &str, user_id: &String) {
+    let grace_period = *TRANSFER_GRACE_PERIOD;
+
+    let mut request_ids: Vec<String> = Vec::new();
+    for key in [
+        keys::file_reqs_sender(session_id, user_id),
+        keys::file_reqs_receiver(session_id, user_id),
+    ] {
+        let ids: Vec<String> = crate::redis_handler::smembers(rcm.clone(), &key)
+            .await
+            .unwrap_or_default();
+
+        request_ids.extend(ids);
+    }
+
+    // only the state of the paused request expires, the sets of the users hold other requests too;
+    // the request is added to the set of the user again when resuming
+    for request_id in request_ids {
+        info!("Pausing transfer: {}", &request_id);
+
+        match pause_receiver(rcm.clone(), session_id, &request_id, user_id).await {
+            Ok(true) => continue,
+            Ok(false) => (),
+            Err(e) => {
+                error!("Failed to pause receiver: {} ({})", &request_id, e);
+                continue;
+            }
+        }
+
+        let key = keys::file_req_users(&request_id);
+        let mut transaction = Transaction::new();
+        transaction.require_member(&key, user_id).sadd(
+            &keys::transfer_paused(&request_id),
+            user_id,
+            Some(grace_period),
+        );
+
+        let mut keys = get_transfer_keys(&request_id);
+        keys.push(key);
+        for key in keys {
+            transaction.expire(&key, Some(grace_period));
+        }
+
+        match crate::redis_handler::exec_atomic(rcm.clone(), transaction).await {
+            Ok(true) => (),
+            Ok(false) => continue,
+            Err(e) => {
+                error!("Failed to pause transfer: {} ({})", &request_id, e);
+                continue;
+            }
+        }
+
+        let message = WsMessage {
+            request_id: request_id.clone(),
+            msg: WsMsg::TransferPaused(WsMsgTransferPaused { grace_period }),
+        };
+        match to_json(&message) {
+            Ok(message_str) => {
+                publish_to_other_users(rcm.clone(), &request_id, user_id, &message_str).await
+            }
+            Err(e) => e.log(),
+        }
+    }
+}
+
+/// Pauses a receiver of a broadcast on its own, as long as another receiver carries on.
+/// The others are not held back by it and only the receiver is detached after the grace period.
+/// Returns `false` if the whole file request is paused instead.
+async fn pause_receiver(
+    rcm: State<DynStore>,
+    session_id: &str,
+    request_id: &str,
+    user_id: &str,
+) -> Result<bool, AppError> {
+    let grace_period = *TRANSFER_GRACE_PERIOD;
+
+    let key = keys::file_req_sender(request_id);
+    let sender_id = crate::redis_handler::get(rcm.clone(), &key).await?;
+    if sender_id == user_id {
+        return Ok(false);
+    }
+
+    let paused_key = keys::transfer_paused(request_id);
+    let paused_ids = crate::redis_handler::smembers(rcm.clone(), &paused_key).await?;
+    let receiver_ids = get_receiver_ids(rcm.clone(), request_id, &sender_id).await?;
+    let active_receiver_id = match receiver_ids
+        .iter()
+        .find(|receiver_id| *receiver_id != user_id && !paused_ids.contains(receiver_id))
+    {
+        Some(receiver_id) => receiver_id,
+        None => return Ok(false),
+    };
+
+    let users_key = keys::file_req_users(request_id);
+    let paused_since = crate::get_current_timestamp().to_string();
+    let mut transaction = Transaction::new();
+    transaction
+        .require_member(&users_key, user_id)
+        .require_member(&users_key, active_receiver_id)
+        .require_not_member(&paused_key, active_receiver_id)
+        .sadd(&paused_key, user_id, None)
+        .hset_multiple(
+            &keys::transfer_paused_since(request_id),
+            &[(user_id, paused_since.as_str())],
+            None,
+        );
+
+    if !crate::redis_handler::exec_atomic(rcm.clone(), transaction).await? {
+        return Ok(false);
+    }
+
+    {
+        let rcm = rcm.clone();
+        let session_id = session_id.to_string();
+        let request_id = request_id.to_string();
+        let user_id = user_id.to_string();
+        tokio::spawn(async move {
+            time::sleep(Duration::from_secs(grace_period as u64)).await;
+            if let Err(e) =
+                expire_paused_receiver(rcm, &session_id, &request_id, &user_id, &paused_since).await
+            {
+                error!("Failed to expire paused receiver: {} ({})", &request_id, e);
+            }
+        });
+    }
+
+    let message = WsMessage {
+        request_id: request_id.to_string(),
+        msg: WsMsg::TransferPaused(WsMsgTransferPaused { grace_period }),
+    };
+    match to_json(&message) {
+        Ok(message_str) => crate::publish_user_message(rcm.clone(), &sender_id, &message_str).await,
+        Err(e) => e.log(),
+    }
+
+    // the sender carries on with the other receivers
+    crate::publish_request_event(rcm, request_id).await;
+
+    Ok(true)
+}
+
+/// Detaches a receiver paused by [`pause_receiver`] that did not resume within the grace period.
+/// The chunks only kept for the receiver are dropped,
+/// the file request ends if the other receivers got the whole file already.
+async fn expire_paused_receiver(
+    rcm: State<DynStore>,
+    session_id: &str,
+    request_id: &str,
+    user_id: &str,
+    paused_since: &str,
+) -> Result<(), AppError> {
+    let users_key = keys::file_req_users(request_id);
+    let paused_key = keys::transfer_paused(request_id);
+    let paused_since_key = keys::transfer_paused_since(request_id);
+
+    // other receivers finishing in the meantime change the writes, those are retried
+    loop {
+        let paused_since_values =
+            crate::redis_handler::hgetall(rcm.clone(), &paused_since_key).await?;
+        let is_paused = crate::redis_handler::sismember(rcm.clone(), &paused_key, user_id).await?
+            && crate::get_hash_value(&paused_since_values, user_id).as_deref()
+                == Some(paused_since);
+        if !is_paused {
+            return Ok(());
+        }
+
+        let key = keys::file_req_sender(request_id);
+        let sender_id = crate::redis_handler::get(rcm.clone(), &key).await?;
+        let receiver_ids = get_receiver_ids(rcm.clone(), request_id, &sender_id).await?;
+        let other_receiver_ids = receiver_ids
+            .iter()
+            .filter(|receiver_id| *receiver_id != user_id)
+            .cloned()
+            .collect::<Vec<String>>();
+
+        let mut transaction = Transaction::new();
+        transaction
+            .require_member(&paused_key, user_id)
+            .require_hash_value(&paused_since_key, user_id, Some(paused_since));
+        for receiver_id in &other_receiver_ids {
+            transaction.require_member(&users_key, receiver_id);
+        }
+
+        let mut session_transaction = Transaction::new();
+        session_transaction.srem(&keys::file_reqs_receiver(session_id, user_id), request_id);
+
+        if other_receiver_ids.is_empty() {
+            for key in get_transfer_keys(request_id) {
+                transaction.del(&key);
+            }
+            transaction.del(&users_key);
+            session_transaction
+                .srem(&keys::file_reqs_sender(session_id, &sender_id), request_id)
+                .srem(&keys::transfers(session_id), request_id);
+        } else {
+            let fields = [user_id.to_string()];
+            finish_transfer_of_receiver(&mut transaction, request_id, user_id);
+            transaction
+                .srem(&paused_key, user_id)
+                .hdel(&paused_since_key, &fields);
+
+            let key = keys::chunk_acked(request_id);
+            let acked_chunk_nrs = crate::redis_handler::hgetall(rcm.clone(), &key).await?;
+            if let (Some(buffered_from_chunk_nr), Some(acked_by_all_chunk_nr)) = (
+                get_min_acked_chunk_nr(&acked_chunk_nrs, &receiver_ids)?,
+                get_min_acked_chunk_nr(&acked_chunk_nrs, &other_receiver_ids)?,
+            ) {
+                let chunk_nrs = ((buffered_from_chunk_nr + 1)..=acked_by_all_chunk_nr)
+                    .map(|chunk_nr| chunk_nr.to_string())
+                    .collect::<Vec<String>>();
+                transaction
+                    .hdel(&keys::chunks(request_id), &chunk_nrs)
+                    .hdel(&keys::chunk_size(request_id), &chunk_nrs);
+            }
+        }
+
+        if crate::redis_handler::exec_atomic(rcm.clone(), transaction).await? {
+            crate::redis_handler::exec_atomic(rcm.clone(), session_transaction).await?;
+            break;
+        }
+    }
+
+    info!("Paused receiver expired: {}", request_id);
+
+    crate::publish_request_event(rcm, request_id).await;
+
+    Ok(())
+}
+
+async fn publish_to_other_users(
+    rcm: State<DynStore>,
+    request_id: &str,
+    user_id: &String,
+    message: &str,
+) {
+    let key = keys::file_req_users(request_id);
+    let user_ids: Vec<String> = crate::redis_handler::smembers(rcm.clone(), &key)
+        .await
+        .unwrap_or_default();
+
+    for other_user_id in user_ids {
+        if &other_user_id == user_id {
+            continue;
+        }
+
+        crate::publish_user_message(rcm.clone(), &other_user_id, message).await;
+    }
+}
+
+async fn resume_transfer(
+    tx: mpsc::Sender<Message>,
+    rcm: State<DynStore>,
+    session_id: &str,
+    user_id: &String,
+    data: ReqResumeTransfer,
+) -> Result<(), AppError> {
+    info!("resume_transfer");
+
+    if !LISTENERS.contains_key(user_id) {
+        return Err(AppError::bad_request("Not registered."));
+    }
+
+    crate::check_user_is_in_file_request(rcm.clone(), &data.request_id, user_id).await?;
+
+    let key = keys::file_req_sender(&data.request_id);
+    let sender_id = crate::redis_handler::get(rcm.clone(), &key).await?;
+    let is_sender = &sender_id == user_id;
+
+    let mut transaction = Transaction::new();
+    transaction.require_member(&keys::file_req_users(&data.request_id), user_id);
+
+    let acked_chunk_nr = if is_sender {
+        get_acked_chunk_nr(rcm.clone(), &data.request_id).await?
+    } else {
+        let key = keys::chunk_acked(&data.request_id);
+        let acked_chunk_nrs = crate::redis_handler::hgetall(rcm.clone(), &key).await?;
+        crate::get_hash_value(&acked_chunk_nrs, user_id)
+            .map(|chunk_nr| chunk_nr.parse().unwrap_or(0))
+    };
+    let is_transferring = acked_chunk_nr.is_some();
+    let acked_chunk_nr: u32 = acked_chunk_nr.unwrap_or(0);
+
+    if is_transferring && is_sender {
+        // chunks requested before the disconnect are requested again,
+        // unless they already arrived
+        let key = keys::chunks(&data.request_id);
+        let mut buffered_chunk_nr = acked_chunk_nr;
+        while crate::redis_handler::hexists(rcm.clone(), &key, &(buffered_chunk_nr + 1).to_string())
+            .await?
+        {
+            buffered_chunk_nr += 1;
+        }
+
+        let key = keys::chunk_req(&data.request_id);
+        let requested_chunk_nr: u32 = crate::redis_handler::get(rcm.clone(), &key)
+            .await?
+            .parse()
+            .unwrap_or(0);
+
+        let dropped_chunk_nrs = ((buffered_chunk_nr + 1)..=requested_chunk_nr)
+            .map(|chunk_nr| chunk_nr.to_string())
+            .collect::<Vec<String>>();
+        transaction
+            .hdel(&keys::chunks(&data.request_id), &dropped_chunk_nrs)
+            .hdel(&keys::chunk_size(&data.request_id), &dropped_chunk_nrs)
+            .set(&key, &buffered_chunk_nr.to_string(), None);
+    } else if is_transferring {
+        // chunks sent before the disconnect are sent again
+        let acked_chunk_nr = acked_chunk_nr.to_string();
+        transaction.hset_multiple(
+            &keys::chunk_sent(&data.request_id),
+            &[(user_id.as_str(), acked_chunk_nr.as_str())],
+            None,
+        );
+    }
+
+    let paused_key = keys::transfer_paused(&data.request_id);
+    let fields = [user_id.to_string()];
+    transaction
+        .srem(&paused_key, user_id)
+        .hdel(&keys::transfer_paused_since(&data.request_id), &fields);
+
+    // the grace period ends once no other participant is paused anymore,
+    // the state then expires like the state of every transfer
+    let mut refreshing_transaction = transaction.clone();
+    refreshing_transaction.require_only_member(&paused_key, user_id);
+    let mut keys = get_transfer_keys(&data.request_id);
+    keys.push(keys::file_req_users(&data.request_id));
+    for key in keys {
+        refreshing_transaction.expire(&key, None);
+    }
+
+    if !crate::redis_handler::exec_atomic(rcm.clone(), refreshing_transaction).await?
+        && !crate::redis_handler::exec_atomic(rcm.clone(), transaction).await?
+    {
+        return Err(AppError::NotFound("File request not found.".to_string()));
+    }
+
+    let key = if is_sender {
+        keys::file_reqs_sender(session_id, user_id)
+    } else {
+        keys::file_reqs_receiver(session_id, user_id)
+    };
+    crate::redis_handler::sadd(rcm.clone(), &key, &data.request_id, None).await?;
+
+    let message = WsMessage {
+        request_id: data.request_id.clone(),
+        msg: WsMsg::TransferResumed(WsMsgTransferResumed {
+            chunk_nr: acked_chunk_nr,
+        }),
+    };
+    // the transfer is resumed already, the others still pick it up with the next event
+    match to_json(&message) {
+        Ok(message_str) => {
+            if tx.send(Message::Text(message_str.clone())).await.is_err() {
+                error!("Failed to send transfer-resumed");
+            }
+            // the other receivers of a broadcast are not paused by a receiver
+            if is_sender {
+                publish_to_other_users(rcm.clone(), &data.request_id, user_id, &message_str).await;
+            } else {
+                crate::publish_user_message(rcm.clone(), &sender_id, &message_str).await;
+            }
+        }
+        Err(e) => e.log(),
+    }
+
+    crate::publish_request_event(rcm, &data.request_id).await;
+
+    Ok(())
+}
+
+/// Aborts a file request on behalf of one of its participants.
+async fn cancel_transfer(
+    rcm: State<DynStore>,
+    session_id: &str,
+    user_id: &String,
+    data: ReqCancelTransfer,
+) -> Result<(), AppError> {
+    info!("cancel_transfer");
+
+    crate::check_user_is_in_file_request(rcm.clone(), &data.request_id, user_id).await?;
+
+    let key = keys::file_req_users(&data.request_id);
+    let user_ids = crate::redis_handler::smembers(rcm.clone(), &key).await?;
+
+    let mut transaction = Transaction::new();
+    transaction.require_member(&key, user_id).del(&key);
+    for key in get_transfer_keys(&data.request_id) {
+        transaction.del(&key);
+    }
+    if !crate::redis_handler::exec_atomic(rcm.clone(), transaction).await? {
+        return Err(AppError::Conflict(
+            "File request already finished.".to_string(),
+        ));
+    }
+
+    let mut transaction = Transaction::new();
+    transaction.srem(&keys::transfers(session_id), &data.request_id);
+    for user_id in &user_ids {
+        transaction
+            .srem(
+                &keys::file_reqs_sender(session_id, user_id),
+                &data.request_id,
+            )
+            .srem(
+                &keys::file_reqs_receiver(session_id, user_id),
+                &data.request_id,
+            );
+    }
+    crate::redis_handler::exec_atomic(rcm.clone(), transaction).await?;
+
+    let message = WsMessage {
+        request_id: data.request_id.clone(),
+        msg: WsMsg::TransferCancelled(WsMsgTransferCancelled {
+            reason: data
+                .reason
+                .unwrap_or("Cancelled by the other participant.".to_string()),
+        }),
+    };
+    match to_json(&message) {
+        Ok(message_str) => {
+            for other_user_id in user_ids.iter().filter(|id| *id != user_id) {
+                crate::publish_user_message(rcm.clone(), other_user_id, &message_str).await;
+            }
+        }
+        Err(e) => e.log(),
+    }
+
+    // queued files of the receivers might start now
+    for user_id in &user_ids {
+        wake_queue(rcm.clone(), session_id, user_id).await;
+    }
+
+    Ok(())
+}
+
+async fn msg_acknowledge_file_request(
+    tx: mpsc::Sender<Message>,
+    rcm: State<DynStore>,
+    session_id: &str,
+    user_id: &String,
+    broadcast: bool,
+) -> Result<(), String> {
+    let user_files: Vec<String> = crate::get_user_files(rcm.clone(), session_id, user_id)
+        .await
+        .unwrap_or_default();
+    if user_files.is_empty() {
+        return Ok(());
+    }
+
+    let key = keys::file_reqs(session_id);
+    let requested_files = match crate::redis_handler::smembers(rcm.clone(), &key).await {
+        Ok(files) => files,
+        Err(_) => return Ok(()),
+    };
+
+    for file in user_files {
+        if !requested_files.contains(&file) {
+            continue;
+        }
+
+        let key = keys::file_reqs_since(session_id, &file);
+        if broadcast {
+            let since: u128 = match crate::redis_handler::get(rcm.clone(), &key).await {
+                Ok(since) => since.parse().unwrap_or(0),
+                Err(_) => continue,
+            };
+
+            // more receivers might join
+            let join_window = (*BROADCAST_JOIN_WINDOW as u128).saturating_mul(1000);
+            if crate::get_current_timestamp() < since + join_window {
+                continue;
+            }
+        }
+
+        // the pending requests are taken over at once, or not at all if another instance was faster
+        let reqs_key = keys::file_reqs(session_id);
+        let mut transaction = Transaction::new();
+        transaction.require_member(&reqs_key, &file).del(&key);
+
+        let key = keys::file_reqs_of_file(session_id, &file);
+        let user_ids = match crate::redis_handler::smembers(rcm.clone(), &key).await {
+            Ok(user_ids) => user_ids,
+            Err(_) => continue,
+        };
+
+        let mut receivers: Vec<WsMsgReceiver> = Vec::new();
+        let mut broadcast_receivers: Vec<WsMsgReceiver> = Vec::new();
+        let mut has_queued_receivers = false;
+        for rec_user_id in user_ids {
+            // receivers at their concurrency limit stay queued
+            let queue_position =
+                match get_queue_positions(rcm.clone(), session_id, &rec_user_id).await {
+                    Ok(positions) => positions
+                        .into_iter()
+                        .find(|(filename, _)| filename == &file)
+                        .map(|(_, position)| position)
+                        .unwrap_or(0),
+                    Err(_) => continue,
+                };
+            if queue_position > 0 {
+                has_queued_receivers = true;
+                continue;
+            }
+
+            let req_key = keys::file_req(session_id, &file, &rec_user_id);
+            let public_key = match crate::redis_handler::get(rcm.clone(), &req_key).await {
+                Ok(public_key) => public_key,
+                Err(_) => continue,
+            };
+
+            let capabilities_key = keys::file_req_capabilities(session_id, &file, &rec_user_id);
+            let capabilities = match crate::redis_handler::get(rcm.clone(), &capabilities_key).await
+            {
+                Ok(names) => Capabilities::from_names(
+                    &names.split(',').map(String::from).collect::<Vec<String>>(),
+                ),
+                Err(_) => continue,
+            };
+
+            transaction
+                .require_member(&key, &rec_user_id)
+                .del(&req_key)
+                .del(&capabilities_key)
+                .srem(&key, &rec_user_id);
+
+            let receiver = WsMsgReceiver {
+                user_id: rec_user_id,
+                public_key,
+            };
+            if broadcast && capabilities.broadcast {
+                broadcast_receivers.push(receiver);
+            } else {
+                receivers.push(receiver);
+            }
+        }
+
+        if !has_queued_receivers {
+            transaction.srem(&reqs_key, &file);
+        }
+
+        // a broadcast serves every receiver supporting it with a single file request,
+        // the others get a file request each
+        let mut receiver_groups: Vec<(Vec<WsMsgReceiver>, bool)> = receivers
+            .into_iter()
+            .map(|receiver| (vec![receiver], false))
+            .collect();
+        if !broadcast_receivers.is_empty() {
+            receiver_groups.push((broadcast_receivers, true));
+        }
+
+        let (request_ids, request_transactions): (Vec<String>, Vec<Transaction>) = receiver_groups
+            .iter()
+            .map(|(receivers, _)| {
+                let receiver_ids = receivers
+                    .iter()
+                    .map(|receiver| receiver.user_id.clone())
+                    .collect::<Vec<String>>();
+
+                create_file_request(&mut transaction, session_id, user_id, &receiver_ids, &file)
+            })
+            .unzip();
+
+        // the state of a new file request is written first, it is only reachable
+        // once the session lists the request
+        let mut is_created = true;
+        for request_transaction in request_transactions {
+            if let Err(e) =
+                crate::redis_handler::exec_atomic(rcm.clone(), request_transaction).await
+            {
+                error!("Failed to create file requests for file: {} ({})", &file, e);
+                is_created = false;
+                break;
+            }
+        }
+
+        let is_created = is_created
+            && match crate::redis_handler::exec_atomic(rcm.clone(), transaction).await {
+                Ok(is_taken_over) => is_taken_over,
+                Err(e) => {
+                    error!("Failed to create file requests for file: {} ({})", &file, e);
+                    false
+                }
+            };
+        if !is_created {
+            discard_file_requests(rcm.clone(), &request_ids).await;
+            continue;
+        }
+
+        for (request_id, (receivers, is_broadcast)) in request_ids.into_iter().zip(receiver_groups)
+        {
+            let message = WsMessage {
+                request_id,
+                msg: WsMsg::AcknowledgeFileRequest(WsMsgAcknowledgeFileRequest {
+                    public_key: receivers[0].public_key.clone(),
+                    filename: file.clone(),
+                    receivers: if is_broadcast { Some(receivers) } else { None },
+                }),
+            };
+
+            let message_str = to_json(&message).map_err(|e| e.to_string())?;
+            if tx.send(Message::Text(message_str)).await.is_err() {
+                return Err("Receiver dropped".to_string());
+            }
+        }
+    }
+
+    Ok(())
+}
+
+/// Adds the writes listing a new file request in the session to the transaction.
+/// Returns the ID of the new file request and the transaction creating its state.
+fn create_file_request(
+    transaction: &mut Transaction,
+    session_id: &str,
+    sender_id: &str,
+    receiver_ids: &[String],
+    filename: &str,
+) -> (String, Transaction) {
+    let request_id = crate::get_uuid();
+
+    let mut request_transaction = Transaction::new();
+    request_transaction.set(&keys::file_req_filename(&request_id), filename, None);
+
+    let key = keys::file_req_users(&request_id);
+    for rec_user_id in receiver_ids {
+        request_transaction.sadd(&key, rec_user_id, None);
+    }
+    request_transaction.sadd(&key, sender_id, None).set(
+        &keys::file_req_sender(&request_id),
+        sender_id,
+        None,
+    );
+
+    for rec_user_id in receiver_ids {
+        transaction
+            .sadd(
+                &keys::file_reqs_receiver(session_id, rec_user_id),
+                &request_id,
+                None,
+            )
+            .lrem(&keys::file_reqs_queue(session_id, rec_user_id), filename);
+    }
+
+    transaction
+        .sadd(
+            &keys::file_reqs_sender(session_id, sender_id),
+            &request_id,
+            None,
+        )
+        .sadd(&keys::transfers(session_id), &request_id, None);
+
+    (request_id, request_transaction)
+}
+
+/// Deletes the state of file requests the session never listed.
+async fn discard_file_requests(rcm: State<DynStore>, request_ids: &[String]) {
+    for request_id in request_ids {
+        let mut transaction = Transaction::new();
+        transaction.del(&keys::file_req_users(request_id));
+        for key in get_transfer_keys(request_id) {
+            transaction.del(&key);
+        }
+
+        if let Err(e) = crate::redis_handler::exec_atomic(rcm.clone(), transaction).await {
+            error!("Failed to discard file request: {} ({})", request_id, e);
+        }
+    }
+}
+
+/// Sends the position of every queued file, whenever it changed.
+async fn msg_queue_position(
+    tx: mpsc::Sender<Message>,
+    rcm: State<DynStore>,
+    session_id: &str,
+    user_id: &str,
+    sent_positions: &mut HashMap<String, usize>,
+) -> Result<(), String> {
+    let positions = match get_queue_positions(rcm.clone(), session_id, user_id).await {
+        Ok(positions) => positions,
+        Err(_) => return Ok(()),
+    };
+
+    sent_positions.retain(|filename, _| positions.iter().any(|(file, _)| file == filename));
+
+    for (filename, position) in positions {
+        if sent_positions.get(&filename) == Some(&position) {
+            continue;
+        }
+        sent_positions.insert(filename.clone(), position);
+
+        let message = WsMessage {
+            request_id: "".to_string(),
+            msg: WsMsg::QueuePosition(WsMsgQueuePosition { filename, position }),
+        };
+
+        let message_str = to_json(&message).map_err(|e| e.to_string())?;
+        if tx.send(Message::Text(message_str)).await.is_err() {
+            return Err("Receiver dropped".to_string());
+        }
+    }
+
+    Ok(())
+}
+
+async fn msg_prepare_for_file_request(
+    tx: mpsc::Sender<Message>,
+    rcm: State<DynStore>,
+    session_id: &str,
+    user_id: &str,
+) -> Result<(), String> {
+    let key = keys::file_reqs_receiver(session_id, user_id);
+    let request_ids = crate::redis_handler::smembers(rcm.clone(), &key)
+        .await
+        .unwrap_or(Vec::new());
+
+    for request_id in request_ids {
+        let key = keys::file_req_prepared(&request_id);
+        match crate::redis_handler::sismember(rcm.clone(), &key, user_id).await {
+            Ok(false) => (),
+            _ => continue,
+        };
+
+        let key = keys::file_req_prep(&request_id);
+        let req_data = match crate::redis_handler::hgetall(rcm.clone(), &key).await {
+            Ok(data) => data,
+            Err(_) => {
+                error!(
+                    "Failed to get file request data for request ID: {}",
+                    &request_id
+                );
+                continue;
+            }
+        };
+
+        if req_data.len() < 6 {
+            continue;
+        }
+
+        // the request is prepared once per receiver
+        let key = keys::file_req_prepared(&request_id);
+        match crate::redis_handler::sadd(rcm.clone(), &key, user_id, None).await {
+            Ok(_) => (),
+            Err(_) => {
+                error!(
+                    "Failed to add file.req.prepared:request_id for request ID: {}",
+                    &request_id
+                );
+                continue;
+            }
+        };
+
+        let filename = crate::get_hash_value(&req_data, "filename");
+        if filename.is_none() {
+            continue;
+        }
+        let filename = filename.unwrap();
+
+        let public_key = crate::get_hash_value(&req_data, "public.key");
+        if public_key.is_none() {
+            continue;
+        }
+        let public_key = public_key.unwrap();
+
+        let amount_of_chunks = crate::get_hash_value(&req_data, "amount.of.chunks");
+        if amount_of_chunks.is_none() {
+            continue;
+        }
+        let amount_of_chunks = amount_of_chunks.unwrap();
+
+        let window_size = crate::get_hash_value(&req_data, "window.size")
+            .and_then(|window_size| window_size.parse().ok())
+            .unwrap_or(1);
+
+        let key = keys::file_req_keys(&request_id);
+        let wrapped_keys = crate::redis_handler::hgetall(rcm.clone(), &key)
+            .await
+            .unwrap_or(Vec::new());
+        let wrapped_key = crate::get_hash_value(&wrapped_keys, user_id)
+            .and_then(|wrapped_key| serde_json::from_str::<WrappedKey>(&wrapped_key).ok());
+
+        let content_hash = crate::get_hash_value(&req_data, "content.hash")
+            .and_then(|content_hash| serde_json::from_str::<ContentHash>(&content_hash).ok());
+
+        let message = WsMessage {
+            request_id: request_id.clone(),
+            msg: WsMsg::PrepareForFileTransfer(WsMsgPrepareForFileTransfer {
+                public_key: public_key.clone(),
+                filename: filename.clone(),
+                amount_of_chunks: amount_of_chunks.parse().unwrap_or(0),
+                window_size,
+                wrapped_key,
+                content_hash,
+            }),
+        };
+
+        let message_str = to_json(&message).map_err(|e| e.to_string())?;
+        if tx.send(Message::Text(message_str)).await.is_err() {
+            return Err("Receiver dropped".to_string());
+        }
+    }
+
+    Ok(())
+}
+
+async fn msg_send_next_chunk(
+    tx: mpsc::Sender<Message>,
+    rcm: State<DynStore>,
+    session_id: &str,
+    user_id: &str,
+) -> Result<(), String> {
+    let key = keys::file_reqs_sender(session_id, user_id);
+    let request_ids: Vec<String> = crate::redis_handler::smembers(rcm.clone(), &key)
+        .await
+        .unwrap_or_default();
+
+    for request_id in request_ids {
+        let acked_chunk_nr = match get_acked_chunk_nr(rcm.clone(), &request_id).await {
+            Ok(Some(chunk_nr)) => chunk_nr,
+            _ => continue,
+        };
+
+        let key = keys::chunk_req(&request_id);
+        let requested_chunk_nr: u32 = match crate::redis_handler::get(rcm.clone(), &key).await {
+            Ok(chunk_nr) => chunk_nr.parse().unwrap_or(0),
+            Err(_) => continue,
+        };
+
+        let key = keys::chunk_window(&request_id);
+        let window_size: u32 = match crate::redis_handler::get(rcm.clone(), &key).await {
+            Ok(window_size) => window_size.parse().unwrap_or(1),
+            Err(_) => continue,
+        };
+
+        let mut last_chunk_nr = acked_chunk_nr + window_size;
+
+        let key = keys::chunk_amount(&request_id);
+        if let Ok(amount_of_chunks) = crate::redis_handler::get(rcm.clone(), &key).await {
+            if let Ok(amount_of_chunks @ 1..) = amount_of_chunks.parse::<u32>() {
+                last_chunk_nr = last_chunk_nr.min(amount_of_chunks);
+            }
+        }
+
+        let key = keys::chunk_last(&request_id);
+        if let Ok(chunk_nr) = crate::redis_handler::get(rcm.clone(), &key).await {
+            if let Ok(chunk_nr) = chunk_nr.parse::<u32>() {
+                last_chunk_nr = last_chunk_nr.min(chunk_nr);
+            }
+        }
+
+        if last_chunk_nr <= requested_chunk_nr {
+            continue;
+        }
+
+        let key = keys::chunk_req(&request_id);
+        match crate::redis_handler::set(rcm.clone(), &key, &last_chunk_nr.to_string(), None).await {
+            Ok(_) => (),
+            Err(e) => {
+                error!("Failed to set chunk.req:request.id ({})", e);
+                continue;
+            }
+        };
+
+        for chunk_nr in (requested_chunk_nr + 1)..=last_chunk_nr {
+            let message = WsMessage {
+                request_id: request_id.clone(),
+                msg: WsMsg::SendNextChunk(WsMsgSendNextChunk { chunk_nr }),
+            };
+
+            let message_str = to_json(&message).map_err(|e| e.to_string())?;
+            if tx.send(Message::Text(message_str)).await.is_err() {
+                return Err("Receiver dropped".to_string());
+            }
+        }
+    }
+
+    Ok(())
+}
+
+async fn msg_add_chunk(
+    tx: mpsc::Sender<Message>,
+    rcm: State<DynStore>,
+    session_id: &str,
+    user_id: &str,
+    binary_chunks: bool,
+    chunk_digests: bool,
+) -> Result<(), String> {
+    let key = keys::file_reqs_receiver(session_id, user_id);
+    let request_ids: Vec<String> = crate::redis_handler::smembers(rcm.clone(), &key)
+        .await
+        .unwrap_or_default();
+
+    for request_id in request_ids {
+        let key = keys::chunk_sent(&request_id);
+        let sent_chunk_nrs = crate::redis_handler::hgetall(rcm.clone(), &key)
+            .await
+            .unwrap_or(Vec::new());
+        let mut sent_chunk_nr: u32 = match crate::get_hash_value(&sent_chunk_nrs, user_id) {
+            Some(chunk_nr) => chunk_nr.parse().unwrap_or(0),
+            None => continue,
+        };
+
+        // chunks are forwarded in order, gaps are filled by the sender later on
+        loop {
+            let chunk_nr = sent_chunk_nr + 1;
+
+            let key = keys::chunks(&request_id);
+            let chunk_data =
+                match crate::redis_handler::hget_bytes(rcm.clone(), &key, &chunk_nr.to_string())
+                    .await
+                {
+                    Ok(chunk_data) => chunk_data,
+                    Err(_) => break,
+                };
+
+            if chunk_data.is_empty() {
+                break;
+            }
+
+            let frame = match ChunkFrame::decode(&chunk_data) {
+                Ok(frame) => frame,
+                Err(e) => {
+                    error!("Invalid chunk data for request ID: {} ({})", &request_id, e);
+                    break;
+                }
+            };
+
+            let key = keys::chunk_sent(&request_id);
+            let chunk_nr_str = chunk_nr.to_string();
+            let items = [(user_id, chunk_nr_str.as_str())];
+            match crate::redis_handler::hset_multiple(rcm.clone(), &key, &items, None).await {
+                Ok(_) => (),
+                Err(e) => {
+                    error!("Failed to set chunk.sent:request.id ({})", e);
+                    break;
+                }
+            };
+            sent_chunk_nr = chunk_nr;
+
+            let message = if binary_chunks && (chunk_digests || frame.digest.is_none()) {
+                Message::Binary(chunk_data)
+            } else if binary_chunks {
+                // clients without digests do not expect the digest in the frame
+                let frame = ChunkFrame {
+                    digest: None,
+                    ..frame
+                };
+                match frame.encode() {
+                    Ok(chunk_data) => Message::Binary(chunk_data),
+                    Err(e) => {
+                        error!("Invalid chunk data for request ID: {} ({})", &request_id, e);
+                        break;
+                    }
+                }
+            } else {
+                let message = WsMessage {
+                    request_id: request_id.clone(),
+                    msg: WsMsg::AddChunk(WsMsgAddChunk {
+                        is_last_chunk: frame.is_last_chunk,
+                        chunk_nr,
+                        chunk: frame.chunk_to_hex(),
+                        iv: frame.iv_to_base64(),
+                        digest: frame.digest_to_base64(),
+                    }),
+                };
+
+                Message::Text(to_json(&message).map_err(|e| e.to_string())?)
+            };
+
+            if tx.send(message).await.is_err() {
+                return Err("Receiver dropped".to_string());
+            }
+        }
+    }
+
+    Ok(())
+}
+
+/// Connections without a websocket, driven by the [fuzz targets](../../fuzz/).
+#[cfg(fuzzing)]
+pub mod fuzzing {
+    use axum::extract::{ws::Message, State};
+    use tokio::sync::{mpsc, watch};
+
+    use super::Connection;
+    use crate::error::AppError;
+    use crate::store::DynStore;
+
+    pub struct FuzzConnection {
+        connection: Connection,
+        session_id: String,
+        tx: mpsc::Sender<Message>,
+        rx: mpsc::Receiver<Message>,
+        shutdown_tx: watch::Sender<()>,
+        shutdown_rx: watch::Receiver<()>,
+    }
+
+    impl FuzzConnection {
+        /// Authenticates like opening the websocket of the session.
+        pub fn open(session_id: &str, jwt: &str) -> Result<Self, AppError> {
+            let claims = crate::decode_jwt(jwt)?;
+            if claims.aud != session_id {
+                return Err(AppError::Unauthorized("Invalid session ID.".to_string()));
+            }
+
+            let (tx, rx) = mpsc::channel::<Message>(1024);
+            let (shutdown_tx, shutdown_rx) = watch::channel(());
+
+            Ok(FuzzConnection {
+                connection: Connection::new(&claims),
+                session_id: session_id.to_string(),
+                tx,
+                rx,
+                shutdown_tx,
+                shutdown_rx,
+            })
+        }
+
+        pub fn user_id(&self) -> &str {
+            &self.connection.user_id
+        }
+
+        pub async fn text(&mut self, rcm: DynStore, message: &str) {
+            super::handle_incomming_message(
+                self.tx.clone(),
+                self.shutdown_rx.clone(),
+                State(rcm),
+                &mut self.connection,
+                &self.session_id,
+                message,
+            )
+            .await;
+        }
+
+        pub async fn binary(&mut self, rcm: DynStore, message: &[u8]) {
+            super::handle_incomming_binary_message(
+                self.tx.clone(),
+                State(rcm),
+                &self.connection,
+                message,
+            )
+            .await;
+        }
+
+        /// Messages sent to the client so far.
+        pub fn received(&mut self) -> Vec<Message> {
+            let mut messages = Vec::new();
+            while let Ok(message) = self.rx.try_recv() {
+                messages.push(message);
+            }
+
+            messages
+        }
+
+        /// Stops the listener like a closed websocket, which pauses the transfers.
+        pub fn close(self) {
+            let _ = self.shutdown_tx.send(());
+        }
+    }
+}
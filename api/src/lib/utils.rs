@@ -1,18 +1,28 @@
+pub mod api;
+pub mod chunk_frame;
+pub mod client_ip;
+pub mod error;
+pub mod keys;
+pub mod protocol;
+pub mod proxy_protocol;
+pub mod rate_limit;
 pub mod redis_handler;
+pub mod store;
+pub mod transmittor;
 
-use axum::{
-    extract::State,
-    http::{HeaderMap, StatusCode},
-};
-use axum_client_ip::SecureClientIp;
+use axum::{extract::State, http::HeaderMap, Router};
+use client_ip::IpSource;
+use error::AppError;
 use rand::seq::SliceRandom;
-use redis::aio::ConnectionManager;
+use store::{redis_config::RedisConfig, DynStore};
+use tokio::net::TcpListener;
 
 use log::error;
 
 use serde::{Deserialize, Serialize};
-use serde_json::json;
 
+use std::net::SocketAddr;
+use std::sync::Arc;
 use std::time::{SystemTime, UNIX_EPOCH};
 
 use rand::Rng;
@@ -22,73 +32,46 @@ use uuid::Uuid;
 
 use jsonwebtoken::{self, EncodingKey};
 
-pub async fn get_redis_connection_manager(
-) -> Result<redis::aio::ConnectionManager, redis::RedisError> {
-    let database_password = std::env::var("DATABASE_PASSWORD").unwrap_or_default();
+/// Connects to Redis as configured by the environment, see [`RedisConfig::from_env`].
+pub async fn get_redis_store() -> Result<DynStore, redis::RedisError> {
+    let config = RedisConfig::from_env()?;
 
-    let redis_conn_url = format!("redis://:{}@database:6379/", database_password);
-    let client = redis::Client::open(redis_conn_url)?;
-
-    let config = redis::aio::ConnectionManagerConfig::new();
-
-    let redis_connection_manager = match ConnectionManager::new_with_config(client, config).await {
-        Ok(m) => m,
+    let store = match config.connect().await {
+        Ok(s) => s,
         Err(e) => {
             error!("Error connecting to Redis: {}", e);
             return Err(e);
         }
     };
 
-    Ok(redis_connection_manager)
+    Ok(Arc::new(store))
 }
 
-const CALL_RATE_LIMIT_SEC: i64 = 1;
-
-pub async fn handle_call_rate_limit(
-    rcm: State<ConnectionManager>,
-    ref ip: &SecureClientIp,
-) -> Result<(), (StatusCode, String)> {
-    let ip = ip.0.to_string();
-
-    if redis_handler::sismember(rcm.clone(), "calls", &ip).await? {
-        return Err((
-            StatusCode::TOO_MANY_REQUESTS,
-            json!({
-                "success": false,
-                "message": "rate limit exceeded"
-            })
-            .to_string(),
-        ));
+/// Serves the router, reading the PROXY header of each connection if that is the IP source.
+pub async fn serve(listener: TcpListener, router: Router) -> std::io::Result<()> {
+    match client_ip::config().source {
+        IpSource::ProxyProtocol => proxy_protocol::serve(listener, router).await,
+        _ => {
+            axum::serve(
+                listener,
+                router.into_make_service_with_connect_info::<SocketAddr>(),
+            )
+            .await
+        }
     }
-
-    redis_handler::sadd(rcm.clone(), "calls", &ip, Some(CALL_RATE_LIMIT_SEC)).await?;
-
-    Ok(())
 }
 
-pub async fn check_session_exists(
-    rcm: State<ConnectionManager>,
-    ref session_id: &str,
-) -> Result<(), (StatusCode, String)> {
-    let key = format!("session:{}", session_id);
+pub async fn check_session_exists(rcm: State<DynStore>, session_id: &str) -> Result<(), AppError> {
+    let key = keys::session(session_id);
 
     if !redis_handler::exists(rcm.clone(), &key).await? {
-        return Err((
-            StatusCode::NOT_FOUND,
-            json!({
-                "success": false,
-                "message": "session id not found"
-            })
-            .to_string(),
-        ));
+        return Err(AppError::NotFound("session id not found".to_string()));
     }
 
     Ok(())
 }
 
-pub async fn get_random_dragon_name(
-    rcm: State<ConnectionManager>,
-) -> Result<String, (StatusCode, String)> {
+pub async fn get_random_dragon_name(rcm: State<DynStore>) -> Result<String, AppError> {
     let dragon_names = vec![
         "Smaug",
         "Drogon",
@@ -118,7 +101,7 @@ pub async fn get_random_dragon_name(
         .choose(&mut rand::thread_rng())
         .unwrap_or(&dragon_names[0])
         .to_string();
-    let key = format!("session:{}", dragon_name);
+    let key = keys::session_name(&dragon_name);
 
     if !redis_handler::exists(rcm.clone(), &key).await? {
         return Ok(dragon_name);
@@ -126,7 +109,7 @@ pub async fn get_random_dragon_name(
 
     // any name from list
     for name in dragon_names {
-        let key = format!("session:{}", name);
+        let key = keys::session_name(name);
         if !redis_handler::exists(rcm.clone(), &key).await? {
             return Ok(name.to_string());
         }
@@ -135,7 +118,7 @@ pub async fn get_random_dragon_name(
     // first random name with counter
     let mut counter = 1;
     loop {
-        let nr_key = format!("session:{}{}", &dragon_name, counter);
+        let nr_key = keys::session_name(&format!("{}{}", &dragon_name, counter));
 
         if !redis_handler::exists(rcm.clone(), &nr_key).await? {
             return Ok(format!("{}{}", &dragon_name, counter));
@@ -154,69 +137,60 @@ pub struct Claims {
     pub is_host: bool,
 }
 
-pub fn create_jwt(
-    ref session_id: &str,
-    ref user_id: Option<&str>,
-) -> Result<String, (StatusCode, String)> {
-    let jwt_key = std::env::var("JWT_KEY").unwrap_or_default();
-
-    let key = EncodingKey::from_secret(jwt_key.as_ref());
-    let header = jsonwebtoken::Header::new(jsonwebtoken::Algorithm::HS256);
+pub fn create_jwt(session_id: &str, user_id: Option<&str>) -> Result<String, AppError> {
     let claims = Claims {
         aud: session_id.to_string(),
         sub: user_id.unwrap_or(&get_uuid()).to_string(),
         iat: get_current_timestamp(),
         exp: get_current_timestamp() + JWT_EXPIRATION_TIME,
-        is_host: !user_id.is_none(),
+        is_host: user_id.is_some(),
     };
 
-    match jsonwebtoken::encode(&header, &claims, &key) {
+    encode_jwt(&claims)
+}
+
+/// Issues a new JWT for a user of the session, e.g. before the current one expires.
+pub fn renew_jwt(session_id: &str, user: &User) -> Result<String, AppError> {
+    let claims = Claims {
+        aud: session_id.to_string(),
+        sub: user.id.clone(),
+        iat: get_current_timestamp(),
+        exp: get_current_timestamp() + JWT_EXPIRATION_TIME,
+        is_host: user.is_host,
+    };
+
+    encode_jwt(&claims)
+}
+
+fn encode_jwt(claims: &Claims) -> Result<String, AppError> {
+    let jwt_key = std::env::var("JWT_KEY").unwrap_or_default();
+
+    let key = EncodingKey::from_secret(jwt_key.as_ref());
+    let header = jsonwebtoken::Header::new(jsonwebtoken::Algorithm::HS256);
+
+    match jsonwebtoken::encode(&header, claims, &key) {
         Ok(token) => Ok(token),
-        Err(_) => {
-            return Err((
-                StatusCode::INTERNAL_SERVER_ERROR,
-                json!({
-                    "success": false,
-                    "response": "failed to create jwt"
-                })
-                .to_string(),
-            ))
-        }
+        Err(e) => Err(AppError::Internal(format!("failed to create jwt: {}", e))),
     }
 }
 
 const JWT_EXPIRATION_TIME: u128 = 60 * 60 * 1000; // 1 hour
 
-pub fn decode_jwt(ref jwt: &str) -> Result<Claims, (StatusCode, String)> {
-    let jwt_key = std::env::var("JWT_KEY").map_err(|_| {
-        (
-            StatusCode::INTERNAL_SERVER_ERROR,
-            json!({
-                "success": false,
-                "response": "failed to locate jwt key"
-            })
-            .to_string(),
-        )
-    })?;
+pub fn decode_jwt(jwt: &str) -> Result<Claims, AppError> {
+    let jwt_key = std::env::var("JWT_KEY")
+        .map_err(|_| AppError::Internal("failed to locate jwt key".to_string()))?;
 
     let mut validation = jsonwebtoken::Validation::new(jsonwebtoken::Algorithm::HS256);
     validation.validate_aud = false;
 
     let key = jsonwebtoken::DecodingKey::from_secret(jwt_key.as_ref());
 
-    let decoded_jwt = match jsonwebtoken::decode::<Claims>(&jwt, &key, &validation) {
+    let decoded_jwt = match jsonwebtoken::decode::<Claims>(jwt, &key, &validation) {
         Ok(v) => v,
         Err(e) => {
             error!("decode_jwt: {:?}", e);
 
-            return Err((
-                StatusCode::UNAUTHORIZED,
-                json!({
-                    "success": false,
-                    "response": "failed to decode jwt"
-                })
-                .to_string(),
-            ));
+            return Err(AppError::Unauthorized("failed to decode jwt".to_string()));
         }
     };
 
@@ -224,67 +198,40 @@ pub fn decode_jwt(ref jwt: &str) -> Result<Claims, (StatusCode, String)> {
     let now = get_current_timestamp();
 
     if (now - claims.iat) > JWT_EXPIRATION_TIME {
-        return Err((
-            StatusCode::UNAUTHORIZED,
-            json!({
-                "success": false,
-                "response": "jwt expired"
-            })
-            .to_string(),
-        ));
+        return Err(AppError::Unauthorized("jwt expired".to_string()));
     }
 
     Ok(claims)
 }
 
-pub fn decode_jwt_from_header(ref headers: &HeaderMap) -> Result<Claims, (StatusCode, String)> {
-    let auth = get_header(&headers, "authorization")?;
+pub fn decode_jwt_from_header(headers: &HeaderMap) -> Result<Claims, AppError> {
+    let auth = get_header(headers, "authorization")?;
     let parts = auth.split(" ");
     let jwt = parts.last().unwrap_or("");
-    let claims = decode_jwt(&jwt)?;
+    let claims = decode_jwt(jwt)?;
 
     Ok(claims)
 }
 
-pub fn get_hash_value(hash_vec: &Vec<String>, key: &str) -> Option<String> {
-    let key_idx = hash_vec.iter().position(|x| x == key);
-    if key_idx.is_none() {
-        return None;
-    }
+pub fn get_hash_value(hash_vec: &[String], key: &str) -> Option<String> {
+    let key_idx = hash_vec.iter().position(|x| x == key)?;
 
-    hash_vec.get(key_idx.unwrap() + 1).map(|x| x.to_string())
+    hash_vec.get(key_idx + 1).map(|x| x.to_string())
 }
 
-pub fn check_user_is_host(
-    ref headers: &HeaderMap,
-    session_id: &str,
-) -> Result<(), (StatusCode, String)> {
-    let auth = get_header(&headers, "authorization")?;
+pub fn check_user_is_host(headers: &HeaderMap, session_id: &str) -> Result<(), AppError> {
+    let auth = get_header(headers, "authorization")?;
     let parts = auth.split(" ");
     let jwt = parts.last().unwrap_or("");
 
-    let claims = decode_jwt(&jwt)?;
+    let claims = decode_jwt(jwt)?;
 
     if claims.aud != session_id {
-        return Err((
-            StatusCode::UNAUTHORIZED,
-            json!({
-                "success": false,
-                "message": "invalid session id"
-            })
-            .to_string(),
-        ));
+        return Err(AppError::Unauthorized("invalid session id".to_string()));
     }
 
     if !claims.is_host {
-        return Err((
-            StatusCode::UNAUTHORIZED,
-            json!({
-                "success": false,
-                "message": "permission denied"
-            })
-            .to_string(),
-        ));
+        return Err(AppError::Unauthorized("permission denied".to_string()));
     }
 
     Ok(())
@@ -302,24 +249,14 @@ pub struct User {
     pub is_host: bool,
 }
 
-pub fn check_user_is_in_session(
-    ref headers: &HeaderMap,
-    session_id: &str,
-) -> Result<User, (StatusCode, String)> {
-    let auth = get_header(&headers, "authorization")?;
+pub fn check_user_is_in_session(headers: &HeaderMap, session_id: &str) -> Result<User, AppError> {
+    let auth = get_header(headers, "authorization")?;
     let parts = auth.split(" ");
     let jwt = parts.last().unwrap_or("");
-    let claims = decode_jwt(&jwt)?;
+    let claims = decode_jwt(jwt)?;
 
     if claims.aud != session_id {
-        return Err((
-            StatusCode::UNAUTHORIZED,
-            json!({
-                "success": false,
-                "message": "invalid session id"
-            })
-            .to_string(),
-        ));
+        return Err(AppError::Unauthorized("invalid session id".to_string()));
     }
 
     Ok(User {
@@ -328,21 +265,16 @@ pub fn check_user_is_in_session(
     })
 }
 
-pub fn get_header(ref headers: &HeaderMap, key: &str) -> Result<String, (StatusCode, String)> {
-    let header = headers.get("Authorization");
+pub fn get_header(headers: &HeaderMap, key: &str) -> Result<String, AppError> {
+    let header = headers.get(key).and_then(|header| header.to_str().ok());
     if header.is_none() {
-        return Err((
-            StatusCode::BAD_REQUEST,
-            json!({
-                "success": false,
-                "message": format!("{} header not found", key)
-            })
-            .to_string(),
+        return Err(AppError::invalid_field(
+            key,
+            format!("{} header not found", key),
         ));
     }
 
-    let header = header.unwrap().to_str().unwrap();
-    Ok(header.to_string())
+    Ok(header.unwrap().to_string())
 }
 
 pub fn get_random_six_digit_code() -> String {
@@ -360,132 +292,103 @@ pub fn get_uuid() -> String {
     Uuid::new_v4().to_string()
 }
 
-pub fn deserialize_data<T: serde::de::DeserializeOwned>(
-    data: &String,
-) -> Result<T, (StatusCode, String)> {
-    match serde_json::from_str::<T>(&data) {
-        Ok(data) => Ok(data),
-        Err(_) => Err((StatusCode::BAD_REQUEST, "Wrong data format.".to_string())),
-    }
-}
-
 pub async fn check_user_is_in_file_request(
-    rcm: State<ConnectionManager>,
-    request_id: &String,
-    user_id: &String,
-) -> Result<(), (StatusCode, String)> {
-    let key = format!("file.req.users:{}", &request_id);
-    redis_handler::sismember(rcm, &key, &user_id)
+    rcm: State<DynStore>,
+    request_id: &str,
+    user_id: &str,
+) -> Result<(), AppError> {
+    let key = keys::file_req_users(request_id);
+    let is_member = redis_handler::sismember(rcm, &key, user_id)
         .await
-        .map_err(|_| {
-            (
-                StatusCode::UNAUTHORIZED,
-                "User not in file request.".to_string(),
-            )
-        })?;
+        .unwrap_or(false);
+
+    if !is_member {
+        return Err(AppError::Unauthorized(
+            "User not in file request.".to_string(),
+        ));
+    }
 
     Ok(())
 }
 
 pub async fn get_user_files(
-    rcm: State<ConnectionManager>,
-    session_id: &String,
+    rcm: State<DynStore>,
+    session_id: &str,
     user_id: &String,
 ) -> Result<Vec<String>, String> {
-    let key = format!("files:{}", &session_id);
+    let key = keys::files(session_id);
     let files = match redis_handler::smembers(rcm.clone(), &key).await {
-        Ok(files) => Some(files),
-        Err(_) => None,
+        Ok(files) => files,
+        Err(_) => return Ok(Vec::new()),
     };
 
-    if files.is_none() {
-        return Ok(Vec::new());
-    }
-
-    let files = files.unwrap();
-
-    let mut user_files: Vec<String> = Vec::new();
-    for file in files {
-        let key = format!("files:{}:{}", &session_id, &file);
-        let owner_id = match redis_handler::hget(rcm.clone(), &key, "owner.id").await {
-            Ok(owner_id) => Some(owner_id),
-            Err(_) => None,
-        };
-
-        if owner_id.is_none() {
-            continue;
-        }
-        let owner_id = owner_id.unwrap();
+    let keys = files
+        .iter()
+        .map(|file| keys::file(session_id, file))
+        .collect::<Vec<String>>();
+    let owner_ids = match redis_handler::hget_many(rcm.clone(), &keys, "owner.id").await {
+        Ok(owner_ids) => owner_ids,
+        Err(_) => return Ok(Vec::new()),
+    };
 
-        if &owner_id == user_id {
-            user_files.push(file);
-        }
-    }
+    let user_files = files
+        .into_iter()
+        .zip(owner_ids)
+        .filter(|(_, owner_id)| owner_id.as_ref() == Some(user_id))
+        .map(|(file, _)| file)
+        .collect();
 
     Ok(user_files)
 }
 
-pub async fn is_request_ready(rcm: State<ConnectionManager>, request_id: &String) -> bool {
-    let key = format!("file.req.ready:{}", &request_id);
-    let is_ready = match redis_handler::exists(rcm.clone(), &key).await {
-        Ok(exists) => exists,
-        Err(_) => {
-            error!("Failed to check if file request is ready: {}", &request_id);
-            false
-        }
-    };
-
-    is_ready
-}
-
-pub async fn prolong_session(rcm: State<ConnectionManager>, session_id: &String) {
-    let key = format!("session:{}", &session_id);
+pub async fn prolong_session(rcm: State<DynStore>, session_id: &String) {
+    let key = keys::session(session_id);
     let session_name = match redis_handler::hget(rcm.clone(), &key, "name").await {
-        Ok(session_name) => Some(session_name),
-        Err(_) => None,
+        Ok(session_name) => session_name,
+        Err(_) => return,
     };
 
-    if session_name.is_none() {
-        return;
-    }
-
-    let session_name = session_name.unwrap();
+    let files_key = keys::files(session_id);
+    let files: Vec<String> = redis_handler::smembers(rcm.clone(), &files_key)
+        .await
+        .unwrap_or_default();
 
-    match redis_handler::expire(rcm.clone(), &key, None).await {
-        Ok(_) => (),
-        Err(_) => {
-            error!("Failed to prolong session: {}", &key);
-        }
+    let mut keys = vec![key, keys::session_name(&session_name), files_key];
+    for file in files {
+        keys.push(keys::file(session_id, &file));
     }
 
-    let key = format!("session:{}", &session_name);
-    match redis_handler::expire(rcm.clone(), &key, None).await {
+    match redis_handler::expire_many(rcm.clone(), &keys, None).await {
         Ok(_) => (),
-        Err(_) => {
-            error!("Failed to prolong session: {}", &session_name);
+        Err(e) => {
+            error!("Failed to prolong session: {} ({})", &session_id, e);
         }
     }
+}
 
-    let key = format!("files:{}", &session_id);
-    let files = match redis_handler::smembers(rcm.clone(), &key).await {
-        Ok(files) => files,
-        Err(_) => Vec::new(),
-    };
+/// Wakes the listener of a user, so it picks up state changes.
+pub async fn publish_user_event(rcm: State<DynStore>, user_id: &String) {
+    publish_user_message(rcm, user_id, "").await;
+}
 
-    match redis_handler::expire(rcm.clone(), &key, None).await {
+/// Forwards a websocket message to a user, regardless of the transmittor instance it is connected to.
+pub async fn publish_user_message(rcm: State<DynStore>, user_id: &String, message: &str) {
+    let channel = keys::events(user_id);
+    match redis_handler::publish(rcm, &channel, message).await {
         Ok(_) => (),
-        Err(_) => {
-            error!("Failed to prolong session files: {}", &session_id);
+        Err(e) => {
+            error!("Failed to publish event for user: {} ({})", &user_id, e);
         }
     }
+}
 
-    for file in files {
-        let key = format!("files:{}:{}", &session_id, &file);
-        match redis_handler::expire(rcm.clone(), &key, None).await {
-            Ok(_) => (),
-            Err(_) => {
-                error!("Failed to prolong file: {}", &key);
-            }
-        }
+pub async fn publish_request_event(rcm: State<DynStore>, request_id: &str) {
+    let key = keys::file_req_users(request_id);
+    let user_ids: Vec<String> = redis_handler::smembers(rcm.clone(), &key)
+        .await
+        .unwrap_or_default();
+
+    for user_id in user_ids {
+        publish_user_event(rcm.clone(), &user_id).await;
     }
-}
\ No newline at end of file
+}
@@ -0,0 +1,188 @@
+mod common;
+
+use std::time::Duration;
+
+use serde_json::json;
+
+use common::{Client, Harness, Session};
+
+const FILENAME: &str = "notes.txt";
+const AMOUNT_OF_CHUNKS: u32 = 2;
+
+/// Read once by the transmittor, every test of the binary sets the same values.
+fn set_env() {
+    std::env::set_var("BROADCAST_JOIN_WINDOW", "1");
+    std::env::set_var("TRANSFER_GRACE_PERIOD", "1");
+}
+
+/// A guest that joined with the access code and requested the file.
+async fn join_and_request(harness: &Harness, session: &Session, capabilities: &[&str]) -> Client {
+    let response = harness.join(&session.name, &session.code).await;
+    assert_eq!(response.status, 200, "{}", response.body);
+    let jwt = response.body["response"]["jwt"].as_str().unwrap();
+
+    let mut guest = harness.connect_with(&session.id, jwt, capabilities).await;
+    guest
+        .send(
+            "request-file",
+            json!({ "public_key": "guest-key", "filename": FILENAME }),
+        )
+        .await;
+    guest.expect_ok("request-file").await;
+
+    guest
+}
+
+/// Sends a chunk and lets the receivers forward it.
+async fn add_chunk(
+    host: &mut Client,
+    receivers: &mut [&mut Client],
+    request_id: &str,
+    chunk_nr: u32,
+) {
+    let message = host.recv("send-next-chunk").await;
+    assert_eq!(message["data"]["chunk_nr"], chunk_nr);
+
+    host.send(
+        "add-chunk",
+        json!({
+            "request_id": request_id,
+            "chunk_nr": chunk_nr,
+            "is_last_chunk": chunk_nr == AMOUNT_OF_CHUNKS,
+            "chunk": format!("{:02x}", chunk_nr).repeat(1000),
+            "iv": "AAAAAAAAAAAAAAAA",
+        }),
+    )
+    .await;
+    host.expect_ok("add-chunk").await;
+
+    for receiver in receivers.iter_mut() {
+        let message = receiver.recv("add-chunk").await;
+        assert_eq!(message["data"]["chunk_nr"], chunk_nr);
+    }
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn only_receivers_supporting_broadcasts_are_grouped() {
+    set_env();
+
+    let harness = Harness::start().await;
+    let session = harness.create_session().await;
+    harness
+        .add_files(&session, json!([{ "name": FILENAME, "size": 6000 }]))
+        .await;
+
+    let mut host = harness
+        .connect_with(&session.id, &session.jwt, &["broadcast"])
+        .await;
+
+    let mut guests = Vec::new();
+    for capabilities in [&["broadcast"][..], &[]] {
+        guests.push(join_and_request(&harness, &session, capabilities).await);
+    }
+
+    let mut broadcasts = 0;
+    let mut requests = 0;
+    for _ in 0..2 {
+        let message = host.recv("acknowledge-file-request").await;
+        match message["data"]["receivers"].as_array() {
+            Some(receivers) => {
+                assert_eq!(receivers.len(), 1, "{}", message);
+                broadcasts += 1;
+            }
+            None => requests += 1,
+        }
+    }
+    assert_eq!((broadcasts, requests), (1, 1));
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn paused_receiver_does_not_hold_back_the_broadcast() {
+    set_env();
+
+    let harness = Harness::start().await;
+    let session = harness.create_session().await;
+    harness
+        .add_files(&session, json!([{ "name": FILENAME, "size": 2000 }]))
+        .await;
+
+    let mut host = harness
+        .connect_with(&session.id, &session.jwt, &["broadcast"])
+        .await;
+    let keys_of_session = harness.keys();
+
+    let mut staying = join_and_request(&harness, &session, &["broadcast"]).await;
+    let mut leaving = join_and_request(&harness, &session, &["broadcast"]).await;
+
+    let message = host.recv("acknowledge-file-request").await;
+    let request_id = message["request_id"].as_str().unwrap().to_string();
+    let receivers = message["data"]["receivers"].as_array().unwrap();
+    assert_eq!(receivers.len(), 2, "{}", message);
+    let wrapped_keys = receivers
+        .iter()
+        .map(|receiver| {
+            let user_id = receiver["user_id"].as_str().unwrap().to_string();
+            (user_id, json!({ "key": "wrapped-key", "iv": "wrapped-iv" }))
+        })
+        .collect::<serde_json::Map<String, serde_json::Value>>();
+
+    host.send(
+        "acknowledge-file-request",
+        json!({
+            "request_id": request_id,
+            "public_key": "host-key",
+            "amount_of_chunks": AMOUNT_OF_CHUNKS,
+            "filename": FILENAME,
+            "wrapped_keys": wrapped_keys,
+        }),
+    )
+    .await;
+    host.expect_ok("acknowledge-file-request").await;
+
+    for guest in [&mut staying, &mut leaving] {
+        guest.recv("prepare-for-file-transfer").await;
+        guest
+            .send(
+                "ready-for-file-transfer",
+                json!({ "request_id": request_id }),
+            )
+            .await;
+    }
+
+    add_chunk(&mut host, &mut [&mut staying, &mut leaving], &request_id, 1).await;
+
+    // one receiver disconnects without acknowledging the chunk, the other one carries on
+    drop(leaving);
+    let message = host.recv("transfer-paused").await;
+    assert_eq!(message["request_id"], request_id.as_str());
+    assert!(
+        !staying
+            .receives("transfer-paused", Duration::from_millis(200))
+            .await
+    );
+
+    staying
+        .send(
+            "received-chunk",
+            json!({ "request_id": request_id, "chunk_nr": 1 }),
+        )
+        .await;
+    staying.expect_ok("received-chunk").await;
+
+    add_chunk(&mut host, &mut [&mut staying], &request_id, 2).await;
+    staying
+        .send(
+            "received-chunk",
+            json!({ "request_id": request_id, "chunk_nr": 2 }),
+        )
+        .await;
+    staying.expect_ok("received-chunk").await;
+
+    // the receiver that left is detached after the grace period, which ends the file request
+    let mut attempts = 0;
+    while harness.keys() != keys_of_session {
+        attempts += 1;
+        assert!(attempts < 250, "keys left: {:?}", harness.keys());
+        tokio::time::sleep(Duration::from_millis(20)).await;
+    }
+}
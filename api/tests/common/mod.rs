@@ -0,0 +1,309 @@
+//! Runs the api and the transmittor in-process on ephemeral ports, sharing an in-memory store,
+//! and drives them like the web app does: plain HTTP requests and JSON over the websocket.
+
+// every test binary only uses a part of the harness
+#![allow(dead_code)]
+
+use std::collections::VecDeque;
+use std::net::SocketAddr;
+use std::sync::{Arc, Once};
+use std::time::Duration;
+
+use futures_util::{SinkExt, StreamExt};
+use serde_json::{json, Value};
+use tokio::io::{AsyncReadExt, AsyncWriteExt};
+use tokio::net::{TcpListener, TcpStream};
+use tokio_tungstenite::{
+    tungstenite::{client::IntoClientRequest, http::HeaderValue, Message},
+    MaybeTlsStream, WebSocketStream,
+};
+
+use utils::store::{memory_store::MemoryStore, DynStore};
+
+/// Time a client waits for a message before the test fails.
+const TIMEOUT: Duration = Duration::from_secs(5);
+
+static INIT: Once = Once::new();
+
+pub struct Harness {
+    pub store: Arc<MemoryStore>,
+    api: SocketAddr,
+    transmittor: SocketAddr,
+}
+
+pub struct Response {
+    pub status: u16,
+    pub body: Value,
+}
+
+/// Session as created by its host.
+pub struct Session {
+    pub id: String,
+    pub name: String,
+    pub code: String,
+    pub jwt: String,
+}
+
+impl Harness {
+    pub async fn start() -> Self {
+        INIT.call_once(|| std::env::set_var("JWT_KEY", "integration-tests"));
+
+        let store = MemoryStore::new();
+        let dyn_store: DynStore = store.clone();
+
+        tokio::spawn(utils::transmittor::dispatch_events(dyn_store.clone()));
+
+        let api_listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let api = api_listener.local_addr().unwrap();
+        tokio::spawn(utils::serve(
+            api_listener,
+            utils::api::router(dyn_store.clone()),
+        ));
+
+        let transmittor_listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let transmittor = transmittor_listener.local_addr().unwrap();
+        tokio::spawn(utils::serve(
+            transmittor_listener,
+            utils::transmittor::router(dyn_store),
+        ));
+
+        Harness {
+            store,
+            api,
+            transmittor,
+        }
+    }
+
+    pub async fn request(
+        &self,
+        method: &str,
+        path: &str,
+        authorization: Option<&str>,
+        body: Option<Value>,
+    ) -> Response {
+        http(self.api, method, path, authorization, body).await
+    }
+
+    pub async fn create_session(&self) -> Session {
+        let response = self.request("POST", "/session", None, None).await;
+        assert_eq!(response.status, 201, "{}", response.body);
+
+        let session = &response.body["response"];
+        Session {
+            id: session["sessionId"].as_str().unwrap().to_string(),
+            name: session["sessionName"].as_str().unwrap().to_string(),
+            code: session["accessCode"].as_str().unwrap().to_string(),
+            jwt: session["jwt"].as_str().unwrap().to_string(),
+        }
+    }
+
+    pub async fn add_files(&self, session: &Session, files: Value) {
+        let response = self
+            .request(
+                "POST",
+                &format!("/files/{}", session.id),
+                Some(&bearer(&session.jwt)),
+                Some(files),
+            )
+            .await;
+        assert_eq!(response.status, 200, "{}", response.body);
+    }
+
+    /// Joins the session like a guest, by its name and access code.
+    pub async fn join(&self, session_name: &str, code: &str) -> Response {
+        let response = self
+            .request("GET", &format!("/idForName/{}", session_name), None, None)
+            .await;
+        assert_eq!(response.status, 200, "{}", response.body);
+        let session_id = response.body["response"]["sessionId"].as_str().unwrap();
+
+        self.request(
+            "GET",
+            &format!("/access/{}", session_id),
+            Some(&sha256::digest(code)),
+            None,
+        )
+        .await
+    }
+
+    /// Opens the websocket of the session and registers.
+    pub async fn connect(&self, session_id: &str, jwt: &str) -> Client {
+        self.connect_with(session_id, jwt, &[]).await
+    }
+
+    /// Opens the websocket of the session and registers with the capabilities.
+    /// Authenticates with the `bearer.<jwt>` subprotocol like the web app.
+    pub async fn connect_with(&self, session_id: &str, jwt: &str, capabilities: &[&str]) -> Client {
+        let url = format!("ws://{}/session/{}", self.transmittor, session_id);
+        let mut request = url.into_client_request().unwrap();
+        request.headers_mut().insert(
+            "sec-websocket-protocol",
+            HeaderValue::from_str(&format!("drag-n-share, bearer.{}", jwt)).unwrap(),
+        );
+        let (ws, _) = tokio_tungstenite::connect_async(request).await.unwrap();
+
+        let mut client = Client {
+            ws,
+            jwt: jwt.to_string(),
+            buffer: VecDeque::new(),
+        };
+        client
+            .send(
+                "register",
+                json!({ "version": 1, "capabilities": capabilities }),
+            )
+            .await;
+        client.expect_ok("register").await;
+
+        client
+    }
+
+    /// Keys of the store, without the ones that expire on their own
+    /// (rate limits, access attempts and the session created per IP).
+    pub fn keys(&self) -> Vec<String> {
+        let mut keys = self
+            .store
+            .keys()
+            .into_iter()
+            .filter(|key| {
+                !key.starts_with("rate.limit:")
+                    && !key.starts_with("access.attempts:")
+                    && !key.starts_with("created.sessions:")
+            })
+            .collect::<Vec<String>>();
+        keys.sort();
+
+        keys
+    }
+}
+
+pub fn bearer(jwt: &str) -> String {
+    format!("Bearer {}", jwt)
+}
+
+/// Client of the websocket, messages that are not waited for yet are kept in order.
+pub struct Client {
+    ws: WebSocketStream<MaybeTlsStream<TcpStream>>,
+    jwt: String,
+    buffer: VecDeque<Value>,
+}
+
+impl Client {
+    pub async fn send(&mut self, command: &str, data: Value) {
+        let request = json!({
+            "jwt": self.jwt,
+            "command": command,
+            "data": data,
+        });
+
+        self.ws
+            .send(Message::Text(request.to_string()))
+            .await
+            .unwrap();
+    }
+
+    /// Next message with the command.
+    pub async fn recv(&mut self, command: &str) -> Value {
+        self.next(|message| message["command"] == command)
+            .await
+            .unwrap_or_else(|| panic!("no {} received", command))
+    }
+
+    /// The reply to a command, which has to succeed.
+    pub async fn expect_ok(&mut self, command: &str) {
+        let reply = self.reply(command).await;
+        assert_eq!(reply["command"], "ok", "{}", reply);
+    }
+
+    /// The reply to a command, which has to fail.
+    pub async fn expect_error(&mut self, command: &str) -> Value {
+        let reply = self.reply(command).await;
+        assert_eq!(reply["command"], "error", "{}", reply);
+
+        reply["data"].clone()
+    }
+
+    /// Whether a message with the command arrives before the timeout.
+    pub async fn receives(&mut self, command: &str, timeout: Duration) -> bool {
+        tokio::time::timeout(timeout, self.next(|message| message["command"] == command))
+            .await
+            .is_ok_and(|message| message.is_some())
+    }
+
+    async fn reply(&mut self, command: &str) -> Value {
+        self.next(|message| {
+            (message["command"] == "ok" || message["command"] == "error")
+                && message["data"]["command"] == command
+        })
+        .await
+        .unwrap_or_else(|| panic!("no reply to {} received", command))
+    }
+
+    /// First message matching, from the buffer or the websocket. `None` after the timeout.
+    async fn next(&mut self, matches: impl Fn(&Value) -> bool) -> Option<Value> {
+        if let Some(i) = self.buffer.iter().position(&matches) {
+            return self.buffer.remove(i);
+        }
+
+        loop {
+            let message = tokio::time::timeout(TIMEOUT, self.ws.next())
+                .await
+                .ok()?
+                .expect("websocket closed")
+                .unwrap();
+
+            let text = match message {
+                Message::Text(text) => text,
+                _ => continue,
+            };
+            let message: Value = serde_json::from_str(&text).unwrap();
+
+            if matches(&message) {
+                return Some(message);
+            }
+            self.buffer.push_back(message);
+        }
+    }
+}
+
+/// Sends a single HTTP/1.1 request, the connection is closed afterwards.
+async fn http(
+    addr: SocketAddr,
+    method: &str,
+    path: &str,
+    authorization: Option<&str>,
+    body: Option<Value>,
+) -> Response {
+    let body = body.map(|body| body.to_string()).unwrap_or_default();
+
+    let mut request = format!(
+        "{} {} HTTP/1.1\r\nHost: {}\r\nConnection: close\r\nContent-Type: application/json\r\nContent-Length: {}\r\n",
+        method,
+        path,
+        addr,
+        body.len()
+    );
+    if let Some(authorization) = authorization {
+        request.push_str(&format!("Authorization: {}\r\n", authorization));
+    }
+    request.push_str("\r\n");
+    request.push_str(&body);
+
+    let mut stream = TcpStream::connect(addr).await.unwrap();
+    stream.write_all(request.as_bytes()).await.unwrap();
+
+    let mut response = Vec::new();
+    stream.read_to_end(&mut response).await.unwrap();
+    let response = String::from_utf8_lossy(&response);
+
+    let status = response[9..12].parse().unwrap();
+    let body = response
+        .split_once("\r\n\r\n")
+        .map(|(_, body)| body)
+        .unwrap_or("");
+
+    Response {
+        status,
+        body: serde_json::from_str(body).unwrap_or(Value::Null),
+    }
+}
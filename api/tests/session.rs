@@ -0,0 +1,56 @@
+mod common;
+
+use common::Harness;
+
+/// Wrong codes accepted per session and IP, see `join_session`.
+const MAX_ATTEMPTS: usize = 5;
+
+#[tokio::test(flavor = "multi_thread")]
+async fn joining_with_the_code_returns_a_jwt() {
+    let harness = Harness::start().await;
+    let session = harness.create_session().await;
+
+    let response = harness.join(&session.name, &session.code).await;
+    assert_eq!(response.status, 200, "{}", response.body);
+    assert!(response.body["response"]["jwt"].is_string());
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn wrong_code_is_rejected() {
+    let harness = Harness::start().await;
+    let session = harness.create_session().await;
+
+    let response = harness
+        .join(&session.name, &wrong_code(&session.code))
+        .await;
+    assert_eq!(response.status, 401, "{}", response.body);
+    assert_eq!(response.body["code"], "unauthorized");
+    assert!(response.body["response"].is_null());
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn too_many_attempts_are_rejected() {
+    let harness = Harness::start().await;
+    let session = harness.create_session().await;
+
+    for _ in 0..MAX_ATTEMPTS {
+        let response = harness
+            .join(&session.name, &wrong_code(&session.code))
+            .await;
+        assert_eq!(response.status, 401, "{}", response.body);
+    }
+
+    // even the right code, until the attempts expired
+    let response = harness.join(&session.name, &session.code).await;
+    assert_eq!(response.status, 429, "{}", response.body);
+    assert_eq!(response.body["code"], "too_many_requests");
+    assert_eq!(response.body["message"], "too many attempts");
+}
+
+fn wrong_code(code: &str) -> String {
+    if code == "000000" {
+        "000001".to_string()
+    } else {
+        "000000".to_string()
+    }
+}
@@ -0,0 +1,275 @@
+mod common;
+
+use std::time::Duration;
+
+use serde_json::{json, Value};
+
+use common::{bearer, Client, Harness, Session};
+
+const FILENAME: &str = "notes.txt";
+const AMOUNT_OF_CHUNKS: u32 = 3;
+const IV: &str = "AAAAAAAAAAAAAAAA";
+
+/// Encrypted chunk as sent by the web app, hex encoded.
+fn chunk(chunk_nr: u32) -> String {
+    format!("{:02x}", chunk_nr).repeat(1000)
+}
+
+fn add_chunk(request_id: &str, chunk_nr: u32, chunk: &str) -> Value {
+    json!({
+        "request_id": request_id,
+        "chunk_nr": chunk_nr,
+        "is_last_chunk": chunk_nr == AMOUNT_OF_CHUNKS,
+        "chunk": chunk,
+        "iv": IV,
+    })
+}
+
+/// A host sharing a file and a guest that joined with the access code, both registered.
+async fn setup(harness: &Harness) -> (Session, Client, Client) {
+    let session = harness.create_session().await;
+    harness
+        .add_files(&session, json!([{ "name": FILENAME, "size": 6000 }]))
+        .await;
+
+    let response = harness.join(&session.name, &session.code).await;
+    assert_eq!(response.status, 200, "{}", response.body);
+    let guest_jwt = response.body["response"]["jwt"].as_str().unwrap();
+
+    let host = harness.connect(&session.id, &session.jwt).await;
+    let guest = harness.connect(&session.id, guest_jwt).await;
+
+    (session, host, guest)
+}
+
+/// Requests the file and goes through the handshake, returns the request id.
+async fn start_transfer(host: &mut Client, guest: &mut Client) -> String {
+    guest
+        .send(
+            "request-file",
+            json!({ "public_key": "guest-key", "filename": FILENAME }),
+        )
+        .await;
+    guest.expect_ok("request-file").await;
+
+    let message = host.recv("acknowledge-file-request").await;
+    assert_eq!(message["data"]["filename"], FILENAME);
+    let request_id = message["request_id"].as_str().unwrap().to_string();
+
+    host.send(
+        "acknowledge-file-request",
+        json!({
+            "request_id": request_id,
+            "public_key": "host-key",
+            "amount_of_chunks": AMOUNT_OF_CHUNKS,
+            "filename": FILENAME,
+        }),
+    )
+    .await;
+    host.expect_ok("acknowledge-file-request").await;
+
+    let message = guest.recv("prepare-for-file-transfer").await;
+    assert_eq!(message["request_id"], request_id.as_str());
+    assert_eq!(message["data"]["public_key"], "host-key");
+    assert_eq!(message["data"]["amount_of_chunks"], AMOUNT_OF_CHUNKS);
+
+    guest
+        .send(
+            "ready-for-file-transfer",
+            json!({ "request_id": request_id }),
+        )
+        .await;
+
+    request_id
+}
+
+/// Sends and acknowledges every chunk, one at a time.
+async fn transfer_chunks(host: &mut Client, guest: &mut Client, request_id: &str) {
+    for chunk_nr in 1..=AMOUNT_OF_CHUNKS {
+        let message = host.recv("send-next-chunk").await;
+        assert_eq!(message["request_id"], request_id);
+        assert_eq!(message["data"]["chunk_nr"], chunk_nr);
+
+        host.send(
+            "add-chunk",
+            add_chunk(request_id, chunk_nr, &chunk(chunk_nr)),
+        )
+        .await;
+        host.expect_ok("add-chunk").await;
+
+        let message = guest.recv("add-chunk").await;
+        assert_eq!(message["request_id"], request_id);
+        assert_eq!(message["data"]["chunk_nr"], chunk_nr);
+        assert_eq!(message["data"]["chunk"], chunk(chunk_nr));
+        assert_eq!(
+            message["data"]["is_last_chunk"],
+            chunk_nr == AMOUNT_OF_CHUNKS
+        );
+
+        guest
+            .send(
+                "received-chunk",
+                json!({ "request_id": request_id, "chunk_nr": chunk_nr }),
+            )
+            .await;
+        guest.expect_ok("received-chunk").await;
+    }
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn file_is_transferred_and_keys_are_removed() {
+    let harness = Harness::start().await;
+    let (session, mut host, mut guest) = setup(&harness).await;
+    let keys_of_session = harness.keys();
+
+    let request_id = start_transfer(&mut host, &mut guest).await;
+
+    transfer_chunks(&mut host, &mut guest, &request_id).await;
+
+    loop {
+        let message = host.recv("transfer-progress").await;
+        if message["data"]["chunks_done"] == AMOUNT_OF_CHUNKS {
+            break;
+        }
+    }
+
+    // the request is cleaned up once the last chunk was acknowledged
+    let mut attempts = 0;
+    while harness.keys() != keys_of_session {
+        attempts += 1;
+        assert!(attempts < 50, "keys left: {:?}", harness.keys());
+        tokio::time::sleep(Duration::from_millis(20)).await;
+    }
+
+    let response = harness
+        .request(
+            "DELETE",
+            &format!("/session/{}", session.id),
+            Some(&bearer(&session.jwt)),
+            None,
+        )
+        .await;
+    assert_eq!(response.status, 200, "{}", response.body);
+    assert!(harness.keys().is_empty(), "keys left: {:?}", harness.keys());
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn paused_transfer_is_resumed_and_keys_are_removed() {
+    let harness = Harness::start().await;
+    let (session, mut host, mut guest) = setup(&harness).await;
+    let keys_of_session = harness.keys();
+
+    let request_id = start_transfer(&mut host, &mut guest).await;
+    host.recv("send-next-chunk").await;
+
+    // the host disconnects before sending the first chunk
+    drop(host);
+    let message = guest.recv("transfer-paused").await;
+    assert_eq!(message["request_id"], request_id.as_str());
+
+    let mut host = harness.connect(&session.id, &session.jwt).await;
+    host.send("resume-transfer", json!({ "request_id": request_id }))
+        .await;
+    host.expect_ok("resume-transfer").await;
+
+    let message = host.recv("transfer-resumed").await;
+    assert_eq!(message["data"]["chunk_nr"], 0);
+    let message = guest.recv("transfer-resumed").await;
+    assert_eq!(message["data"]["chunk_nr"], 0);
+
+    transfer_chunks(&mut host, &mut guest, &request_id).await;
+
+    // the state of the request does not outlive the transfer once it was resumed
+    let mut attempts = 0;
+    while harness.keys() != keys_of_session {
+        attempts += 1;
+        assert!(attempts < 50, "keys left: {:?}", harness.keys());
+        tokio::time::sleep(Duration::from_millis(20)).await;
+    }
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn chunk_too_big_is_rejected() {
+    let harness = Harness::start().await;
+    let (_session, mut host, mut guest) = setup(&harness).await;
+    let request_id = start_transfer(&mut host, &mut guest).await;
+
+    host.recv("send-next-chunk").await;
+
+    let too_big = "00".repeat(35_001);
+    host.send("add-chunk", add_chunk(&request_id, 1, &too_big))
+        .await;
+    let error = host.expect_error("add-chunk").await;
+    assert_eq!(error["field"], "chunk");
+    assert_eq!(error["message"], "Chunk too big.");
+    assert!(
+        !guest
+            .receives("add-chunk", Duration::from_millis(200))
+            .await
+    );
+
+    // the transfer goes on with a valid chunk
+    host.send("add-chunk", add_chunk(&request_id, 1, &chunk(1)))
+        .await;
+    host.expect_ok("add-chunk").await;
+    let message = guest.recv("add-chunk").await;
+    assert_eq!(message["data"]["chunk"], chunk(1));
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn chunk_with_wrong_number_is_rejected() {
+    let harness = Harness::start().await;
+    let (_session, mut host, mut guest) = setup(&harness).await;
+    let request_id = start_transfer(&mut host, &mut guest).await;
+
+    host.recv("send-next-chunk").await;
+
+    // chunks that were not requested yet
+    for chunk_nr in [0, 2, AMOUNT_OF_CHUNKS + 1] {
+        host.send("add-chunk", add_chunk(&request_id, chunk_nr, &chunk(2)))
+            .await;
+        let error = host.expect_error("add-chunk").await;
+        assert_eq!(error["field"], "chunk_nr");
+        assert_eq!(error["message"], "Wrong chunk number.");
+    }
+
+    assert!(
+        !guest
+            .receives("add-chunk", Duration::from_millis(200))
+            .await
+    );
+
+    host.send("add-chunk", add_chunk(&request_id, 1, &chunk(1)))
+        .await;
+    host.expect_ok("add-chunk").await;
+
+    // adding the same chunk twice
+    host.send("add-chunk", add_chunk(&request_id, 1, &chunk(1)))
+        .await;
+    let error = host.expect_error("add-chunk").await;
+    assert_eq!(error["code"], "conflict");
+    assert_eq!(error["message"], "Chunk already added.");
+
+    let message = guest.recv("add-chunk").await;
+    assert_eq!(message["data"]["chunk_nr"], 1);
+    assert!(
+        !guest
+            .receives("add-chunk", Duration::from_millis(200))
+            .await
+    );
+
+    // a chunk the receiver already acknowledged
+    guest
+        .send(
+            "received-chunk",
+            json!({ "request_id": request_id, "chunk_nr": 1 }),
+        )
+        .await;
+    guest.expect_ok("received-chunk").await;
+    host.recv("send-next-chunk").await;
+
+    host.send("add-chunk", add_chunk(&request_id, 1, &chunk(1)))
+        .await;
+    let error = host.expect_error("add-chunk").await;
+    assert_eq!(error["message"], "Wrong chunk number.");
+}
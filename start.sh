@@ -31,6 +31,12 @@ cat <<EOF > "$ENV_FILE_PATH"
 DATABASE_PASSWORD=$PASSWORD
 JWT_KEY=$JWT_KEY
 RUST_LOG=error,api=info
+MAX_CHUNK_WINDOW_SIZE=16
+TRANSFER_GRACE_PERIOD=600
+BROADCAST_JOIN_WINDOW=5
+MAX_CONCURRENT_TRANSFERS=3
+CLIENT_IP_SOURCE=x-forwarded-for
+TRUSTED_PROXIES=10.0.0.0/8,172.16.0.0/12,192.168.0.0/16
 EOF
 chmod 666 "$ENV_FILE_PATH"
 
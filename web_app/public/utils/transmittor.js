@@ -1,7 +1,9 @@
 import { nextTick } from "vue";
-import { generateKeyPair, deriveSharedSecret, convertKeyToBase64, importKeyFromBase64, exportPrivateKeyToBase64, importPrivateKeyFromBase64, exportSharedSecretToBase64, importSharedSecretFromBase64, generateIv, ivToBase64, base64ToIv, arrayBufferToHex, hexToArrayBuffer, encryptData, decryptData, downloadDataUrl, getFile, storeLargeString, getLargeString } from '~/public/utils/utils';
+import { generateKeyPair, deriveSharedSecret, convertKeyToBase64, importKeyFromBase64, exportPrivateKeyToBase64, importPrivateKeyFromBase64, exportSharedSecretToBase64, importSharedSecretFromBase64, generateIv, ivToBase64, base64ToIv, arrayBufferToHex, hexToArrayBuffer, encryptData, decryptData, downloadDataUrl, getFile, storeLargeString, getLargeString, deleteLargeString, digestChunk, hashDigests, encryptDigest, decryptDigest, encryptContentHash, decryptContentHash, isEqualDigest } from '~/public/utils/utils';
 
 const chunkSize = 32768;
+// every chunk is verified against its digest
+const capabilities = ['chunk-digests'];
 
 export function trnsRegister(socket) {
     console.log('trnsRegister');
@@ -10,7 +12,7 @@ export function trnsRegister(socket) {
     socket.send(JSON.stringify({
         jwt: jwtCookie.value,
         command: 'register',
-        data: JSON.stringify({})
+        data: JSON.stringify({ capabilities: capabilities })
     }));
 }
 
@@ -39,7 +41,8 @@ export async function trnsRequestFile(socket, filename) {
     }));
 }
 
-export async function trnsWsHandleMessage(socket, message) {
+// onTransferFailed(requestId, reason) is called when a received file fails verification
+export async function trnsWsHandleMessage(socket, message, onTransferFailed) {
     console.log('trnsWsHandleMessage');
     const requestId = message.request_id;
     const data = message.data;
@@ -55,7 +58,12 @@ export async function trnsWsHandleMessage(socket, message) {
             await trnsHandleSendNextChunk(socket, requestId, data);
             break;
         case 'add-chunk':
-            await trnsHandleAddChunk(socket, requestId, data);
+            await trnsHandleAddChunk(socket, requestId, data, onTransferFailed);
+            break;
+        case 'ok':
+            break;
+        case 'error':
+            console.error(`Command ${data.command} failed (${data.code}):`, data.message);
             break;
         default:
             console.error('Unknown command:', message.command);
@@ -91,7 +99,13 @@ async function trnsHandleAcknowledgeFileRequest(socket, requestId, data) {
     const file = await getFile(filename);
     const amountOfChunks = Math.ceil(file.length / chunkSize);
 
-    await trnsAcknwoledgeFileRequest(socket, requestId, base64PublicKey, amountOfChunks, filename);
+    const digests = [];
+    for (let chunkNr = 1; chunkNr <= amountOfChunks; chunkNr++) {
+        digests.push(await digestChunk(chunkNr, file.slice((chunkNr - 1) * chunkSize, chunkNr * chunkSize)));
+    }
+    const contentHash = await encryptContentHash(secretObj, await hashDigests(digests));
+
+    await trnsAcknwoledgeFileRequest(socket, requestId, base64PublicKey, amountOfChunks, filename, contentHash);
 }
 
 async function trnsHandlePrepareForFileTransfer(socket, requestId, data) {
@@ -125,6 +139,11 @@ async function trnsHandlePrepareForFileTransfer(socket, requestId, data) {
     const chunkAmountCookie = useCookie(`${requestId}-chunkAmount`);
     chunkAmountCookie.value = amountOfChunks;
 
+    if (data.content_hash) {
+        const contentHashCookie = useCookie(`${requestId}-contentHash`);
+        contentHashCookie.value = JSON.stringify(data.content_hash);
+    }
+
     await trnsReadyForFileTransfer(socket, requestId);
 }
 
@@ -154,10 +173,12 @@ async function trnsHandleSendNextChunk(socket, requestId, data) {
     const encryptedChunk = await encryptData(secret, iv, chunk);
     const hexChunk = arrayBufferToHex(encryptedChunk);
 
-    await trnsAddChunk(socket, requestId, isLastChunk, chunkNr, hexChunk, base64Iv);
+    const digest = await encryptDigest(secret, await digestChunk(chunkNr, chunk));
+
+    await trnsAddChunk(socket, requestId, isLastChunk, chunkNr, hexChunk, base64Iv, digest);
 }
 
-async function trnsHandleAddChunk(socket, requestId, data) {
+async function trnsHandleAddChunk(socket, requestId, data, onTransferFailed) {
     console.log('trnsHandleAddChunk');
     const isLastChunk = data.is_last_chunk;
     const chunkNr = data.chunk_nr;
@@ -169,12 +190,34 @@ async function trnsHandleAddChunk(socket, requestId, data) {
 
     const chunk = await decryptData(secret, iv, encryptedChunk);
 
+    // a sender that hashed the whole file sends the digest of every chunk,
+    // only chunks of a sender without a content hash go without one
+    const digest = await digestChunk(chunkNr, chunk);
+    const contentHashCookie = useCookie(`${requestId}-contentHash`);
+    const isVerified = data.digest
+        ? await decryptDigest(secret, data.digest)
+            .then((expected) => isEqualDigest(digest, expected))
+            .catch(() => false)
+        : !contentHashCookie.value;
+    if (!isVerified) {
+        await trnsFailTransfer(socket, requestId, `Chunk ${chunkNr} failed verification`, onTransferFailed);
+        return;
+    }
+
+    const hexDigests = await getLargeString(`${requestId}-digests`).catch(() => '');
+    await storeLargeString(`${requestId}-digests`, `${hexDigests}${arrayBufferToHex(digest)}`);
+
     getLargeString(`${requestId}-file`)
         .then(async (file) => {
             file = `${file}${chunk}`;
             await storeLargeString(`${requestId}-file`, file);
 
             if (isLastChunk) {
+                if (!(await trnsVerifyContentHash(requestId, secret))) {
+                    await trnsFailTransfer(socket, requestId, 'File failed verification', onTransferFailed);
+                    return;
+                }
+
                 const fileParts = file.split(',');
                 const decodedFile = atob(fileParts[1]);
 
@@ -190,9 +233,40 @@ async function trnsHandleAddChunk(socket, requestId, data) {
     await trnsReceivedChunk(socket, requestId, chunkNr);
 }
 
+// cancels the transfer of a corrupted file and drops what was received of it
+async function trnsFailTransfer(socket, requestId, reason, onTransferFailed) {
+    console.error(`${reason} (${requestId})`);
+
+    trnsCancelTransfer(socket, requestId, reason);
 
+    await deleteLargeString(`${requestId}-file`);
+    await deleteLargeString(`${requestId}-digests`);
 
-async function trnsAcknwoledgeFileRequest(socket, requestId, publicKey, amountOfChunks, filename) {
+    if (onTransferFailed) {
+        onTransferFailed(requestId, reason);
+    }
+}
+
+async function trnsVerifyContentHash(requestId, secret) {
+    const contentHashCookie = useCookie(`${requestId}-contentHash`);
+    if (!contentHashCookie.value) {
+        return true;
+    }
+
+    // a content hash that cannot be read fails the verification, it is not skipped
+    try {
+        const contentHash = typeof contentHashCookie.value === 'string' ? JSON.parse(contentHashCookie.value) : contentHashCookie.value;
+        const hexDigests = await getLargeString(`${requestId}-digests`);
+        const digests = (hexDigests.match(/.{64}/g) || []).map((hexDigest) => hexToArrayBuffer(hexDigest));
+
+        return isEqualDigest(await hashDigests(digests), await decryptContentHash(secret, contentHash));
+    } catch (e) {
+        console.error(e);
+        return false;
+    }
+}
+
+async function trnsAcknwoledgeFileRequest(socket, requestId, publicKey, amountOfChunks, filename, contentHash) {
     console.log('trnsAcknwoledgeFileRequest');
     const jwtCookie = useCookie('jwt');
 
@@ -204,6 +278,7 @@ async function trnsAcknwoledgeFileRequest(socket, requestId, publicKey, amountOf
             public_key: publicKey,
             amount_of_chunks: amountOfChunks,
             filename: filename,
+            content_hash: contentHash,
         })
     }));
 }
@@ -221,7 +296,7 @@ async function trnsReadyForFileTransfer(socket, requestId) {
     }));
 }
 
-async function trnsAddChunk(socket, requestId, isLastChunk, chunkNr, hexChunk, iv) {
+async function trnsAddChunk(socket, requestId, isLastChunk, chunkNr, hexChunk, iv, digest) {
     console.log('trnsAddChunk');
     const jwtCookie = useCookie('jwt');
 
@@ -233,7 +308,8 @@ async function trnsAddChunk(socket, requestId, isLastChunk, chunkNr, hexChunk, i
             is_last_chunk: isLastChunk,
             chunk_nr: chunkNr,
             chunk: hexChunk,
-            iv: iv
+            iv: iv,
+            digest: digest
         })
     }));
 }
@@ -250,4 +326,18 @@ async function trnsReceivedChunk(socket, requestId, chunkNr) {
             chunk_nr: chunkNr
         })
     }));
-}
\ No newline at end of file
+}
+
+function trnsCancelTransfer(socket, requestId, reason) {
+    console.log('trnsCancelTransfer');
+    const jwtCookie = useCookie('jwt');
+
+    socket.send(JSON.stringify({
+        jwt: jwtCookie.value,
+        command: 'cancel-transfer',
+        data: JSON.stringify({
+            request_id: requestId,
+            reason: reason
+        })
+    }));
+}
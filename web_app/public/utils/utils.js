@@ -206,6 +206,57 @@ export async function decryptData(sharedSecret, iv, encryptedData) {
     return new TextDecoder().decode(decryptedData);
 }
 
+export async function digestChunk(chunkNr, chunk) {
+    const encodedChunk = new TextEncoder().encode(chunk);
+    const data = new Uint8Array(4 + encodedChunk.length);
+    new DataView(data.buffer).setUint32(0, chunkNr);
+    data.set(encodedChunk, 4);
+
+    return await crypto.subtle.digest('SHA-256', data);
+}
+
+export async function hashDigests(digests) {
+    const data = new Uint8Array(digests.length * 32);
+    digests.forEach((digest, i) => data.set(new Uint8Array(digest), i * 32));
+
+    return await crypto.subtle.digest('SHA-256', data);
+}
+
+export async function encryptDigest(sharedSecret, digest) {
+    const iv = await generateIv();
+    const encryptedDigest = await crypto.subtle.encrypt({ name: "AES-GCM", iv: iv }, sharedSecret, digest);
+
+    const data = new Uint8Array(iv.length + encryptedDigest.byteLength);
+    data.set(iv);
+    data.set(new Uint8Array(encryptedDigest), iv.length);
+
+    return arrayBufferToBase64(data.buffer);
+}
+
+export async function decryptDigest(sharedSecret, base64Digest) {
+    const data = new Uint8Array(base64ToArrayBuffer(base64Digest));
+    const iv = data.slice(0, 12);
+
+    return await crypto.subtle.decrypt({ name: "AES-GCM", iv: iv }, sharedSecret, data.slice(12));
+}
+
+export async function encryptContentHash(sharedSecret, hash) {
+    const iv = await generateIv();
+    const encryptedHash = await crypto.subtle.encrypt({ name: "AES-GCM", iv: iv }, sharedSecret, hash);
+
+    return { hash: arrayBufferToBase64(encryptedHash), iv: ivToBase64(iv) };
+}
+
+export async function decryptContentHash(sharedSecret, contentHash) {
+    const iv = base64ToIv(contentHash.iv);
+
+    return await crypto.subtle.decrypt({ name: "AES-GCM", iv: iv }, sharedSecret, base64ToArrayBuffer(contentHash.hash));
+}
+
+export function isEqualDigest(a, b) {
+    return arrayBufferToHex(a) === arrayBufferToHex(b);
+}
+
 export function downloadDataUrl(dataUrl, filename) {
     // Split the data URL to get the MIME type and the base64 data
     const [metadata, base64Data] = dataUrl.split(',');
@@ -344,6 +395,18 @@ export async function getLargeString(id) {
     });
 }
 
+export async function deleteLargeString(id) {
+    const db = await openDatabase();
+    const transaction = db.transaction(['files'], 'readwrite');
+    const store = transaction.objectStore('files');
+
+    const request = store.delete(id);
+
+    request.onerror = (event) => {
+        console.error(`Failed to delete string ${id}: `, event.target.error);
+    };
+}
+
 async function clearIndexedDB() {
     const databases = await indexedDB.databases();
     databases.forEach((db) => {